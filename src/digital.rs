@@ -0,0 +1,272 @@
+// External crates
+use ndarray::{Array1, Array2, ArrayView1};
+
+// Local modules
+use crate::header::Channel;
+use crate::import_hash::RhsError;
+
+/// Rising and falling edges of one digital channel. Samples are counted from
+/// the first sample of the data, and times are in seconds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DigitalEdges {
+    /// Native name of the channel, e.g. `DIGITAL-IN-01`.
+    pub channel: String,
+    pub rising_samples: Vec<u64>,
+    pub rising_times: Vec<f64>,
+    pub falling_samples: Vec<u64>,
+    pub falling_times: Vec<f64>,
+}
+
+/// Options controlling how `decode_strobed_words` reads codes from a group of
+/// digital channels.
+#[derive(Debug, Clone, Default)]
+pub struct StrobedWordOptions {
+    /// Native order (bit number) of each channel holding a bit of the code,
+    /// least significant bit first.
+    pub bits: Vec<i32>,
+    /// Native order of the channel whose rising edge marks each code. Without
+    /// a strobe, a code is read whenever the bits change to a non-zero value.
+    pub strobe: Option<i32>,
+    /// Time to wait after the strobe edge or change before reading the code,
+    /// in ms, so that bits switching slightly late are not missed.
+    pub settle_ms: f32,
+}
+
+/// Codes decoded by `decode_strobed_words`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StrobedWords {
+    /// Sample of each strobe edge or change, counted from the first sample of
+    /// the data.
+    pub samples: Vec<u64>,
+    /// Time of each sample in seconds.
+    pub times: Vec<f64>,
+    pub codes: Vec<u32>,
+}
+
+/// The state of the channel stored at bit `native_order` of `words`, such as
+/// the `board_dig_in_raw` word, at every sample.
+pub fn digital_channel(words: ArrayView1<u16>, native_order: i32) -> std::result::Result<Array1<bool>, RhsError> {
+    let mask = bit_mask(native_order)?;
+    Ok(words.mapv(|word| word & mask != 0))
+}
+
+/// The states of `channels`, the digital input or output channels whose
+/// states are packed into `words`, one row per channel. This takes one byte
+/// per channel and sample, so on long recordings it is best limited to the
+/// channels needed.
+pub fn digital_channels(words: ArrayView1<u16>, channels: &[Channel]) -> std::result::Result<Array2<bool>, RhsError> {
+    let mut states = Array2::from_elem((channels.len(), words.len()), false);
+    for (mut row, channel) in states.outer_iter_mut().zip(channels) {
+        row.assign(&digital_channel(words, channel.native_order)?);
+    }
+    Ok(states)
+}
+
+/// Find the rising and falling edges of each of `channels`, the digital
+/// input or output channels whose states are packed into `words`, given the
+/// timestamp of each sample in seconds.
+pub fn find_digital_edges(words: ArrayView1<u16>, times: ArrayView1<f64>, channels: &[Channel]) -> std::result::Result<Vec<DigitalEdges>, RhsError> {
+    channels.iter()
+        .map(|channel| find_bit_edges(words, times, &channel.native_channel_name, channel.native_order))
+        .collect()
+}
+
+/// Find the rising and falling edges of the channel named `channel` stored at
+/// bit `native_order` of `words`. The first sample cannot be an edge, since
+/// the state before it is unknown.
+pub fn find_bit_edges(words: ArrayView1<u16>, times: ArrayView1<f64>, channel: &str, native_order: i32) -> std::result::Result<DigitalEdges, RhsError> {
+    check_times(words, times)?;
+    let mask = bit_mask(native_order)?;
+    let mut edges = DigitalEdges {
+        channel: channel.to_string(),
+        rising_samples: Vec::new(),
+        rising_times: Vec::new(),
+        falling_samples: Vec::new(),
+        falling_times: Vec::new(),
+    };
+    for i in 1..words.len() {
+        match (words[i - 1] & mask != 0, words[i] & mask != 0) {
+            (false, true) => {
+                edges.rising_samples.push(i as u64);
+                edges.rising_times.push(times[i]);
+            },
+            (true, false) => {
+                edges.falling_samples.push(i as u64);
+                edges.falling_times.push(times[i]);
+            },
+            _ => {},
+        }
+    }
+    Ok(edges)
+}
+
+/// Decode the codes written to a group of digital channels packed into
+/// `words`, sampled at `sample_rate` Hz, such as trial numbers sent by a
+/// behaviour controller on DIGITAL-IN-01 to 08.
+pub fn decode_strobed_words(words: ArrayView1<u16>, times: ArrayView1<f64>, sample_rate: f32, options: &StrobedWordOptions) -> std::result::Result<StrobedWords, RhsError> {
+    check_times(words, times)?;
+    if options.bits.is_empty() {
+        return Err(RhsError::InvalidArgumentError("A strobed word needs at least one bit".to_string()));
+    }
+    let bit_masks = options.bits.iter().map(|&bit| bit_mask(bit)).collect::<std::result::Result<Vec<u16>, RhsError>>()?;
+    if !options.settle_ms.is_finite() || options.settle_ms < 0.0 {
+        return Err(RhsError::InvalidArgumentError(format!("Settle time must be zero or more milliseconds, not {}", options.settle_ms)));
+    }
+    let settle_samples = (options.settle_ms * sample_rate / 1000.0).round() as usize;
+
+    let num_samples = words.len();
+    let code_of = |word: u16| {
+        bit_masks.iter().enumerate()
+            .filter(|(_, &mask)| word & mask != 0)
+            .fold(0u32, |code, (bit, _)| code | 1 << bit)
+    };
+    let settled_code = |i: usize| code_of(words[(i + settle_samples).min(num_samples - 1)]);
+
+    let mut decoded = StrobedWords::default();
+    let mut push = |i: usize, code: u32| {
+        decoded.samples.push(i as u64);
+        decoded.times.push(times[i]);
+        decoded.codes.push(code);
+    };
+    match options.strobe {
+        Some(strobe) => {
+            let strobe_mask = bit_mask(strobe)?;
+            for i in 1..num_samples {
+                if words[i - 1] & strobe_mask == 0 && words[i] & strobe_mask != 0 {
+                    push(i, settled_code(i));
+                }
+            }
+        },
+        None if num_samples > 0 => {
+            // Read each change once the bits have settled, and skip changes
+            // back to zero between codes.
+            let mut previous = code_of(words[0]);
+            let mut i = 1;
+            while i < num_samples {
+                if code_of(words[i]) != previous {
+                    let code = settled_code(i);
+                    if code != 0 && code != previous {
+                        push(i, code);
+                    }
+                    previous = code;
+                    i += settle_samples;
+                }
+                i += 1;
+            }
+        },
+        None => {},
+    }
+    Ok(decoded)
+}
+
+fn bit_mask(native_order: i32) -> std::result::Result<u16, RhsError> {
+    if !(0..16).contains(&native_order) {
+        return Err(RhsError::InvalidArgumentError(format!("Digital channel bit {} is outside the 16-bit word", native_order)));
+    }
+    Ok(1 << native_order)
+}
+
+fn check_times(words: ArrayView1<u16>, times: ArrayView1<f64>) -> std::result::Result<(), RhsError> {
+    if words.len() != times.len() {
+        return Err(RhsError::InvalidArgumentError(format!("{} digital words for {} timestamps", words.len(), times.len())));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 1 kHz, so that each ms is one sample.
+    const SAMPLE_RATE: f32 = 1000.0;
+
+    fn times(num_samples: usize) -> Array1<f64> {
+        Array1::from_iter((0..num_samples).map(|i| i as f64 / SAMPLE_RATE as f64))
+    }
+
+    // Words holding `codes` on bits 0 to 2, each from its sample on.
+    fn words(num_samples: usize, codes: &[(usize, u16)]) -> Array1<u16> {
+        let mut words = Array1::zeros(num_samples);
+        for &(sample, code) in codes {
+            words.slice_mut(ndarray::s![sample..]).fill(code);
+        }
+        words
+    }
+
+    fn decode(words: &Array1<u16>, strobe: Option<i32>, settle_ms: f32) -> StrobedWords {
+        let options = StrobedWordOptions { bits: vec![0, 1, 2], strobe, settle_ms };
+        decode_strobed_words(words.view(), times(words.len()).view(), SAMPLE_RATE, &options).unwrap()
+    }
+
+    #[test]
+    fn bit_edges() {
+        // Bit 3 is high from the first sample, so that is not an edge.
+        let words = Array1::from(vec![0b1000, 0b1010, 0b1010, 0b0000, 0b0010, 0b1000]);
+        let edges = find_bit_edges(words.view(), times(6).view(), "DIGITAL-IN-02", 1).unwrap();
+        assert_eq!(edges.channel, "DIGITAL-IN-02");
+        assert_eq!(edges.rising_samples, [1, 4]);
+        assert_eq!(edges.rising_times, [0.001, 0.004]);
+        assert_eq!(edges.falling_samples, [3, 5]);
+
+        let edges = find_bit_edges(words.view(), times(6).view(), "DIGITAL-IN-04", 3).unwrap();
+        assert_eq!(edges.rising_samples, [5]);
+        assert_eq!(edges.falling_samples, [3]);
+
+        assert_eq!(digital_channel(words.view(), 3).unwrap().to_vec(), [true, true, true, false, false, true]);
+    }
+
+    #[test]
+    fn strobed_codes() {
+        // The strobe on bit 7 rises with each code, and the code changes
+        // again while the strobe is high.
+        let strobe = 1 << 7;
+        let words = words(20, &[(2, 5 | strobe), (4, 5), (8, 6 | strobe), (9, 6), (12, 1), (15, 6 | strobe), (16, 0)]);
+        let decoded = decode(&words, Some(7), 0.0);
+        assert_eq!(decoded.samples, [2, 8, 15]);
+        assert_eq!(decoded.codes, [5, 6, 6]);
+        assert_eq!(decoded.times, [0.002, 0.008, 0.015]);
+    }
+
+    #[test]
+    fn strobed_codes_read_after_settling() {
+        // Bit 2 only switches one sample after the strobe.
+        let strobe = 1 << 7;
+        let words = words(10, &[(2, 1 | strobe), (3, 5 | strobe), (6, 0)]);
+        assert_eq!(decode(&words, Some(7), 0.0).codes, [1]);
+        let decoded = decode(&words, Some(7), 1.0);
+        assert_eq!(decoded.samples, [2]);
+        assert_eq!(decoded.codes, [5]);
+    }
+
+    #[test]
+    fn unstrobed_codes_repeat_after_return_to_zero() {
+        // 5, back to zero, 5 again, then straight to 3 and back to zero.
+        let words = words(20, &[(2, 5), (5, 0), (8, 5), (11, 3), (14, 0)]);
+        let decoded = decode(&words, None, 0.0);
+        assert_eq!(decoded.samples, [2, 8, 11]);
+        assert_eq!(decoded.codes, [5, 5, 3]);
+    }
+
+    #[test]
+    fn unstrobed_codes_read_after_settling() {
+        // Code 7 is written one bit per sample, so without settling each
+        // partial code is read as a code of its own.
+        let words = words(20, &[(2, 1), (3, 3), (4, 7), (10, 0), (13, 7)]);
+        assert_eq!(decode(&words, None, 0.0).codes, [1, 3, 7, 7]);
+
+        let decoded = decode(&words, None, 2.0);
+        assert_eq!(decoded.samples, [2, 13]);
+        assert_eq!(decoded.codes, [7, 7]);
+    }
+
+    #[test]
+    fn invalid_arguments() {
+        let words = Array1::zeros(4);
+        assert!(find_bit_edges(words.view(), times(4).view(), "DIGITAL-IN-17", 16).is_err());
+        assert!(find_bit_edges(words.view(), times(3).view(), "DIGITAL-IN-01", 0).is_err());
+        let options = StrobedWordOptions { bits: Vec::new(), ..Default::default() };
+        assert!(decode_strobed_words(words.view(), times(4).view(), SAMPLE_RATE, &options).is_err());
+        let options = StrobedWordOptions { bits: vec![0], settle_ms: -1.0, ..Default::default() };
+        assert!(decode_strobed_words(words.view(), times(4).view(), SAMPLE_RATE, &options).is_err());
+    }
+}
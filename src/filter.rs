@@ -0,0 +1,439 @@
+// Standard library imports
+use std::collections::HashMap;
+use std::f64::consts::PI;
+
+// External crates
+use ndarray::Array2;
+use rayon::prelude::*;
+
+// Local modules
+use crate::import_hash::RhsError;
+
+/// One second-order IIR section, normalized so that `a0` is 1:
+/// `y[i] = b0 x[i] + b1 x[i-1] + b2 x[i-2] - a1 y[i-1] - a2 y[i-2]`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Biquad {
+    pub b0: f64,
+    pub b1: f64,
+    pub b2: f64,
+    pub a1: f64,
+    pub a2: f64,
+}
+
+impl Biquad {
+    /// Filter `signal_in`, starting as if the signal had held its first value
+    /// forever, so that a constant offset does not set off a transient.
+    pub fn filter(&self, signal_in: &[f64]) -> Vec<f64> {
+        let Some(&first) = signal_in.first() else {
+            return Vec::new();
+        };
+        let mut signal_out = Vec::with_capacity(signal_in.len());
+        let (mut x1, mut x2) = (first, first);
+        let (mut y1, mut y2) = (first * self.dc_gain(), first * self.dc_gain());
+        for &x in signal_in {
+            let y = self.b2 * x2 + self.b1 * x1 + self.b0 * x - self.a2 * y2 - self.a1 * y1;
+            (x2, x1, y2, y1) = (x1, x, y1, y);
+            signal_out.push(y);
+        }
+        signal_out
+    }
+
+    // Output at sample i, given the input and the output before it.
+    fn output(&self, i: usize, signal_in: &[f64], signal_out: &[f64]) -> f64 {
+        self.b2 * signal_in[i - 2] + self.b1 * signal_in[i - 1] + self.b0 * signal_in[i] - self.a2 * signal_out[i - 2] - self.a1 * signal_out[i - 1]
+    }
+
+    fn dc_gain(&self) -> f64 {
+        (self.b0 + self.b1 + self.b2) / (1.0 + self.a1 + self.a2)
+    }
+
+    // Radius of the section's largest pole, the roots of z^2 + a1 z + a2.
+    fn pole_radius(&self) -> f64 {
+        let discriminant = self.a1 * self.a1 - 4.0 * self.a2;
+        if discriminant < 0.0 {
+            self.a2.sqrt()
+        } else {
+            (self.a1.abs() + discriminant.sqrt()) / 2.0
+        }
+    }
+}
+
+
+/// Filter `signal_in`, sampled at `f_sample` Hz, with a second-order IIR notch
+/// at `f_notch` Hz and `bandwidth` Hz wide, as Intan's own software does.
+pub fn notch_filter(signal_in: &[f64], f_sample: f32, f_notch: f32, bandwidth: f32) -> Vec<f64> {
+    let signal_length = signal_in.len();
+    let notch = notch_biquad(f_sample, f_notch, bandwidth);
+
+    // The filter starts from the first two samples, so shorter signals are
+    // returned as they are.
+    if signal_length < 3 {
+        return signal_in.to_vec();
+    }
+
+    let mut signal_out = vec![0.0; signal_length];
+
+    signal_out[0] = signal_in[0];
+    signal_out[1] = signal_in[1];
+
+    for i in 2..signal_length {
+        signal_out[i] = notch.output(i, signal_in, &signal_out);
+    }
+
+    signal_out
+}
+
+fn notch_biquad(f_sample: f32, f_notch: f32, bandwidth: f32) -> Biquad {
+    let t_step = 1.0 / f_sample;
+    let f_c = f_notch * t_step;
+    let iir_parameters = calculate_iir_parameters(bandwidth, t_step, f_c);
+    let a = iir_parameters["a"];
+    let a0 = iir_parameters["a0"];
+    Biquad {
+        b0: a * iir_parameters["b0"] / a0,
+        b1: a * iir_parameters["b1"] / a0,
+        b2: a * iir_parameters["b2"] / a0,
+        a1: iir_parameters["a1"] / a0,
+        a2: iir_parameters["a2"] / a0,
+    }
+}
+
+fn calculate_iir_parameters(bandwidth: f32, t_step: f32, f_c: f32) -> HashMap<String, f64> {
+    let f_c = f_c as f64;
+    let bandwidth = bandwidth as f64;
+    let t_step = t_step as f64; 
+    let mut parameters = HashMap::new();
+    let d = (-2.0 * PI * (bandwidth / 2.0) * t_step).exp();
+    let b = (1.0 + d * d) * (2.0 * PI * f_c).cos();
+    let a0 = 1.0;
+    let a1 = -b;
+    let a2 = d * d;
+    let a = (1.0 + d * d) / 2.0;
+    let b0 = 1.0;
+    let b1 = -2.0 * (2.0 * PI * f_c).cos();
+    let b2 = 1.0;
+
+    parameters.insert("d".to_string(), d);
+    parameters.insert("b".to_string(), b);
+    parameters.insert("a0".to_string(), a0);
+    parameters.insert("a1".to_string(), a1);
+    parameters.insert("a2".to_string(), a2);
+    parameters.insert("a".to_string(), a);
+    parameters.insert("b0".to_string(), b0);
+    parameters.insert("b1".to_string(), b1);
+    parameters.insert("b2".to_string(), b2);
+
+    parameters
+}
+
+/// Zero-phase version of `notch_filter`: the signal is filtered forward and
+/// then backward, so the notch is twice as deep and nothing around it is
+/// shifted in time. Spike waveforms keep their shape, at the cost of the
+/// filter no longer being causal.
+pub fn notch_filter_zero_phase(signal_in: &[f64], f_sample: f32, f_notch: f32, bandwidth: f32) -> Vec<f64> {
+    let pole_radius = notch_biquad(f_sample, f_notch, bandwidth).pole_radius();
+    filtfilt(signal_in, settle_samples(pole_radius), |signal| notch_filter(signal, f_sample, f_notch, bandwidth))
+}
+
+/// Run the causal `filter` over `signal_in` forward and then backward, as
+/// SciPy's `filtfilt` does, for zero phase shift.
+///
+/// Each end of the signal is first extended by `pad_len` samples reflected
+/// about its end sample (odd padding), so that the filter has settled by the
+/// time it reaches the real data. `pad_len` is clipped to one sample less
+/// than the signal.
+pub fn filtfilt(signal_in: &[f64], pad_len: usize, filter: impl Fn(&[f64]) -> Vec<f64>) -> Vec<f64> {
+    let signal_length = signal_in.len();
+    if signal_length < 2 {
+        return filter(signal_in);
+    }
+    let pad_len = pad_len.min(signal_length - 1);
+
+    let first = signal_in[0];
+    let last = signal_in[signal_length - 1];
+    let mut padded = Vec::with_capacity(signal_length + 2 * pad_len);
+    padded.extend((1..=pad_len).rev().map(|i| 2.0 * first - signal_in[i]));
+    padded.extend_from_slice(signal_in);
+    padded.extend((1..=pad_len).map(|i| 2.0 * last - signal_in[signal_length - 1 - i]));
+
+    let mut signal_out = filter(&padded);
+    signal_out.reverse();
+    let mut signal_out = filter(&signal_out);
+    signal_out.reverse();
+
+    signal_out.drain(..pad_len);
+    signal_out.truncate(signal_length);
+    signal_out
+}
+
+// Number of samples for the impulse response of a filter whose largest pole
+// has radius `pole_radius` to fall to 0.1% of its peak, used as the padding
+// for zero-phase filtering.
+fn settle_samples(pole_radius: f64) -> usize {
+    if pole_radius <= 0.0 {
+        return 0;
+    }
+    (0.001_f64.ln() / pole_radius.ln()).ceil() as usize
+}
+
+/// Pass band of a Butterworth filter, with cutoff frequencies in Hz.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilterBand {
+    HighPass(f32),
+    LowPass(f32),
+    /// Low and high cutoff.
+    BandPass(f32, f32),
+}
+
+/// A Butterworth filter, run as a cascade of second-order sections.
+///
+/// Band-pass filters are a high-pass and a low-pass filter of the given order
+/// in series, so 300-6000 Hz picks out the spike band and 1-300 Hz the local
+/// field potential.
+#[derive(Debug, Clone)]
+pub struct ButterworthFilter {
+    sections: Vec<Biquad>,
+}
+
+impl ButterworthFilter {
+    pub fn new(band: FilterBand, order: usize, f_sample: f32) -> std::result::Result<ButterworthFilter, RhsError> {
+        if order == 0 {
+            return Err(RhsError::InvalidArgumentError("Butterworth filters need an order of at least 1".to_string()));
+        }
+        let check_cutoff = |f_cutoff: f32| {
+            if f_cutoff.is_nan() || f_cutoff <= 0.0 || f_cutoff >= f_sample / 2.0 {
+                return Err(RhsError::InvalidArgumentError(format!("Cutoff frequency {} Hz must lie between 0 Hz and the Nyquist frequency, {} Hz", f_cutoff, f_sample / 2.0)));
+            }
+            Ok(())
+        };
+
+        let sections = match band {
+            FilterBand::HighPass(f_cutoff) => {
+                check_cutoff(f_cutoff)?;
+                butterworth_sections(true, f_cutoff, f_sample, order)
+            },
+            FilterBand::LowPass(f_cutoff) => {
+                check_cutoff(f_cutoff)?;
+                butterworth_sections(false, f_cutoff, f_sample, order)
+            },
+            FilterBand::BandPass(f_low, f_high) => {
+                check_cutoff(f_low)?;
+                check_cutoff(f_high)?;
+                if f_low >= f_high {
+                    return Err(RhsError::InvalidArgumentError(format!("Band-pass low cutoff ({} Hz) must be below the high cutoff ({} Hz)", f_low, f_high)));
+                }
+                let mut sections = butterworth_sections(true, f_low, f_sample, order);
+                sections.extend(butterworth_sections(false, f_high, f_sample, order));
+                sections
+            },
+        };
+
+        Ok(ButterworthFilter { sections })
+    }
+
+    /// The second-order sections, in the order they are applied.
+    pub fn sections(&self) -> &[Biquad] {
+        &self.sections
+    }
+
+    /// Filter `signal_in` causally, as a hardware filter would.
+    pub fn filter(&self, signal_in: &[f64]) -> Vec<f64> {
+        let mut signal_out = signal_in.to_vec();
+        for section in &self.sections {
+            signal_out = section.filter(&signal_out);
+        }
+        signal_out
+    }
+
+    /// Filter `signal_in` forward and then backward (see `filtfilt`), for zero
+    /// phase shift and twice the attenuation of `filter`.
+    pub fn filter_zero_phase(&self, signal_in: &[f64]) -> Vec<f64> {
+        let pole_radius = self.sections.iter().map(Biquad::pole_radius).fold(0.0, f64::max);
+        filtfilt(signal_in, settle_samples(pole_radius), |signal| self.filter(signal))
+    }
+}
+
+// Sections of a Butterworth high-pass or low-pass filter of `order`, from the
+// bilinear transform of the analog prototype with its cutoff prewarped
+// (Robert Bristow-Johnson's Audio EQ Cookbook). Odd orders end with a
+// first-order section.
+fn butterworth_sections(high_pass: bool, f_cutoff: f32, f_sample: f32, order: usize) -> Vec<Biquad> {
+    let w0 = 2.0 * PI * f_cutoff as f64 / f_sample as f64;
+    let (sin_w0, cos_w0) = w0.sin_cos();
+    let mut sections = Vec::new();
+
+    // Each pair of complex poles of the prototype lies at angle theta from
+    // the negative real axis and gives one section with Q = 1 / (2 cos theta).
+    for m in 0..order / 2 {
+        let theta = PI * (order - 1 - 2 * m) as f64 / (2 * order) as f64;
+        let q = 1.0 / (2.0 * theta.cos());
+        let alpha = sin_w0 / (2.0 * q);
+        let a0 = 1.0 + alpha;
+        let (b0, b1) = if high_pass { ((1.0 + cos_w0) / 2.0, -(1.0 + cos_w0)) } else { ((1.0 - cos_w0) / 2.0, 1.0 - cos_w0) };
+        sections.push(Biquad { b0: b0 / a0, b1: b1 / a0, b2: b0 / a0, a1: -2.0 * cos_w0 / a0, a2: (1.0 - alpha) / a0 });
+    }
+
+    if order % 2 == 1 {
+        let k = (w0 / 2.0).tan();
+        let (b0, b1) = if high_pass { (1.0 / (1.0 + k), -1.0 / (1.0 + k)) } else { (k / (1.0 + k), k / (1.0 + k)) };
+        sections.push(Biquad { b0, b1, b2: 0.0, a1: (k - 1.0) / (k + 1.0), a2: 0.0 });
+    }
+
+    sections
+}
+
+/// Run `filter` over every channel (row) of `data` in parallel, on the
+/// current rayon thread pool.
+pub fn filter_channels(data: &mut Array2<f32>, filter: impl Fn(&[f64]) -> Vec<f64> + Sync) {
+    let channels: Vec<_> = data.outer_iter_mut().collect();
+    channels.into_par_iter().for_each(|mut channel| {
+        let channel_data: Vec<f64> = channel.iter().map(|&x| x as f64).collect();
+        for (sample, filtered) in channel.iter_mut().zip(filter(&channel_data)) {
+            *sample = filtered as f32;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+
+    const F_SAMPLE: f32 = 30000.0;
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!((actual - expected).abs() <= tolerance, "{} is not within {} of {}", actual, tolerance, expected);
+    }
+
+    fn sine(frequency: f64, num_samples: usize) -> Vec<f64> {
+        (0..num_samples).map(|i| (2.0 * PI * frequency * i as f64 / F_SAMPLE as f64).sin()).collect()
+    }
+
+    // Magnitude of the frequency response of `filter` at `frequency` Hz.
+    fn gain(filter: &ButterworthFilter, frequency: f64) -> f64 {
+        let w = 2.0 * PI * frequency / F_SAMPLE as f64;
+        filter.sections().iter().map(|section| {
+            // H(z) at z = e^jw, as (re, im) of numerator and denominator.
+            let response = |c0: f64, c1: f64, c2: f64| (c0 + c1 * w.cos() + c2 * (2.0 * w).cos(), -c1 * w.sin() - c2 * (2.0 * w).sin());
+            let (num_re, num_im) = response(section.b0, section.b1, section.b2);
+            let (den_re, den_im) = response(1.0, section.a1, section.a2);
+            (num_re.hypot(num_im)) / (den_re.hypot(den_im))
+        }).product()
+    }
+
+    #[test]
+    fn second_order_coefficients_match_bilinear_design() {
+        // scipy.signal.butter(2, 1000, btype=..., fs=30000)
+        let low_pass = ButterworthFilter::new(FilterBand::LowPass(1000.0), 2, F_SAMPLE).unwrap();
+        let expected = [0.009525762376195455, 0.01905152475239091, 0.009525762376195455, -1.7055521455440839, 0.7436551950488658];
+        let section = low_pass.sections()[0];
+        for (actual, expected) in [section.b0, section.b1, section.b2, section.a1, section.a2].into_iter().zip(expected) {
+            assert_close(actual, expected, 1e-12);
+        }
+
+        let high_pass = ButterworthFilter::new(FilterBand::HighPass(1000.0), 2, F_SAMPLE).unwrap();
+        let expected = [0.8623018351482374, -1.7246036702964749, 0.8623018351482374, -1.7055521455440839, 0.7436551950488658];
+        let section = high_pass.sections()[0];
+        for (actual, expected) in [section.b0, section.b1, section.b2, section.a1, section.a2].into_iter().zip(expected) {
+            assert_close(actual, expected, 1e-12);
+        }
+    }
+
+    #[test]
+    fn frequency_response_is_butterworth() {
+        for order in 1..=6 {
+            let low_pass = ButterworthFilter::new(FilterBand::LowPass(1000.0), order, F_SAMPLE).unwrap();
+            let high_pass = ButterworthFilter::new(FilterBand::HighPass(1000.0), order, F_SAMPLE).unwrap();
+            assert_eq!(low_pass.sections().len(), order.div_ceil(2));
+            // -3 dB at the cutoff, flat in the pass band.
+            assert_close(gain(&low_pass, 1000.0), std::f64::consts::FRAC_1_SQRT_2, 1e-9);
+            assert_close(gain(&high_pass, 1000.0), std::f64::consts::FRAC_1_SQRT_2, 1e-9);
+            assert_close(gain(&low_pass, 0.0), 1.0, 1e-9);
+            assert_close(gain(&high_pass, F_SAMPLE as f64 / 2.0), 1.0, 1e-9);
+            // Falling off faster with each order; the bilinear transform
+            // only steepens the analog 1 / sqrt(1 + (f / fc)^2n).
+            let analog = 1.0 / (1.0 + 4.0f64.powi(2 * order as i32)).sqrt();
+            assert!(gain(&low_pass, 4000.0) <= analog);
+        }
+
+        let band_pass = ButterworthFilter::new(FilterBand::BandPass(300.0, 6000.0), 4, F_SAMPLE).unwrap();
+        assert_close(gain(&band_pass, 1500.0), 1.0, 1e-3);
+        assert!(gain(&band_pass, 30.0) < 1e-3);
+    }
+
+    #[test]
+    fn invalid_filters_are_rejected() {
+        assert!(ButterworthFilter::new(FilterBand::LowPass(1000.0), 0, F_SAMPLE).is_err());
+        assert!(ButterworthFilter::new(FilterBand::LowPass(15000.0), 2, F_SAMPLE).is_err());
+        assert!(ButterworthFilter::new(FilterBand::HighPass(-1.0), 2, F_SAMPLE).is_err());
+        assert!(ButterworthFilter::new(FilterBand::BandPass(6000.0, 300.0), 2, F_SAMPLE).is_err());
+    }
+
+    #[test]
+    fn filter_starts_in_steady_state() {
+        // A constant offset passes a low-pass filter and is removed by a
+        // high-pass filter from the first sample, without a transient.
+        let signal = vec![250.0; 100];
+        for order in [1, 2, 3] {
+            let low_pass = ButterworthFilter::new(FilterBand::LowPass(1000.0), order, F_SAMPLE).unwrap();
+            let high_pass = ButterworthFilter::new(FilterBand::HighPass(300.0), order, F_SAMPLE).unwrap();
+            assert!(low_pass.filter(&signal).iter().all(|&x| (x - 250.0).abs() < 1e-9));
+            assert!(high_pass.filter(&signal).iter().all(|&x| x.abs() < 1e-9));
+        }
+    }
+
+    #[test]
+    fn filtfilt_pads_with_odd_reflections() {
+        let padded = RefCell::new(Vec::new());
+        let signal_out = filtfilt(&[1.0, 2.0, 4.0], 2, |signal| {
+            padded.borrow_mut().push(signal.to_vec());
+            signal.to_vec()
+        });
+        assert_eq!(signal_out, [1.0, 2.0, 4.0]);
+        assert_eq!(padded.borrow()[0], [-2.0, 0.0, 1.0, 2.0, 4.0, 6.0, 7.0]);
+        // The backward pass runs over the reversed forward output.
+        assert_eq!(padded.borrow()[1], [7.0, 6.0, 4.0, 2.0, 1.0, 0.0, -2.0]);
+
+        // Padding is clipped to one sample less than the signal.
+        let signal_out = filtfilt(&[1.0, 3.0], 10, |signal| {
+            assert_eq!(signal.len(), 4);
+            signal.to_vec()
+        });
+        assert_eq!(signal_out, [1.0, 3.0]);
+    }
+
+    #[test]
+    fn zero_phase_filter_does_not_shift_a_sine() {
+        // A 100 Hz sine well inside the pass band of a 1 kHz low-pass filter.
+        let signal = sine(100.0, 3001);
+        let filter = ButterworthFilter::new(FilterBand::LowPass(1000.0), 4, F_SAMPLE).unwrap();
+
+        let zero_phase = filter.filter_zero_phase(&signal);
+        assert_eq!(zero_phase.len(), signal.len());
+        for (filtered, original) in zero_phase.iter().zip(&signal) {
+            assert_close(*filtered, *original, 1e-3);
+        }
+
+        // The causal filter delays the sine, so it lags the original.
+        let causal = filter.filter(&signal);
+        let error = causal[1500..].iter().zip(&signal[1500..]).map(|(a, b)| (a - b).abs()).fold(0.0, f64::max);
+        assert!(error > 0.05);
+    }
+
+    #[test]
+    fn notch_removes_mains() {
+        let rms = |signal: &[f64]| (signal[15000..].iter().map(|x| x * x).sum::<f64>() / (signal.len() - 15000) as f64).sqrt();
+        let mains = notch_filter(&sine(60.0, 30000), F_SAMPLE, 60.0, 10.0);
+        assert!(rms(&mains) < 1e-3);
+        let spikes = notch_filter(&sine(1000.0, 30000), F_SAMPLE, 60.0, 10.0);
+        assert_close(rms(&spikes), std::f64::consts::FRAC_1_SQRT_2, 1e-2);
+
+        // Intan's notch design passes 1 kHz with a gain of about 1.008, which
+        // the zero-phase filter applies twice.
+        let zero_phase = notch_filter_zero_phase(&sine(1000.0, 30000), F_SAMPLE, 60.0, 10.0);
+        for (filtered, original) in zero_phase[1000..29000].iter().zip(&sine(1000.0, 30000)[1000..29000]) {
+            assert_close(*filtered, *original, 2e-2);
+        }
+    }
+}
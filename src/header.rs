@@ -0,0 +1,595 @@
+// Standard library imports
+use std::collections::HashMap;
+
+// Local modules
+use crate::import_hash::DataType;
+
+/// Which of the two Intan file formats a header was read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FileType {
+    /// RHS2000 stimulation/recording controller files (.rhs).
+    #[default]
+    Rhs,
+    /// RHD2000 recording controller and evaluation board files (.rhd).
+    Rhd,
+}
+
+/// Version of the software that wrote the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Version {
+    pub major: i16,
+    pub minor: i16,
+}
+
+/// The kind of signal a channel records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SignalType {
+    Amplifier,
+    /// RHD only.
+    AuxInput,
+    /// RHD only.
+    SupplyVoltage,
+    BoardAdc,
+    /// RHS only.
+    BoardDac,
+    BoardDigIn,
+    BoardDigOut,
+}
+
+impl SignalType {
+    /// Every signal type, in the order channel lists appear in the header.
+    pub const ALL: [SignalType; 7] = [
+        SignalType::Amplifier,
+        SignalType::AuxInput,
+        SignalType::SupplyVoltage,
+        SignalType::BoardAdc,
+        SignalType::BoardDac,
+        SignalType::BoardDigIn,
+        SignalType::BoardDigOut,
+    ];
+
+    /// Decode the signal type stored with each channel in the header. RHS
+    /// files number board DAC channels 4, which RHD files use for digital
+    /// inputs, so the code depends on the file type. Returns `None` for codes
+    /// the file type cannot hold.
+    pub fn from_code(file_type: FileType, code: i16) -> Option<SignalType> {
+        match (file_type, code) {
+            (_, 0) => Some(SignalType::Amplifier),
+            (FileType::Rhd, 1) => Some(SignalType::AuxInput),
+            (FileType::Rhd, 2) => Some(SignalType::SupplyVoltage),
+            (_, 3) => Some(SignalType::BoardAdc),
+            (FileType::Rhd, 4) => Some(SignalType::BoardDigIn),
+            (FileType::Rhd, 5) => Some(SignalType::BoardDigOut),
+            (FileType::Rhs, 4) => Some(SignalType::BoardDac),
+            (FileType::Rhs, 5) => Some(SignalType::BoardDigIn),
+            (FileType::Rhs, 6) => Some(SignalType::BoardDigOut),
+            _ => None,
+        }
+    }
+
+    /// Key of the channel list holding channels of this type, as returned by
+    /// `load_file`.
+    pub fn group_name(self) -> &'static str {
+        match self {
+            SignalType::Amplifier => "amplifier_channels",
+            SignalType::AuxInput => "aux_input_channels",
+            SignalType::SupplyVoltage => "supply_voltage_channels",
+            SignalType::BoardAdc => "board_adc_channels",
+            SignalType::BoardDac => "board_dac_channels",
+            SignalType::BoardDigIn => "board_dig_in_channels",
+            SignalType::BoardDigOut => "board_dig_out_channels",
+        }
+    }
+}
+
+/// Mains notch filter setting of the acquisition software.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NotchFilterMode {
+    #[default]
+    Disabled,
+    Hz50,
+    Hz60,
+}
+
+impl NotchFilterMode {
+    /// Unknown codes are treated as a disabled filter.
+    pub fn from_code(code: i16) -> NotchFilterMode {
+        match code {
+            1 => NotchFilterMode::Hz50,
+            2 => NotchFilterMode::Hz60,
+            _ => NotchFilterMode::Disabled,
+        }
+    }
+
+    /// Notch frequency in Hz, or `None` if the filter was off.
+    pub fn frequency(self) -> Option<f32> {
+        match self {
+            NotchFilterMode::Disabled => None,
+            NotchFilterMode::Hz50 => Some(50.0),
+            NotchFilterMode::Hz60 => Some(60.0),
+        }
+    }
+}
+
+/// How amplifiers recover after stimulation (RHS only).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AmpSettleMode {
+    /// Switch the amplifier to its lower settle bandwidth.
+    #[default]
+    SwitchLowerBandwidth,
+    /// Traditional fast settle, holding the amplifier input at ground.
+    TraditionalFastSettle,
+}
+
+impl AmpSettleMode {
+    pub fn from_code(code: i16) -> Option<AmpSettleMode> {
+        match code {
+            0 => Some(AmpSettleMode::SwitchLowerBandwidth),
+            1 => Some(AmpSettleMode::TraditionalFastSettle),
+            _ => None,
+        }
+    }
+
+    pub fn code(self) -> i32 {
+        match self {
+            AmpSettleMode::SwitchLowerBandwidth => 0,
+            AmpSettleMode::TraditionalFastSettle => 1,
+        }
+    }
+}
+
+/// How electrodes are discharged after stimulation (RHS only).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChargeRecoveryMode {
+    /// Drive the electrode towards the target voltage through the
+    /// current-limited charge recovery circuit.
+    #[default]
+    CurrentLimited,
+    /// Short the electrode to ground through the charge recovery switch.
+    Switch,
+}
+
+impl ChargeRecoveryMode {
+    pub fn from_code(code: i16) -> Option<ChargeRecoveryMode> {
+        match code {
+            0 => Some(ChargeRecoveryMode::CurrentLimited),
+            1 => Some(ChargeRecoveryMode::Switch),
+            _ => None,
+        }
+    }
+
+    pub fn code(self) -> i32 {
+        match self {
+            ChargeRecoveryMode::CurrentLimited => 0,
+            ChargeRecoveryMode::Switch => 1,
+        }
+    }
+}
+
+/// Sample rates and amplifier bandwidth settings, in Hz.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FrequencyParameters {
+    pub amplifier_sample_rate: f32,
+    /// RHD only: auxiliary inputs are sampled every 4th amplifier sample.
+    pub aux_input_sample_rate: Option<f32>,
+    /// RHD only: supply voltage and temperature sensors are sampled once per
+    /// data block.
+    pub supply_voltage_sample_rate: Option<f32>,
+    pub board_adc_sample_rate: f32,
+    pub board_dig_in_sample_rate: f32,
+    pub dsp_enabled: bool,
+    pub desired_dsp_cutoff_frequency: f32,
+    pub actual_dsp_cutoff_frequency: f32,
+    pub desired_lower_bandwidth: f32,
+    pub actual_lower_bandwidth: f32,
+    /// RHS only: lower bandwidth used while amplifiers settle after
+    /// stimulation.
+    pub desired_lower_settle_bandwidth: Option<f32>,
+    /// RHS only.
+    pub actual_lower_settle_bandwidth: Option<f32>,
+    pub desired_upper_bandwidth: f32,
+    pub actual_upper_bandwidth: f32,
+    pub notch_filter_mode: NotchFilterMode,
+    pub desired_impedance_test_frequency: f32,
+    pub actual_impedance_test_frequency: f32,
+}
+
+/// Stimulation settings of an RHS recording.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StimParameters {
+    /// Current of one stimulation step, in Amps.
+    pub stim_step_size: f32,
+    /// In Amps.
+    pub charge_recovery_current_limit: f32,
+    /// In Volts.
+    pub charge_recovery_target_voltage: f32,
+    pub amp_settle_mode: AmpSettleMode,
+    pub charge_recovery_mode: ChargeRecoveryMode,
+}
+
+/// One enabled channel, as described in the header.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Channel {
+    pub signal_type: SignalType,
+    pub port_name: String,
+    pub port_prefix: String,
+    pub port_number: i32,
+    /// Name given by the hardware, e.g. `A-000`.
+    pub native_channel_name: String,
+    /// Name given by the user, e.g. `A-000` or `CA1-left`.
+    pub custom_channel_name: String,
+    pub native_order: i32,
+    pub custom_order: i32,
+    pub chip_channel: i32,
+    pub board_stream: i32,
+    /// In Ohms.
+    pub electrode_impedance_magnitude: f32,
+    /// In degrees.
+    pub electrode_impedance_phase: f32,
+}
+
+/// Spike scope trigger settings of one amplifier channel.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SpikeTrigger {
+    /// 0 to trigger on a digital input, 1 to trigger on a voltage threshold.
+    pub voltage_trigger_mode: i32,
+    /// In microVolts.
+    pub voltage_threshold: i32,
+    pub digital_trigger_channel: i32,
+    /// 0 to trigger on falling edges, 1 on rising edges.
+    pub digital_edge_polarity: i32,
+}
+
+/// Everything the header of an .rhs or .rhd file describes.
+///
+/// Channel lists only hold enabled channels. When `load_file` is asked for a
+/// subset of channels, the lists returned with the data are narrowed to the
+/// selection.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RhsHeader {
+    pub file_type: FileType,
+    pub version: Version,
+    /// Amplifier sample rate in Hz.
+    pub sample_rate: f32,
+    pub frequency_parameters: FrequencyParameters,
+    /// RHS only.
+    pub stim_parameters: Option<StimParameters>,
+    pub notes: [String; 3],
+    /// RHS only: whether DC amplifier data was saved alongside the amplifier
+    /// data.
+    pub dc_amplifier_data_saved: bool,
+    /// Selects the board ADC conversion of RHD files.
+    pub eval_board_mode: i32,
+    pub reference_channel: String,
+    /// RHD only. Temperature sensors have no channel list, only a count.
+    pub num_temp_sensor_channels: usize,
+    pub amplifier_channels: Vec<Channel>,
+    /// One per amplifier channel, in the same order.
+    pub spike_triggers: Vec<SpikeTrigger>,
+    pub aux_input_channels: Vec<Channel>,
+    pub supply_voltage_channels: Vec<Channel>,
+    pub board_adc_channels: Vec<Channel>,
+    pub board_dac_channels: Vec<Channel>,
+    pub board_dig_in_channels: Vec<Channel>,
+    pub board_dig_out_channels: Vec<Channel>,
+}
+
+impl RhsHeader {
+    pub fn is_rhd(&self) -> bool {
+        self.file_type == FileType::Rhd
+    }
+
+    pub fn version_at_least(&self, major: i16, minor: i16) -> bool {
+        self.version >= Version { major, minor }
+    }
+
+    /// RHD files written before version 1.2 store timestamps as unsigned
+    /// 32-bit integers; later RHD files and all RHS files store them signed.
+    pub fn timestamps_signed(&self) -> bool {
+        !self.is_rhd() || self.version_at_least(1, 2)
+    }
+
+    /// RHS files and RHD files from version 2.0 onwards hold 128 samples per
+    /// data block; RHD files written by version 1.x hold 60.
+    pub fn num_samples_per_data_block(&self) -> usize {
+        if self.is_rhd() && !self.version_at_least(2, 0) { 60 } else { 128 }
+    }
+
+    pub fn channels(&self, signal_type: SignalType) -> &Vec<Channel> {
+        match signal_type {
+            SignalType::Amplifier => &self.amplifier_channels,
+            SignalType::AuxInput => &self.aux_input_channels,
+            SignalType::SupplyVoltage => &self.supply_voltage_channels,
+            SignalType::BoardAdc => &self.board_adc_channels,
+            SignalType::BoardDac => &self.board_dac_channels,
+            SignalType::BoardDigIn => &self.board_dig_in_channels,
+            SignalType::BoardDigOut => &self.board_dig_out_channels,
+        }
+    }
+
+    pub fn channels_mut(&mut self, signal_type: SignalType) -> &mut Vec<Channel> {
+        match signal_type {
+            SignalType::Amplifier => &mut self.amplifier_channels,
+            SignalType::AuxInput => &mut self.aux_input_channels,
+            SignalType::SupplyVoltage => &mut self.supply_voltage_channels,
+            SignalType::BoardAdc => &mut self.board_adc_channels,
+            SignalType::BoardDac => &mut self.board_dac_channels,
+            SignalType::BoardDigIn => &mut self.board_dig_in_channels,
+            SignalType::BoardDigOut => &mut self.board_dig_out_channels,
+        }
+    }
+
+    /// The first setting that differs between this header and `other`, or
+    /// `None` if data recorded with the two can be joined into one recording.
+    /// Notes and electrode impedances are free to differ.
+    pub fn incompatible_field(&self, other: &RhsHeader) -> Option<&'static str> {
+        if self.file_type != other.file_type {
+            return Some("file type");
+        }
+        if self.version != other.version {
+            return Some("version");
+        }
+        if self.sample_rate != other.sample_rate {
+            return Some("sample rate");
+        }
+        if self.frequency_parameters != other.frequency_parameters {
+            return Some("frequency parameters");
+        }
+        if self.stim_parameters != other.stim_parameters {
+            return Some("stimulation parameters");
+        }
+        if self.dc_amplifier_data_saved != other.dc_amplifier_data_saved {
+            return Some("DC amplifier setting");
+        }
+        if self.eval_board_mode != other.eval_board_mode {
+            return Some("evaluation board mode");
+        }
+        if self.reference_channel != other.reference_channel {
+            return Some("reference channel");
+        }
+        if self.num_temp_sensor_channels != other.num_temp_sensor_channels {
+            return Some("temperature sensor channels");
+        }
+        for signal_type in SignalType::ALL {
+            let (channels, other_channels) = (self.channels(signal_type), other.channels(signal_type));
+            if channels.len() != other_channels.len() || channels.iter().zip(other_channels).any(|(a, b)| !a.same_channel(b)) {
+                return Some(signal_type.group_name());
+            }
+        }
+        if self.spike_triggers != other.spike_triggers {
+            return Some("spike triggers");
+        }
+        None
+    }
+
+    /// The header in the form `load_file` returns it: stimulation and
+    /// frequency parameters, notes, reference channel, spike triggers and
+    /// every non-empty channel list.
+    pub fn to_dict(&self) -> HashMap<String, DataType> {
+        let mut result_out: HashMap<String, DataType> = HashMap::new();
+
+        if let Some(stim_parameters) = &self.stim_parameters {
+            result_out.insert("stim_parameters".to_string(), stim_parameters.to_dict());
+        }
+
+        let notes = self.notes.iter().enumerate()
+            .map(|(i, note)| (format!("note{}", i + 1), DataType::String(note.clone())))
+            .collect();
+        result_out.insert("notes".to_string(), DataType::HashMap(notes));
+
+        if !self.amplifier_channels.is_empty() {
+            let spike_triggers = self.spike_triggers.iter().map(SpikeTrigger::to_dict).collect();
+            result_out.insert("spike_triggers".to_string(), DataType::VecChannel(spike_triggers));
+        }
+
+        result_out.insert("frequency_parameters".to_string(), self.frequency_parameters.to_dict());
+        result_out.insert("reference_channel".to_string(), DataType::String(self.reference_channel.clone()));
+
+        for signal_type in SignalType::ALL {
+            let channels = self.channels(signal_type);
+            if !channels.is_empty() {
+                let channels = channels.iter().map(Channel::to_dict).collect();
+                result_out.insert(signal_type.group_name().to_string(), DataType::VecChannel(channels));
+            }
+        }
+
+        result_out
+    }
+}
+
+impl FrequencyParameters {
+    fn to_dict(&self) -> DataType {
+        let mut freq: HashMap<String, DataType> = HashMap::new();
+        let mut insert = |key: &str, value: DataType| {
+            freq.insert(key.to_string(), value);
+        };
+
+        insert("amplifier_sample_rate", DataType::Float(self.amplifier_sample_rate));
+        if let Some(rate) = self.aux_input_sample_rate {
+            insert("aux_input_sample_rate", DataType::Float(rate));
+        }
+        if let Some(rate) = self.supply_voltage_sample_rate {
+            insert("supply_voltage_sample_rate", DataType::Float(rate));
+        }
+        insert("board_adc_sample_rate", DataType::Float(self.board_adc_sample_rate));
+        insert("board_dig_in_sample_rate", DataType::Float(self.board_dig_in_sample_rate));
+        insert("dsp_enabled", DataType::Int(self.dsp_enabled as i32));
+        insert("desired_dsp_cutoff_frequency", DataType::Float(self.desired_dsp_cutoff_frequency));
+        insert("actual_dsp_cutoff_frequency", DataType::Float(self.actual_dsp_cutoff_frequency));
+        insert("desired_lower_bandwidth", DataType::Float(self.desired_lower_bandwidth));
+        insert("actual_lower_bandwidth", DataType::Float(self.actual_lower_bandwidth));
+        if let Some(bandwidth) = self.desired_lower_settle_bandwidth {
+            insert("desired_lower_settle_bandwidth", DataType::Float(bandwidth));
+        }
+        if let Some(bandwidth) = self.actual_lower_settle_bandwidth {
+            insert("actual_lower_settle_bandwidth", DataType::Float(bandwidth));
+        }
+        insert("desired_upper_bandwidth", DataType::Float(self.desired_upper_bandwidth));
+        insert("actual_upper_bandwidth", DataType::Float(self.actual_upper_bandwidth));
+        // Reported in whole Hz, or None if the filter was off.
+        insert("notch_filter_frequency", match self.notch_filter_mode.frequency() {
+            Some(frequency) => DataType::Int(frequency as i32),
+            None => DataType::None,
+        });
+        insert("desired_impedance_test_frequency", DataType::Float(self.desired_impedance_test_frequency));
+        insert("actual_impedance_test_frequency", DataType::Float(self.actual_impedance_test_frequency));
+
+        DataType::HashMap(freq)
+    }
+}
+
+impl StimParameters {
+    fn to_dict(&self) -> DataType {
+        let mut stim_parameters: HashMap<String, DataType> = HashMap::new();
+        stim_parameters.insert("stim_step_size".to_string(), DataType::Float(self.stim_step_size));
+        stim_parameters.insert("charge_recovery_current_limit".to_string(), DataType::Float(self.charge_recovery_current_limit));
+        stim_parameters.insert("charge_recovery_target_voltage".to_string(), DataType::Float(self.charge_recovery_target_voltage));
+        stim_parameters.insert("amp_settle_mode".to_string(), DataType::Int(self.amp_settle_mode.code()));
+        stim_parameters.insert("charge_recovery_mode".to_string(), DataType::Int(self.charge_recovery_mode.code()));
+        DataType::HashMap(stim_parameters)
+    }
+}
+
+impl Channel {
+    // Whether both describe the same channel, whatever its impedance.
+    fn same_channel(&self, other: &Channel) -> bool {
+        Channel {
+            electrode_impedance_magnitude: other.electrode_impedance_magnitude,
+            electrode_impedance_phase: other.electrode_impedance_phase,
+            ..self.clone()
+        } == *other
+    }
+
+    fn to_dict(&self) -> HashMap<String, DataType> {
+        let mut channel: HashMap<String, DataType> = HashMap::new();
+        channel.insert("port_name".to_string(), DataType::String(self.port_name.clone()));
+        channel.insert("port_prefix".to_string(), DataType::String(self.port_prefix.clone()));
+        channel.insert("port_number".to_string(), DataType::Int(self.port_number));
+        channel.insert("native_channel_name".to_string(), DataType::String(self.native_channel_name.clone()));
+        channel.insert("custom_channel_name".to_string(), DataType::String(self.custom_channel_name.clone()));
+        channel.insert("native_order".to_string(), DataType::Int(self.native_order));
+        channel.insert("custom_order".to_string(), DataType::Int(self.custom_order));
+        channel.insert("chip_channel".to_string(), DataType::Int(self.chip_channel));
+        channel.insert("board_stream".to_string(), DataType::Int(self.board_stream));
+        channel.insert("electrode_impedance_magnitude".to_string(), DataType::Float(self.electrode_impedance_magnitude));
+        channel.insert("electrode_impedance_phase".to_string(), DataType::Float(self.electrode_impedance_phase));
+        channel
+    }
+}
+
+impl SpikeTrigger {
+    fn to_dict(&self) -> HashMap<String, DataType> {
+        let mut trigger: HashMap<String, DataType> = HashMap::new();
+        trigger.insert("voltage_trigger_mode".to_string(), DataType::Int(self.voltage_trigger_mode));
+        trigger.insert("voltage_threshold".to_string(), DataType::Int(self.voltage_threshold));
+        trigger.insert("digital_trigger_channel".to_string(), DataType::Int(self.digital_trigger_channel));
+        trigger.insert("digital_edge_polarity".to_string(), DataType::Int(self.digital_edge_polarity));
+        trigger
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import_hash::{open_file, read_header};
+    use crate::test_files::{test_dir, write_rhd, write_rhs, RhdChannels};
+
+    fn rhs_header(name: &str) -> RhsHeader {
+        let path = test_dir(name).join("header.rhs");
+        write_rhs(&path, 2, &[], |_, _| 0);
+        read_header(&mut open_file(path.to_str().unwrap()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn signal_type_codes_depend_on_file_type() {
+        let rhs = [Some(SignalType::Amplifier), None, None, Some(SignalType::BoardAdc), Some(SignalType::BoardDac), Some(SignalType::BoardDigIn), Some(SignalType::BoardDigOut), None];
+        let rhd = [Some(SignalType::Amplifier), Some(SignalType::AuxInput), Some(SignalType::SupplyVoltage), Some(SignalType::BoardAdc), Some(SignalType::BoardDigIn), Some(SignalType::BoardDigOut), None, None];
+        for (file_type, signal_types) in [(FileType::Rhs, rhs), (FileType::Rhd, rhd)] {
+            for (code, signal_type) in signal_types.into_iter().enumerate() {
+                assert_eq!(SignalType::from_code(file_type, code as i16), signal_type, "{:?} code {}", file_type, code);
+            }
+            assert_eq!(SignalType::from_code(file_type, -1), None);
+        }
+    }
+
+    #[test]
+    fn setting_codes_round_trip() {
+        for (code, mode, frequency) in [(0, NotchFilterMode::Disabled, None), (1, NotchFilterMode::Hz50, Some(50.0)), (2, NotchFilterMode::Hz60, Some(60.0))] {
+            assert_eq!(NotchFilterMode::from_code(code), mode);
+            assert_eq!(mode.frequency(), frequency);
+        }
+        assert_eq!(NotchFilterMode::from_code(3), NotchFilterMode::Disabled);
+
+        for mode in [AmpSettleMode::SwitchLowerBandwidth, AmpSettleMode::TraditionalFastSettle] {
+            assert_eq!(AmpSettleMode::from_code(mode.code() as i16), Some(mode));
+        }
+        assert_eq!(AmpSettleMode::from_code(2), None);
+        for mode in [ChargeRecoveryMode::CurrentLimited, ChargeRecoveryMode::Switch] {
+            assert_eq!(ChargeRecoveryMode::from_code(mode.code() as i16), Some(mode));
+        }
+        assert_eq!(ChargeRecoveryMode::from_code(-1), None);
+    }
+
+    #[test]
+    fn header_dict_keys() {
+        let mut header = rhs_header("header_dict");
+        header.stim_parameters.as_mut().unwrap().amp_settle_mode = AmpSettleMode::TraditionalFastSettle;
+        let dict = header.to_dict();
+        let mut keys: Vec<&str> = dict.keys().map(String::as_str).collect();
+        keys.sort();
+        assert_eq!(keys, ["amplifier_channels", "frequency_parameters", "notes", "reference_channel", "spike_triggers", "stim_parameters"]);
+
+        let Some(DataType::HashMap(stim_parameters)) = dict.get("stim_parameters") else {
+            panic!("no stim_parameters");
+        };
+        assert!(matches!(stim_parameters.get("amp_settle_mode"), Some(DataType::Int(1))));
+        assert!(matches!(stim_parameters.get("charge_recovery_mode"), Some(DataType::Int(0))));
+        let Some(DataType::HashMap(frequency_parameters)) = dict.get("frequency_parameters") else {
+            panic!("no frequency_parameters");
+        };
+        assert!(frequency_parameters.contains_key("desired_lower_settle_bandwidth"));
+        assert!(!frequency_parameters.contains_key("aux_input_sample_rate"));
+        assert!(matches!(dict.get("amplifier_channels"), Some(DataType::VecChannel(channels)) if channels.len() == 2));
+        assert!(matches!(dict.get("spike_triggers"), Some(DataType::VecChannel(triggers)) if triggers.len() == 2));
+
+        // RHD headers have no stimulation, but sample auxiliary inputs and
+        // supply voltages at their own rates.
+        let path = test_dir("header_dict").join("header.rhd");
+        write_rhd(&path, (3, 0), RhdChannels { amplifier: 1, aux_input: 1, supply_voltage: 1, temp_sensor: 0 }, &[], |_, _, _| 0);
+        let mut rhd_header = read_header(&mut open_file(path.to_str().unwrap()).unwrap()).unwrap();
+        rhd_header.frequency_parameters.notch_filter_mode = NotchFilterMode::Hz50;
+        let dict = rhd_header.to_dict();
+        assert!(!dict.contains_key("stim_parameters"));
+        assert!(dict.contains_key("aux_input_channels") && dict.contains_key("supply_voltage_channels"));
+        let Some(DataType::HashMap(frequency_parameters)) = dict.get("frequency_parameters") else {
+            panic!("no frequency_parameters");
+        };
+        assert!(matches!(frequency_parameters.get("aux_input_sample_rate"), Some(DataType::Float(rate)) if *rate == 5000.0));
+        assert!(matches!(frequency_parameters.get("notch_filter_frequency"), Some(DataType::Int(50))));
+    }
+
+    #[test]
+    fn incompatible_fields() {
+        let header = rhs_header("incompatible");
+        assert_eq!(header.incompatible_field(&header.clone()), None);
+
+        // Notes and impedances may change between the files of a series.
+        let mut other = header.clone();
+        other.notes[0] = "second file".to_string();
+        other.amplifier_channels[1].electrode_impedance_magnitude = 5.0e5;
+        assert_eq!(header.incompatible_field(&other), None);
+
+        let check = |field: &str, change: fn(&mut RhsHeader)| {
+            let mut other = header.clone();
+            change(&mut other);
+            assert_eq!(header.incompatible_field(&other), Some(field));
+        };
+        check("file type", |header| header.file_type = FileType::Rhd);
+        check("sample rate", |header| header.sample_rate = 20000.0);
+        check("frequency parameters", |header| header.frequency_parameters.notch_filter_mode = NotchFilterMode::Hz60);
+        check("stimulation parameters", |header| header.stim_parameters = None);
+        check("DC amplifier setting", |header| header.dc_amplifier_data_saved = !header.dc_amplifier_data_saved);
+        check("amplifier_channels", |header| header.amplifier_channels[1].custom_channel_name = "CA1".to_string());
+        check("spike triggers", |header| header.spike_triggers[0].voltage_threshold += 10);
+    }
+}
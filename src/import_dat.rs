@@ -0,0 +1,484 @@
+// Standard library imports
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+// External crates
+use byteorder::{ByteOrder, LittleEndian};
+use ndarray::{Array1, ArrayView1, ArrayView2, Axis};
+
+// Local modules
+use crate::header::{Channel, RhsHeader};
+use crate::import_hash::{
+    header_info, initialize_memory, open_file, parse_data_to_result, print_header_summary, print_record_time_summary, read_header, select_channels,
+    Arrays, ChannelRows, DataType, HeaderInfo, LoadOptions, RhsError,
+};
+
+/// The layouts Intan RHX can save a recording in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SaveFormat {
+    /// A single .rhs or .rhd file holding the header and all data blocks.
+    Traditional,
+    /// An info.rhs header with one .dat file per signal type.
+    OneFilePerSignalType,
+    /// An info.rhs header with one .dat file per channel.
+    OneFilePerChannel,
+}
+
+/// Work out which save format `path` (a recording file, session directory or
+/// info.rhs header) was written in. Any other file is a traditional
+/// recording, even when it sits in a split session's directory.
+pub fn detect_save_format(path: &str) -> SaveFormat {
+    let is_session_header = Path::new(path).file_name().is_some_and(|file_name| file_name == "info.rhs" || file_name == "info.rhd");
+    if !Path::new(path).is_dir() && !is_session_header {
+        return SaveFormat::Traditional;
+    }
+    let session_dir = match resolve_session_paths(path, "info.rhs") {
+        Ok((session_dir, _)) => session_dir,
+        Err(_) => return SaveFormat::Traditional,
+    };
+
+    // Both split formats write a separate time.dat next to the header.
+    if !session_dir.join("time.dat").is_file() {
+        return SaveFormat::Traditional;
+    }
+
+    let signal_type_files = ["amplifier.dat", "stim.dat", "analogin.dat", "analogout.dat", "digitalin.dat", "digitalout.dat"];
+    if signal_type_files.iter().any(|file_name| session_dir.join(file_name).is_file()) {
+        SaveFormat::OneFilePerSignalType
+    } else {
+        SaveFormat::OneFilePerChannel
+    }
+}
+
+/// Load an RHS session saved in the "One File Per Signal Type" format.
+///
+/// `path` may be either the session directory or the `info.rhs` header file
+/// inside it. Signal data is read from the `.dat` files next to the header and
+/// returned in the same result HashMap that `load_file` builds for a
+/// traditional single-file recording, converted according to `options`.
+pub fn load_file_per_signal_type(path: &str, options: &LoadOptions) -> std::result::Result<(HashMap<String, DataType>, bool), RhsError> {
+    // Start timing
+    let tic = Instant::now();
+
+    let (session_dir, header_path) = resolve_session_paths(path, "info.rhs")?;
+
+    // read file header
+    let mut fid: File = open_file(&header_path)?;
+    let header: RhsHeader = read_header(&mut fid)?;
+    if header.is_rhd() {
+        return Err(RhsError::InvalidArgumentError("One File Per Signal Type loading only supports RHS sessions".to_string()));
+    }
+
+    // The .dat files hold every channel in the full header, while only the
+    // selected channels are allocated and returned.
+    let mut selected_header = header.clone();
+    let channel_rows = select_channels(&mut selected_header, options)?;
+    options.check_filters(&header)?;
+
+    // The timestamp file always exists and holds one i32 per sample, so it
+    // determines how much data is present.
+    let num_samples = file_size(&session_dir.join("time.dat"))? / 4;
+    let data_present = num_samples > 0;
+    if options.verbose {
+        print_header_summary(&header);
+        print_record_time_summary(num_samples, header.sample_rate, data_present);
+    }
+
+    let mut result_out: HashMap<String, DataType> = selected_header.to_dict();
+
+    if data_present {
+        let sample_range = options.sample_range(num_samples, header.sample_rate)?;
+        let sample_range = sample_range.start as usize..sample_range.end as usize;
+        if options.verbose {
+            println!("\nAllocating memory for data...");
+        }
+        let (mut data, _) = initialize_memory(&selected_header, sample_range.len() as u64);
+        if options.verbose {
+            println!("Reading data from files...");
+        }
+        read_signal_type_files(&header, channel_rows.as_ref(), &session_dir, num_samples as usize, &sample_range, &mut data)?;
+
+        parse_data_to_result(&selected_header, &mut data, options, &mut result_out)?;
+    }
+
+    // Report how long read took.
+    if options.verbose {
+        println!("Done! Elapsed time: {:.1} seconds", tic.elapsed().as_secs_f64());
+    }
+
+    Ok((result_out, data_present))
+}
+
+/// Load an RHS session saved in the "One File Per Channel" format.
+///
+/// `path` may be either the session directory or the `info.rhs` header file
+/// inside it. Each enabled channel is read from its own `.dat` file, located
+/// through the channel's native name (e.g. `amp-A-000.dat`, `stim-A-000.dat`,
+/// `board-DIGITAL-IN-01.dat`). If `options` selects channels, only their files
+/// are read.
+pub fn load_file_per_channel(path: &str, options: &LoadOptions) -> std::result::Result<(HashMap<String, DataType>, bool), RhsError> {
+    // Start timing
+    let tic = Instant::now();
+
+    let (session_dir, header_path) = resolve_session_paths(path, "info.rhs")?;
+
+    // read file header
+    let mut fid: File = open_file(&header_path)?;
+    let mut header: RhsHeader = read_header(&mut fid)?;
+    if header.is_rhd() {
+        return Err(RhsError::InvalidArgumentError("One File Per Channel loading only supports RHS sessions".to_string()));
+    }
+
+    select_channels(&mut header, options)?;
+    options.check_filters(&header)?;
+
+    // The timestamp file always exists and holds one i32 per sample, so it
+    // determines how much data is present.
+    let num_samples = file_size(&session_dir.join("time.dat"))? / 4;
+    let data_present = num_samples > 0;
+    if options.verbose {
+        print_header_summary(&header);
+        print_record_time_summary(num_samples, header.sample_rate, data_present);
+    }
+
+    let mut result_out: HashMap<String, DataType> = header.to_dict();
+
+    if data_present {
+        let sample_range = options.sample_range(num_samples, header.sample_rate)?;
+        let sample_range = sample_range.start as usize..sample_range.end as usize;
+        if options.verbose {
+            println!("\nAllocating memory for data...");
+        }
+        let (mut data, _) = initialize_memory(&header, sample_range.len() as u64);
+        if options.verbose {
+            println!("Reading data from files...");
+        }
+        read_channel_files(&header, &session_dir, num_samples as usize, &sample_range, &mut data)?;
+
+        parse_data_to_result(&header, &mut data, options, &mut result_out)?;
+    }
+
+    // Report how long read took.
+    if options.verbose {
+        println!("Done! Elapsed time: {:.1} seconds", tic.elapsed().as_secs_f64());
+    }
+
+    Ok((result_out, data_present))
+}
+
+/// Read the info.rhs header of a One File Per Signal Type or One File Per
+/// Channel session and work out how much data it holds from the size of
+/// time.dat. No sample data is read.
+pub fn read_session_header_info(path: &str) -> std::result::Result<HeaderInfo, RhsError> {
+    let (session_dir, header_path) = resolve_session_paths(path, "info.rhs")?;
+
+    let mut fid: File = open_file(&header_path)?;
+    let header: RhsHeader = read_header(&mut fid)?;
+
+    let num_samples = file_size(&session_dir.join("time.dat"))? / 4;
+    let data_present = num_samples > 0;
+
+    // Split sessions are not stored in blocks; report how many blocks the
+    // same samples would fill in a traditional file.
+    let samples_per_block = header.num_samples_per_data_block() as u64;
+    let num_blocks = num_samples.div_ceil(samples_per_block);
+
+    Ok(header_info(header, data_present, num_blocks, num_samples))
+}
+
+pub(crate) fn resolve_session_paths(path: &str, header_file_name: &str) -> std::result::Result<(PathBuf, PathBuf), RhsError> {
+    let path = Path::new(path);
+    if path.is_dir() {
+        Ok((path.to_path_buf(), path.join(header_file_name)))
+    } else {
+        let session_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        Ok((session_dir, path.to_path_buf()))
+    }
+}
+
+fn read_signal_type_files(header: &RhsHeader, channel_rows: Option<&ChannelRows>, session_dir: &Path, num_samples: usize, sample_range: &Range<usize>, data: &mut HashMap<String, Arrays>) -> std::result::Result<(), RhsError> {
+    read_timestamps(session_dir, sample_range, data)?;
+
+    let num_amplifier_channels = header.amplifier_channels.len();
+
+    // Amplifier data is saved as signed 16-bit integers already centered on
+    // zero. Shift it back to the unsigned encoding used inside .rhs files so
+    // the usual scaling applies.
+    read_signal_type_file(&session_dir.join("amplifier.dat"), num_amplifier_channels, num_samples, sample_range, true, data, "amplifier_data", channel_rows)?;
+
+    if header.dc_amplifier_data_saved {
+        read_signal_type_file(&session_dir.join("dcamplifier.dat"), num_amplifier_channels, num_samples, sample_range, false, data, "dc_amplifier_data", channel_rows)?;
+    }
+
+    read_signal_type_file(&session_dir.join("stim.dat"), num_amplifier_channels, num_samples, sample_range, false, data, "stim_data_raw", channel_rows)?;
+    read_signal_type_file(&session_dir.join("analogin.dat"), header.board_adc_channels.len(), num_samples, sample_range, false, data, "board_adc_data", channel_rows)?;
+    read_signal_type_file(&session_dir.join("analogout.dat"), header.board_dac_channels.len(), num_samples, sample_range, false, data, "board_dac_data", channel_rows)?;
+
+    // Digital inputs and outputs are saved as a single 16-bit word per sample.
+    if !header.board_dig_in_channels.is_empty() {
+        read_signal_type_file(&session_dir.join("digitalin.dat"), 1, num_samples, sample_range, false, data, "board_dig_in_raw", channel_rows)?;
+    }
+    if !header.board_dig_out_channels.is_empty() {
+        read_signal_type_file(&session_dir.join("digitalout.dat"), 1, num_samples, sample_range, false, data, "board_dig_out_raw", channel_rows)?;
+    }
+
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn read_signal_type_file(file_path: &Path, num_channels: usize, num_samples: usize, sample_range: &Range<usize>, signed: bool, data: &mut HashMap<String, Arrays>, data_key: &str, channel_rows: Option<&ChannelRows>) -> std::result::Result<(), RhsError> {
+    // Nothing to read if no channels of this signal type are enabled or
+    // selected.
+    let num_rows = match data.get(data_key) {
+        Some(Arrays::ArrayTwoU16(dest)) => dest.nrows(),
+        _ => 0,
+    };
+    if num_channels < 1 || num_rows < 1 {
+        return Ok(());
+    }
+
+    check_file_size(file_path, num_samples * num_channels * 2)?;
+
+    // Samples are interleaved: all channels for the first sample, then all
+    // channels for the second sample, and so on.
+    let buffer = read_sample_range(file_path, num_channels * 2, sample_range)?;
+    let signals = decode_samples(&buffer, signed);
+    let interleaved = ArrayView2::from_shape((sample_range.len(), num_channels), &signals)?;
+    let selected;
+    let signals = match channel_rows.and_then(|channel_rows| channel_rows.get(data_key)) {
+        Some(rows) => {
+            selected = interleaved.t().select(Axis(0), rows);
+            selected.view()
+        },
+        None => interleaved.t(),
+    };
+
+    match data.get_mut(data_key) {
+        Some(Arrays::ArrayTwoU16(dest)) => dest.assign(&signals),
+        _ => return Err(RhsError::missing_array(data_key)),
+    }
+
+    Ok(())
+}
+
+fn read_channel_files(header: &RhsHeader, session_dir: &Path, num_samples: usize, sample_range: &Range<usize>, data: &mut HashMap<String, Arrays>) -> std::result::Result<(), RhsError> {
+    read_timestamps(session_dir, sample_range, data)?;
+
+    for (i, channel) in header.amplifier_channels.iter().enumerate() {
+        let native_channel_name = &channel.native_channel_name;
+
+        // Amplifier data is saved as signed 16-bit integers already centered
+        // on zero, so shift it back to the encoding used inside .rhs files.
+        let amplifier = read_channel_file(&session_dir.join(format!("amp-{}.dat", native_channel_name)), num_samples, sample_range, true)?;
+        assign_row(data, "amplifier_data", i, &amplifier)?;
+
+        if header.dc_amplifier_data_saved {
+            let dc_amplifier = read_channel_file(&session_dir.join(format!("dc-{}.dat", native_channel_name)), num_samples, sample_range, false)?;
+            assign_row(data, "dc_amplifier_data", i, &dc_amplifier)?;
+        }
+
+        let stim = read_channel_file(&session_dir.join(format!("stim-{}.dat", native_channel_name)), num_samples, sample_range, false)?;
+        assign_row(data, "stim_data_raw", i, &stim)?;
+    }
+
+    for (channels, data_key) in [(&header.board_adc_channels, "board_adc_data"), (&header.board_dac_channels, "board_dac_data")] {
+        for (i, channel) in channels.iter().enumerate() {
+            let signal = read_channel_file(&board_file(session_dir, channel), num_samples, sample_range, false)?;
+            assign_row(data, data_key, i, &signal)?;
+        }
+    }
+
+    // Each digital channel is saved as 0 or 1 per sample. Pack them back into
+    // the 16-bit word used inside .rhs files, so each channel lands on the bit
+    // given by its native order.
+    for (channels, data_key) in [(&header.board_dig_in_channels, "board_dig_in_raw"), (&header.board_dig_out_channels, "board_dig_out_raw")] {
+        if channels.is_empty() {
+            continue;
+        }
+        let mut raw = Array1::<u16>::zeros(sample_range.len());
+        for channel in channels {
+            let signal = read_channel_file(&board_file(session_dir, channel), num_samples, sample_range, false)?;
+            raw.zip_mut_with(&ArrayView1::from(&signal), |word, &bit| if bit != 0 { *word |= 1 << channel.native_order });
+        }
+        assign_row(data, data_key, 0, raw.as_slice().unwrap_or_default())?;
+    }
+
+    Ok(())
+}
+
+fn board_file(session_dir: &Path, channel: &Channel) -> PathBuf {
+    session_dir.join(format!("board-{}.dat", channel.native_channel_name))
+}
+
+fn read_channel_file(file_path: &Path, num_samples: usize, sample_range: &Range<usize>, signed: bool) -> std::result::Result<Vec<u16>, RhsError> {
+    check_file_size(file_path, num_samples * 2)?;
+
+    let buffer = read_sample_range(file_path, 2, sample_range)?;
+    Ok(decode_samples(&buffer, signed))
+}
+
+fn read_timestamps(session_dir: &Path, sample_range: &Range<usize>, data: &mut HashMap<String, Arrays>) -> std::result::Result<(), RhsError> {
+    // Timestamps are saved as signed 32-bit integers.
+    let buffer = read_sample_range(&session_dir.join("time.dat"), 4, sample_range)?;
+    let timestamps: Vec<i32> = buffer.chunks_exact(4).map(LittleEndian::read_i32).collect();
+    data.insert("t".to_string(), Arrays::ArrayOne(Array1::from(timestamps)));
+    Ok(())
+}
+
+// Seek to the first sample in `sample_range` and read only the samples
+// inside it.
+fn read_sample_range(file_path: &Path, bytes_per_sample: usize, sample_range: &Range<usize>) -> std::result::Result<Vec<u8>, RhsError> {
+    let mut fid = open_file(file_path)?;
+    let offset = (sample_range.start * bytes_per_sample) as u64;
+    let mut buffer = vec![0; sample_range.len() * bytes_per_sample];
+    fid.seek(SeekFrom::Start(offset))
+        .and_then(|_| fid.read_exact(&mut buffer))
+        .map_err(|e| RhsError::from(e).at(format!("reading {}", file_path.display()), Some(offset)))?;
+    Ok(buffer)
+}
+
+fn file_size(file_path: &Path) -> std::result::Result<u64, RhsError> {
+    fs::metadata(file_path)
+        .map(|metadata| metadata.len())
+        .map_err(|e| RhsError::from(e).at(format!("reading the size of {}", file_path.display()), None))
+}
+
+// Each .dat file holds exactly as many samples as time.dat.
+fn check_file_size(file_path: &Path, expected: usize) -> std::result::Result<(), RhsError> {
+    let size = file_size(file_path)?;
+    if size != expected as u64 {
+        return Err(RhsError::FileSizeError { context: file_path.display().to_string(), size, expected: expected as u64 });
+    }
+    Ok(())
+}
+
+fn decode_samples(buffer: &[u8], signed: bool) -> Vec<u16> {
+    // Signed samples are centered on zero; flipping the top bit moves them to
+    // the offset-binary encoding used inside .rhs files.
+    if signed {
+        buffer.chunks_exact(2).map(|bytes| (LittleEndian::read_i16(bytes) as u16) ^ 0x8000).collect()
+    } else {
+        buffer.chunks_exact(2).map(LittleEndian::read_u16).collect()
+    }
+}
+
+fn assign_row(data: &mut HashMap<String, Arrays>, data_key: &str, row: usize, signal: &[u16]) -> std::result::Result<(), RhsError> {
+    match data.get_mut(data_key) {
+        Some(Arrays::ArrayTwoU16(dest)) => {
+            dest.index_axis_mut(Axis(0), row).assign(&ArrayView1::from(signal));
+            Ok(())
+        },
+        _ => Err(RhsError::missing_array(data_key)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::Array2;
+
+    use crate::import_hash::Position;
+    use crate::test_files::{test_dir, write_session};
+
+    const NUM_SAMPLES: usize = 200;
+
+    // Amplifier samples on both sides of zero, and digital inputs counting
+    // in binary, so that bit `channel` of the packed word is the state of
+    // DIGITAL-IN-0<channel + 1>.
+    fn amplifier(channel: usize, sample: usize) -> i16 {
+        (sample as i16 - 100) * (channel as i16 + 1)
+    }
+
+    fn dig_in(channel: usize, sample: usize) -> bool {
+        (sample >> channel) & 1 == 1
+    }
+
+    fn u16_array<'a>(result: &'a HashMap<String, DataType>, data_key: &str) -> &'a Array2<u16> {
+        match result.get(data_key) {
+            Some(DataType::Array(Arrays::ArrayTwoU16(array))) => array,
+            other => panic!("unexpected {} {:?}", data_key, other),
+        }
+    }
+
+    type LoadSession = fn(&str, &LoadOptions) -> std::result::Result<(HashMap<String, DataType>, bool), RhsError>;
+
+    // Load a session written in `format` whole and in part, and check the
+    // samples against those written.
+    fn check_session(name: &str, format: SaveFormat, load: LoadSession) {
+        let dir = test_dir(name);
+        write_session(&dir, format, 3, 3, NUM_SAMPLES, amplifier, dig_in);
+        assert_eq!(detect_save_format(dir.to_str().unwrap()), format);
+        let path = dir.join("info.rhs");
+        let path = path.to_str().unwrap();
+
+        // Signed amplifier samples are shifted to the unsigned encoding of
+        // .rhs files, and the digital inputs packed into one word.
+        let options = LoadOptions { raw: true, ..Default::default() };
+        let (result, data_present) = load(path, &options).unwrap();
+        assert!(data_present);
+        let expected = Array2::from_shape_fn((3, NUM_SAMPLES), |(channel, sample)| (amplifier(channel, sample) as i32 + 32768) as u16);
+        assert_eq!(u16_array(&result, "amplifier_data"), &expected);
+        let expected = Array2::from_shape_fn((1, NUM_SAMPLES), |(_, sample)| (sample & 0b111) as u16);
+        assert_eq!(u16_array(&result, "board_dig_in_raw"), &expected);
+        match result.get("t") {
+            Some(DataType::Array(Arrays::ArrayOne(t))) => assert_eq!(t.to_vec(), (0..NUM_SAMPLES as i32).collect::<Vec<i32>>()),
+            other => panic!("unexpected t {:?}", other),
+        }
+
+        // Scaled, part of the recording and only some channels.
+        let options = LoadOptions {
+            start: Some(Position::Sample(50)),
+            stop: Some(Position::Sample(150)),
+            channels: Some(vec!["A-002".to_string(), "DIGITAL-IN-02".to_string()]),
+            ..Default::default()
+        };
+        let (result, _) = load(path, &options).unwrap();
+        match result.get("amplifier_data") {
+            Some(DataType::Array(Arrays::ArrayTwoFloat(amplifier_data))) => {
+                let expected = Array2::from_shape_fn((1, 100), |(_, sample)| 0.195 * amplifier(2, sample + 50) as f32);
+                assert_eq!(amplifier_data, &expected);
+            },
+            other => panic!("unexpected amplifier_data {:?}", other),
+        }
+        // Unselected digital inputs may or may not be read into the shared
+        // word, but the selected one keeps its bit.
+        let expected = Array2::from_shape_fn((1, 100), |(_, sample)| ((sample + 50) & 0b010) as u16);
+        assert_eq!(u16_array(&result, "board_dig_in_raw").mapv(|word| word & 0b010), expected);
+    }
+
+    #[test]
+    fn one_file_per_signal_type_session() {
+        check_session("per_signal_type", SaveFormat::OneFilePerSignalType, load_file_per_signal_type);
+    }
+
+    #[test]
+    fn one_file_per_channel_session() {
+        check_session("per_channel", SaveFormat::OneFilePerChannel, load_file_per_channel);
+    }
+
+    #[test]
+    fn save_format_detection() {
+        let dir = test_dir("save_format");
+        for file_name in ["info.rhs", "time.dat", "amplifier.dat", "recording.rhs"] {
+            fs::write(dir.join(file_name), []).unwrap();
+        }
+        let detect = |file_name: &str| detect_save_format(dir.join(file_name).to_str().unwrap());
+
+        assert_eq!(detect(""), SaveFormat::OneFilePerSignalType);
+        assert_eq!(detect("info.rhs"), SaveFormat::OneFilePerSignalType);
+        // A traditional file saved next to a split session is still read
+        // whole.
+        assert_eq!(detect("recording.rhs"), SaveFormat::Traditional);
+
+        fs::remove_file(dir.join("amplifier.dat")).unwrap();
+        assert_eq!(detect("info.rhs"), SaveFormat::OneFilePerChannel);
+
+        fs::remove_file(dir.join("time.dat")).unwrap();
+        assert_eq!(detect(""), SaveFormat::Traditional);
+        assert_eq!(detect("info.rhs"), SaveFormat::Traditional);
+    }
+}
@@ -2,114 +2,360 @@
 
 
 // Standard library imports
-use std::collections::HashMap;
-use::std::fmt;
+use std::collections::{BTreeSet, HashMap};
 use std::fs::{File, metadata};
-use std::f64::consts::PI;
-use std::io::{Read, Result, Seek, SeekFrom, self};
-use std::io::Error as IOError;
+use std::io::{Read, Seek, SeekFrom, self};
+use std::ops::Range;
+use std::path::Path;
 use std::time::Instant;
 
 // External crates
 use byteorder::{ByteOrder, ReadBytesExt, LittleEndian};
 use ndarray::{Array1, Array2, ArrayView1, ArrayView2, s, Axis};
-//use plotters::prelude::*;
+use rayon::ThreadPool;
 
 // Local modules
-// use crate::your_module;
+use crate::header::{AmpSettleMode, ChargeRecoveryMode, Channel, FileType, NotchFilterMode, RhsHeader, SignalType, SpikeTrigger, StimParameters, Version};
+use crate::filter::{filter_channels, notch_filter, notch_filter_zero_phase, ButterworthFilter, FilterBand};
+use crate::import_parallel::{read_data_blocks_parallel, thread_pool};
+use crate::stim::{artifact_mask, remove_artifacts, ArtifactMethod};
 
 #[derive(Debug, Clone)]
 pub enum DataType {
     String(String),
     Int(i32),
+    /// Sample counts and indices, which outgrow `i32` in long recordings.
+    Long(i64),
     Float(f32),
     Bool(bool),
     HashMap(HashMap<String, DataType>),
     VecInt(Vec<i32>),
+    /// Block indices, which outgrow `i32` in long recordings.
+    VecLong(Vec<i64>),
     VecChannel(Vec<HashMap<String, DataType>>),
     Array(Arrays),
     None,
 }
 
 #[derive(Debug, Clone)]
+#[allow(clippy::enum_variant_names)]
 pub enum Arrays {
     ArrayOne(Array1<i32>),
     ArrayTwo(Array2<i32>),
     ArrayTwoBool(Array2<bool>),
-}
+    // Unscaled 16-bit samples as stored in the file.
+    ArrayTwoU16(Array2<u16>),
+    // Scaled timestamps in seconds.
+    ArrayOneFloat(Array1<f64>),
+    // Scaled signals in physical units (microVolts, Volts, microAmps, ...).
+    ArrayTwoFloat(Array2<f32>),
+}
+
+// Magic numbers at the start of every RHS and RHD header file.
+const RHS_MAGIC_NUMBER: u32 = 0xd69127ac;
+const RHD_MAGIC_NUMBER: u32 = 0xc6912702;
+
+// Header and data readers fail with an `RhsError`.
+type Result<T> = std::result::Result<T, RhsError>;
+
+/// A position within a recording, counted from the first sample in the file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Position {
+    Sample(u64),
+    Seconds(f64),
+}
+
+/// Options controlling which data `load_file` reads and how it converts it.
+#[derive(Debug, Clone, Default)]
+pub struct LoadOptions {
+    /// Skip scaling and return amplifier, DC amplifier, board ADC/DAC,
+    /// auxiliary input, supply voltage and temperature sensor data as the
+    /// unsigned 16-bit samples stored in the file. The gain, offset and units
+    /// needed to convert each signal (`gain * (raw - offset)`) are returned
+    /// under `scale_factors`. Timestamps are returned as the 32 bits stored
+    /// in the file, so the unsigned timestamps of RHD files before version
+    /// 1.2 (see `RhsHeader::timestamps_signed`) turn negative after 2^31
+    /// samples.
+    pub raw: bool,
+    /// First sample to load. Defaults to the start of the recording.
+    ///
+    /// RHD files sample auxiliary inputs, supply voltage and temperature
+    /// sensors at lower rates within each data block, so for them `start` is
+    /// rounded down and `stop` up to whole data blocks (60 or 128 samples)
+    /// for every signal; check `t` for the samples actually loaded. RHS
+    /// ranges are exact.
+    pub start: Option<Position>,
+    /// Sample to stop loading at (exclusive). Defaults to the end of the
+    /// recording. Rounded up to a whole data block for RHD files, as for
+    /// `start`.
+    pub stop: Option<Position>,
+    /// Channels to load, by native (`A-000`) or custom name. When this or
+    /// `port` is set, only the selected amplifier and board channels are
+    /// allocated and returned.
+    pub channels: Option<Vec<String>>,
+    /// Load every amplifier channel on this port, by prefix (`B`) or name
+    /// (`Port B`).
+    pub port: Option<String>,
+    /// Decode the data blocks of traditional .rhs and .rhd files on this many
+    /// threads (0 for one per core). Blocks are decoded one at a time on the
+    /// calling thread if `None`.
+    pub num_threads: Option<usize>,
+    /// Load traditional .rhs and .rhd files that were cut short, for example
+    /// when the acquisition software crashed or the disk filled up. Bytes after
+    /// the last complete data block are dropped and counted in
+    /// `dropped_bytes`, and blocks whose timestamps break the sequence in `t`
+    /// are loaded as they are and listed in `corrupted_blocks`.
+    pub lenient: bool,
+    /// Mains notch filter applied to scaled amplifier data. Off by default,
+    /// and raw data is never filtered.
+    pub notch: NotchOptions,
+    /// Frequency bands, such as the spike band and the local field potential,
+    /// to derive from the scaled and notch filtered amplifier data. Each is
+    /// returned as its own array of channels by samples, while
+    /// `amplifier_data` is left as it is. Channels are filtered in parallel,
+    /// on `num_threads` threads if set.
+    pub bands: Vec<BandOptions>,
+    /// Stimulation artifact removal applied to scaled amplifier data from
+    /// .rhs files before any filtering. Off by default.
+    pub artifacts: ArtifactOptions,
+    /// Print a summary of the header and the data, progress and warnings
+    /// such as timestamp gaps while loading, as Intan's own readers do. Off
+    /// by default.
+    pub verbose: bool,
+}
+
+/// When `load_file` notch filters amplifier data.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ApplyNotch {
+    /// Never filter.
+    #[default]
+    Skip,
+    /// Filter only if the header says the notch filter was on during
+    /// recording and the file was saved by a version of Intan's software
+    /// before RHX 3.0, which saved the data unfiltered. This is what Intan's
+    /// own readers do.
+    Auto,
+    /// Always filter, whatever the header says.
+    Force,
+}
+
+/// Settings of the mains notch filter applied by `load_file`.
+#[derive(Debug, Clone)]
+pub struct NotchOptions {
+    pub apply: ApplyNotch,
+    /// Mains frequency in Hz. Defaults to the notch filter setting in the
+    /// header, which must then be 50 or 60 Hz.
+    pub frequency: Option<f32>,
+    /// Width of each notch in Hz.
+    pub bandwidth: f32,
+    /// Number of multiples of `frequency` to remove, counting the frequency
+    /// itself; 3 removes 60, 120 and 180 Hz. Multiples at or above the Nyquist
+    /// frequency are left out.
+    pub harmonics: usize,
+    /// Filter forward and then backward (see `notch_filter_zero_phase`), so
+    /// that spike waveforms are not distorted by the filter's phase shift.
+    pub zero_phase: bool,
+}
+
+impl Default for NotchOptions {
+    fn default() -> NotchOptions {
+        NotchOptions { apply: ApplyNotch::Skip, frequency: None, bandwidth: 10.0, harmonics: 1, zero_phase: false }
+    }
+}
+
+impl NotchOptions {
+    /// Frequencies to remove from data recorded with `header`, or none if the
+    /// filter does not apply.
+    pub(crate) fn frequencies(&self, header: &RhsHeader) -> std::result::Result<Vec<f32>, RhsError> {
+        let recorded_frequency = header.frequency_parameters.notch_filter_mode.frequency();
+        match self.apply {
+            ApplyNotch::Skip => return Ok(Vec::new()),
+            // Data saved by RHX 3.0 or later already had any active notch
+            // filter applied, so it should not be re-applied.
+            ApplyNotch::Auto if recorded_frequency.is_none() || header.version_at_least(3, 0) => return Ok(Vec::new()),
+            _ => {},
+        }
+
+        let Some(frequency) = self.frequency.or(recorded_frequency) else {
+            return Err(RhsError::InvalidArgumentError("The notch filter was off during recording, so a notch frequency must be given".to_string()));
+        };
+        if frequency.is_nan() || frequency <= 0.0 || self.bandwidth.is_nan() || self.bandwidth <= 0.0 {
+            return Err(RhsError::InvalidArgumentError(format!("Notch frequency ({} Hz) and bandwidth ({} Hz) must be positive", frequency, self.bandwidth)));
+        }
+        if self.harmonics == 0 {
+            return Err(RhsError::InvalidArgumentError("The notch filter needs at least one harmonic".to_string()));
+        }
 
-enum UnknownChannelTypeError {
-    AuxInputSignals,
-    VddSignals,
-    UnknownChannelType,
-    IoError
+        Ok((1..=self.harmonics)
+            .map(|harmonic| frequency * harmonic as f32)
+            .filter(|&f_notch| f_notch < header.sample_rate / 2.0)
+            .collect())
+    }
 }
 
-impl From<std::io::Error> for UnknownChannelTypeError {
-    fn from(_: std::io::Error) -> Self {
-        UnknownChannelTypeError::IoError
+/// A frequency band of amplifier data derived by `load_file`.
+#[derive(Debug, Clone)]
+pub struct BandOptions {
+    /// Key the filtered data is returned under, such as `spike_band`.
+    pub name: String,
+    pub band: FilterBand,
+    /// Order of the Butterworth filter. A band-pass filter is a high-pass and
+    /// a low-pass filter of this order in series.
+    pub order: usize,
+    /// Filter forward and then backward (see `filtfilt`), for zero phase
+    /// shift.
+    pub zero_phase: bool,
+}
+
+/// Settings of the stimulation artifact removal applied by `load_file`.
+///
+/// The samples treated on each channel are those flagged in `amp_settle_data`
+/// or `charge_recovery_data` or with non-zero `stim_data`, widened by
+/// `pre_ms` and `post_ms`. With `ArtifactMethod::Nan`, the samples are zeroed
+/// before filtering, which would otherwise spread the NaNs through the whole
+/// channel, and set to NaN in `amplifier_data` and every band afterwards.
+#[derive(Debug, Clone)]
+pub struct ArtifactOptions {
+    pub method: ArtifactMethod,
+    /// Time to treat before each flagged stretch, in ms.
+    pub pre_ms: f32,
+    /// Time to treat after each flagged stretch, in ms, covering the
+    /// amplifiers' recovery once they are released.
+    pub post_ms: f32,
+    /// Treat every channel wherever any channel is flagged, since stimulating
+    /// one electrode also disturbs the amplifiers of its neighbours.
+    pub all_channels: bool,
+}
+
+impl Default for ArtifactOptions {
+    fn default() -> ArtifactOptions {
+        ArtifactOptions { method: ArtifactMethod::Keep, pre_ms: 0.0, post_ms: 1.0, all_channels: false }
+    }
+}
+
+impl ArtifactOptions {
+    // Samples to treat before and after each flagged stretch of data
+    // recorded with `header`.
+    fn window_samples(&self, header: &RhsHeader) -> std::result::Result<(usize, usize), RhsError> {
+        if header.stim_parameters.is_none() {
+            return Err(RhsError::InvalidArgumentError("Stimulation artifacts can only be removed from .rhs recordings".to_string()));
+        }
+        let to_samples = |name: &str, ms: f32| {
+            if !ms.is_finite() || ms < 0.0 {
+                return Err(RhsError::InvalidArgumentError(format!("{} must be zero or more milliseconds, not {}", name, ms)));
+            }
+            Ok((ms * header.sample_rate / 1000.0).round() as usize)
+        };
+        Ok((to_samples("Artifact pre-window", self.pre_ms)?, to_samples("Artifact post-window", self.post_ms)?))
     }
 }
 
-impl fmt::Display for UnknownChannelTypeError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            UnknownChannelTypeError::AuxInputSignals => write!(f, "Error: AuxInputSignals"),
-            UnknownChannelTypeError::VddSignals => write!(f, "Error: VddSignals"),
-            UnknownChannelTypeError::UnknownChannelType => write!(f, "Error: UnknownChannelType"),
-            UnknownChannelTypeError::IoError => write!(f, "Error: IoError"),
+impl LoadOptions {
+    /// Check the notch, band filter and artifact settings against the header,
+    /// so that mistakes are reported before any data is read.
+    pub(crate) fn check_filters(&self, header: &RhsHeader) -> std::result::Result<(), RhsError> {
+        self.notch.frequencies(header)?;
+        self.band_filters(header.sample_rate)?;
+        if self.artifacts.method != ArtifactMethod::Keep {
+            self.artifacts.window_samples(header)?;
         }
+        Ok(())
+    }
+
+    // Design the filter of each band for data sampled at `sample_rate`.
+    fn band_filters(&self, sample_rate: f32) -> std::result::Result<Vec<(&BandOptions, ButterworthFilter)>, RhsError> {
+        let mut filters = Vec::new();
+        for (i, band) in self.bands.iter().enumerate() {
+            if band.name.is_empty() || self.bands[..i].iter().any(|other| other.name == band.name) {
+                return Err(RhsError::InvalidArgumentError(format!("Band names must be unique and not empty, not '{}'", band.name)));
+            }
+            filters.push((band, ButterworthFilter::new(band.band, band.order, sample_rate)?));
+        }
+        Ok(filters)
+    }
+
+    /// Resolve `start` and `stop` into the range of samples to load from a
+    /// recording of `num_samples` samples. A `stop` past the end of the
+    /// recording is clipped to it.
+    pub(crate) fn sample_range(&self, num_samples: u64, sample_rate: f32) -> std::result::Result<Range<u64>, RhsError> {
+        let to_sample = |position: Position| match position {
+            Position::Sample(sample) => sample,
+            Position::Seconds(seconds) => (seconds * sample_rate as f64).round().max(0.0) as u64,
+        };
+        let start = self.start.map_or(0, to_sample);
+        let stop = self.stop.map_or(num_samples, to_sample).min(num_samples);
+
+        if start >= stop {
+            return Err(RhsError::InvalidArgumentError(format!("Sample range {}..{} is empty; the recording has {} samples", start, stop, num_samples)));
+        }
+
+        Ok(start..stop)
     }
 }
 
-pub fn load_file(file_path: &str) -> std::result::Result<(HashMap<String, DataType>, bool), Box<dyn std::error::Error>> {
+pub fn load_file(file_path: &str, options: &LoadOptions) -> std::result::Result<(HashMap<String, DataType>, bool), RhsError> {
     // Start timing
     let tic = Instant::now();
 
     //open file
-    let mut fid: File = File::open(file_path).unwrap();
+    let mut fid: File = open_file(file_path)?;
 
 
     // read file header
-    let mut header: HashMap<String, DataType> = read_header(&mut fid)?;
+    let header: RhsHeader = read_header(&mut fid)?;
 
     // Calculate how much data is present and summarize to console
-    let (data_present, filesize, num_blocks, num_samples) = 
-            calculate_data_size(&mut header, &file_path, &mut fid).expect("Error");
+    let data_size = calculate_data_size(&header, file_path, options.lenient, &mut fid)?;
+    let (data_present, num_samples) = (data_size.data_present, data_size.num_samples);
+    if options.verbose {
+        print_header_summary(&header);
+        print_data_size(&header, &data_size);
+    }
 
-    // if .rhd file contains data, read all present data blocks into 'data'
+    // Data blocks are laid out according to the full header, while only
+    // the selected channels are allocated and returned.
+    let mut selected_header = header.clone();
+    let channel_rows = select_channels(&mut selected_header, options)?;
+    options.check_filters(&header)?;
+
+    // if .rhd file contains data, read the requested data blocks into 'data'
     // dict, and verify the amout of data read.
     let mut data: HashMap<String, Arrays> = HashMap::new();
+    let mut corrupted_blocks: Vec<u64> = Vec::new();
     if data_present {
-        data = read_all_data_blocks(&mut header, num_samples, num_blocks, &mut fid)?;
-        //let position = fid.seek(SeekFrom::Current(0))?;
-        check_end_of_file(filesize, &mut fid)?;
+        let sample_range = file_sample_range(&header, options, num_samples)?;
+        data = read_all_data_blocks(&header, &selected_header, channel_rows.as_ref(), &sample_range, options, &mut fid)?;
+        if sample_range.end == num_samples {
+            check_end_of_file(data_size.filesize, &mut fid)?;
+        }
+        if options.lenient {
+            corrupted_blocks = find_corrupted_blocks(&header, &data, sample_range.start)?;
+            if options.verbose && !corrupted_blocks.is_empty() {
+                println!("Warning: {} data block{} with corrupted timestamps found: {:?}", corrupted_blocks.len(), plural(corrupted_blocks.len()), corrupted_blocks);
+            }
+        }
     }
 
     // Save information in 'header' to 'result_out' HashMap
-    let mut result_out: HashMap<String, DataType> = HashMap::new();
-    header_to_result(&header, &mut result_out);
+    let mut result_out: HashMap<String, DataType> = selected_header.to_dict();
+    if options.lenient {
+        result_out.insert("dropped_bytes".to_string(), DataType::Long(data_size.dropped_bytes as i64));
+        result_out.insert("corrupted_blocks".to_string(), DataType::VecLong(corrupted_blocks.into_iter().map(|block| block as i64).collect()));
+    }
 
     // If .rhd file contains data, parse data into readable forms and, if
-    // necessary, apply the same notch filter that was active during recording.
+    // requested, apply the notch filter.
     if data_present {
-        parse_data(&mut header, &mut data);
-        apply_notch_filter(&mut header, &mut data);
-
-        // Save recorded data in 'data' to 'result_out' HashMap.
-        data_to_result(&header, &mut data, &mut result_out);
+        parse_data_to_result(&selected_header, &mut data, options, &mut result_out)?;
     }
     // Otherwise (.rhd file is just a header for One File Per Signal Type or
     // One File Per Channel data formats, in which actual data is saved in
-    // separate .dat files), just return data as an empty HashMap.
-    /* 
-    else {
-        data = HashMap::new();
-    }
-    */
+    // separate .dat files), only the header is returned.
 
     // Report how long read took.
-    println!("Done! Elapsed time: {:.1} seconds", tic.elapsed().as_secs_f64());
+    if options.verbose {
+        println!("Done! Elapsed time: {:.1} seconds", tic.elapsed().as_secs_f64());
+    }
 
     //return the data
     Ok((result_out, data_present))
@@ -119,257 +365,356 @@ pub fn load_file(file_path: &str) -> std::result::Result<(HashMap<String, DataTy
 
 
 
-fn find_channel_in_group(channel_name: &str, signal_group: &Vec<HashMap<String, DataType>>) -> (bool, usize) {
-    for (count, this_channel) in signal_group.iter().enumerate() {
-        if let Some(DataType::String(custom_channel_name)) = this_channel.get("custom_channel_name") {
-            if custom_channel_name == channel_name {
-                return (true, count);
-            }
-        }
-    }
-    (false, 0)
-}
-
-fn find_channel_in_header(channel_name: &str, header: &HashMap<String, DataType>) -> (bool, String, usize) {
-    let mut signal_group_name = String::new();
-    let mut channel_found = false;
-    let mut channel_index = 0;
-
-    let groups = vec![
-        "amplifier_channels",
-        "dc_amplifier_channels",
-        "stim_channels",
-        "amp_settle_channels",
-        "charge_recovery_channels",
-        "compliance_limit_channels",
-        "board_adc_channels",
-        "board_dac_channels",
-        "board_dig_in_channels",
-        "board_dig_out_channels",
+/// Header information and data size of a recording, gathered without reading
+/// any sample data.
+#[derive(Debug, Clone)]
+pub struct HeaderInfo {
+    pub header: RhsHeader,
+    pub data_present: bool,
+    pub num_blocks: u64,
+    /// Number of amplifier samples per channel.
+    pub num_samples: u64,
+    /// Record duration in milliseconds.
+    pub duration_ms: f64,
+}
+
+/// Read the header of a traditional .rhs or .rhd file and work out how much
+/// data follows it from the file size alone. No data blocks are read, so this
+/// is cheap even for very large recordings. With `lenient`, a file that ends
+/// partway through a data block is counted up to its last complete block, as
+/// `LoadOptions::lenient` loads it, instead of raising an error.
+pub fn read_header_info(file_path: &str, lenient: bool) -> std::result::Result<HeaderInfo, RhsError> {
+    let mut fid: File = open_file(file_path)?;
+    let header: RhsHeader = read_header(&mut fid)?;
+    let data_size = calculate_data_size(&header, file_path, lenient, &mut fid)?;
+
+    Ok(header_info(header, data_size.data_present, data_size.num_blocks, data_size.num_samples))
+}
+
+pub(crate) fn header_info(header: RhsHeader, data_present: bool, num_blocks: u64, num_samples: u64) -> HeaderInfo {
+    let duration_ms = num_samples as f64 / header.sample_rate as f64 * 1000.0;
+    HeaderInfo {
+        header,
+        data_present,
+        num_blocks,
+        num_samples,
+        duration_ms,
+    }
+}
+
+fn find_channel_in_group(channel_name: &str, signal_group: &[Channel]) -> Option<usize> {
+    signal_group.iter().position(|channel| channel.native_channel_name == channel_name || channel.custom_channel_name == channel_name)
+}
+
+fn find_channel_in_header(channel_name: &str, header: &RhsHeader) -> Option<(SignalType, usize)> {
+    let signal_types = [
+        SignalType::Amplifier,
+        SignalType::BoardAdc,
+        SignalType::BoardDac,
+        SignalType::BoardDigIn,
+        SignalType::BoardDigOut,
     ];
 
-    for group in groups {
-        if let Some(DataType::VecChannel(signal_group)) = header.get(group) {
-            let (found, index) = find_channel_in_group(channel_name, signal_group);
-            if found {
-                channel_found = true;
-                channel_index = index;
-                signal_group_name = group.to_string();
-                break;
+    signal_types.into_iter().find_map(|signal_type| {
+        find_channel_in_group(channel_name, header.channels(signal_type)).map(|index| (signal_type, index))
+    })
+}
+
+/// Rows of each signal array to keep when only some channels are loaded,
+/// keyed by data key. Signals missing from the map keep every row.
+pub(crate) type ChannelRows = HashMap<String, Vec<usize>>;
+
+// Channel groups that can be narrowed down, with the data arrays holding one
+// row per channel in each group. Digital channels share a single word per
+// sample, so only their channel lists are narrowed.
+const SELECTABLE_GROUPS: [(SignalType, &[&str]); 5] = [
+    (SignalType::Amplifier, &["amplifier_data", "dc_amplifier_data", "stim_data_raw"]),
+    (SignalType::BoardAdc, &["board_adc_data"]),
+    (SignalType::BoardDac, &["board_dac_data"]),
+    (SignalType::BoardDigIn, &[]),
+    (SignalType::BoardDigOut, &[]),
+];
+
+/// Restrict the channel lists in `header` to the channels picked by
+/// `options.channels` and `options.port`, and return the rows those channels
+/// occupy in the file's signal arrays. Returns `None` if no selection was
+/// requested, leaving `header` untouched.
+pub(crate) fn select_channels(header: &mut RhsHeader, options: &LoadOptions) -> std::result::Result<Option<ChannelRows>, RhsError> {
+    if options.channels.is_none() && options.port.is_none() {
+        return Ok(None);
+    }
+
+    let mut selected: HashMap<SignalType, BTreeSet<usize>> = HashMap::new();
+    for channel_name in options.channels.iter().flatten() {
+        let (signal_type, index) = find_channel_in_header(channel_name, header)
+            .ok_or_else(|| RhsError::ChannelNotFoundError { channel: channel_name.clone() })?;
+        selected.entry(signal_type).or_default().insert(index);
+    }
+
+    if let Some(port) = &options.port {
+        let mut port_found = false;
+        for (index, channel) in header.amplifier_channels.iter().enumerate() {
+            if channel.port_prefix == *port || channel.port_name == *port {
+                selected.entry(SignalType::Amplifier).or_default().insert(index);
+                port_found = true;
             }
         }
+        if !port_found {
+            return Err(RhsError::PortNotFoundError { port: port.clone() });
+        }
     }
 
-    if channel_found {
-        return (true, signal_group_name, channel_index);
+    let mut channel_rows = ChannelRows::new();
+    for (signal_type, data_keys) in SELECTABLE_GROUPS {
+        let rows: Vec<usize> = selected.get(&signal_type).map(|indices| indices.iter().copied().collect()).unwrap_or_default();
+
+        let channels = header.channels_mut(signal_type);
+        *channels = rows.iter().filter_map(|&row| channels.get(row).cloned()).collect();
+
+        // Spike triggers are stored in the same order as the amplifier
+        // channels, so they are narrowed down together.
+        if signal_type == SignalType::Amplifier {
+            header.spike_triggers = rows.iter().filter_map(|&row| header.spike_triggers.get(row).cloned()).collect();
+        }
+
+        for data_key in data_keys {
+            channel_rows.insert(data_key.to_string(), rows.clone());
+        }
     }
 
-    (false, signal_group_name, channel_index)
+    Ok(Some(channel_rows))
+}
+
+fn rows_for<'a>(channel_rows: Option<&'a ChannelRows>, data_key: &str) -> Option<&'a [usize]> {
+    channel_rows.and_then(|channel_rows| channel_rows.get(data_key)).map(Vec::as_slice)
 }
 
-fn read_header(fid: &mut File) -> std::result::Result<HashMap<String, DataType>, std::io::Error> {
+/// Parse the header of a traditional .rhs or .rhd file, leaving `fid` at the
+/// first data block.
+pub fn read_header(fid: &mut File) -> Result<RhsHeader> {
     
-    let mut header: HashMap<String, DataType> = HashMap::new();
+    let mut header = RhsHeader::default();
 
-    check_magic_number(fid)?;
+    read_step(fid, "reading the magic number", |fid| check_magic_number(fid, &mut header))?;
     
-    read_version_number(fid, &mut header)?;
-    set_num_samples_per_data_block(&mut header);
+    read_step(fid, "reading the version number", |fid| read_version_number(fid, &mut header))?;
 
-    read_sample_rate(fid, &mut header)?;
-    read_freq_settings(fid, &mut header)?;
+    read_step(fid, "reading the sample rate", |fid| read_sample_rate(fid, &mut header))?;
+    if header.is_rhd() {
+        read_step(fid, "reading frequency settings", |fid| read_rhd_freq_settings(fid, &mut header))?;
+    } else {
+        read_step(fid, "reading frequency settings", |fid| read_freq_settings(fid, &mut header))?;
+    }
+
+    read_step(fid, "reading the notch filter mode", |fid| read_notch_filter_mode(fid, &mut header))?;
+    read_step(fid, "reading impedance test frequencies", |fid| read_impedance_test_frequencies(fid, &mut header))?;
 
-    read_notch_filter_frequency(fid, &mut header)?;
-    read_impedance_test_frequencies(fid, &mut header)?;
-    read_amp_settle_mode(fid, &mut header)?;
-    read_charge_recovery_mode(fid, &mut header)?;
+    if header.is_rhd() {
+        read_step(fid, "reading notes", |fid| read_notes(fid, &mut header))?;
+        read_step(fid, "reading the number of temperature sensors", |fid| read_num_temp_sensor_channels(fid, &mut header))?;
+        read_step(fid, "reading the board mode", |fid| read_rhd_eval_board_mode(fid, &mut header))?;
+        read_step(fid, "reading the reference channel", |fid| read_rhd_reference_channel(fid, &mut header))?;
 
-    create_frequency_parameters(&mut header)?;
+        create_rhd_frequency_parameters(&mut header);
+    } else {
+        let mut stim_parameters = StimParameters::default();
+        read_step(fid, "reading the amp settle mode", |fid| read_amp_settle_mode(fid, &mut stim_parameters))?;
+        read_step(fid, "reading the charge recovery mode", |fid| read_charge_recovery_mode(fid, &mut stim_parameters))?;
 
-    read_stim_step_size(fid, &mut header)?;
-    read_recovery_current_limit(fid, &mut header)?;
-    read_recovery_target_voltage(fid, &mut header)?;
+        create_frequency_parameters(&mut header);
 
-    read_notes(fid, &mut header)?;
-    read_dc_amp_saved(fid, &mut header)?;
-    read_eval_board_mode(fid, &mut header)?;
-    read_reference_channel(fid, &mut header)?;
+        read_step(fid, "reading stimulation parameters", |fid| {
+            read_stim_step_size(fid, &mut stim_parameters)?;
+            read_recovery_current_limit(fid, &mut stim_parameters)?;
+            read_recovery_target_voltage(fid, &mut stim_parameters)
+        })?;
+        header.stim_parameters = Some(stim_parameters);
 
+        read_step(fid, "reading notes", |fid| read_notes(fid, &mut header))?;
+        read_step(fid, "reading the DC amplifier flag", |fid| read_dc_amp_saved(fid, &mut header))?;
+        read_step(fid, "reading the board mode", |fid| read_eval_board_mode(fid, &mut header))?;
+        read_step(fid, "reading the reference channel", |fid| read_reference_channel(fid, &mut header))?;
+    }
 
-    initialize_channels(&mut header)?;
-    read_signal_summary(fid, &mut header)?; 
+    read_step(fid, "reading the signal summary", |fid| read_signal_summary(fid, &mut header))?; 
 
     Ok(header)
 }
 
-fn check_magic_number(fid: &mut File) -> Result<()> {
+// Run one step of reading the header. If it fails without saying where, the
+// error records what was being read and the offset the step started at.
+fn read_step<T>(fid: &mut File, context: &str, step: impl FnOnce(&mut File) -> Result<T>) -> Result<T> {
+    let offset = fid.stream_position()?;
+    step(fid).map_err(|e| e.at(context, Some(offset)))
+}
+
+/// Open `path` for reading, naming the file if that fails.
+pub(crate) fn open_file(path: impl AsRef<Path>) -> Result<File> {
+    let path = path.as_ref();
+    File::open(path).map_err(|e| RhsError::from(e).at(format!("opening {}", path.display()), None))
+}
+
+fn check_magic_number(fid: &mut File, header: &mut RhsHeader) -> Result<()> {
     let magic_number: u32 = fid.read_u32::<LittleEndian>()?;
-    if magic_number != 0xd69127ac {
-        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Unrecognized file type."));
-    }
+    header.file_type = match magic_number {
+        RHS_MAGIC_NUMBER => FileType::Rhs,
+        RHD_MAGIC_NUMBER => FileType::Rhd,
+        _ => return Err(RhsError::UnrecognizedFileError { magic_number }),
+    };
     Ok(())
 }
 
-fn read_version_number(fid: &mut File, header: &mut HashMap<String, DataType>) -> std::io::Result<()> {
+fn read_version_number(fid: &mut File, header: &mut RhsHeader) -> Result<()> {
     let mut version_bytes = [0; 4];
     fid.read_exact(&mut version_bytes)?;
 
     let major = i16::from_le_bytes([version_bytes[0], version_bytes[1]]);
     let minor = i16::from_le_bytes([version_bytes[2], version_bytes[3]]);
 
-    let mut version = HashMap::new();
-    version.insert("major".to_string(), DataType::Int(major as i32));
-    version.insert("minor".to_string(), DataType::Int(minor as i32));
-
-    header.insert("version".to_string(), DataType::HashMap(version));
-
-    println!("\nReading Intan Technologies RHS Data File, Version {}.{}\n", major, minor);
+    header.version = Version { major, minor };
 
     Ok(())
 }
 
-fn set_num_samples_per_data_block(header: &mut HashMap<String, DataType>) -> () {
-    header.insert("num_samples_per_data_block".to_string(), DataType::Int(128));
+fn read_sample_rate(fid: &mut File, header: &mut RhsHeader) -> Result<()> {
+    header.sample_rate = fid.read_f32::<LittleEndian>()?;
+    Ok(())
 }
 
-fn read_sample_rate(fid: &mut File, header: &mut HashMap<String, DataType>) -> Result<()> {
-    header.insert("sample_rate".to_string(), DataType::Float(fid.read_f32::<LittleEndian>()?));
+fn read_freq_settings(fid: &mut File, header: &mut RhsHeader) -> Result<()> {
+    let freq = &mut header.frequency_parameters;
+    freq.dsp_enabled = fid.read_i16::<LittleEndian>()? != 0;
+    freq.actual_dsp_cutoff_frequency = fid.read_f32::<LittleEndian>()?;
+    freq.actual_lower_bandwidth = fid.read_f32::<LittleEndian>()?;
+    freq.actual_lower_settle_bandwidth = Some(fid.read_f32::<LittleEndian>()?);
+    freq.actual_upper_bandwidth = fid.read_f32::<LittleEndian>()?;
+    freq.desired_dsp_cutoff_frequency = fid.read_f32::<LittleEndian>()?;
+    freq.desired_lower_bandwidth = fid.read_f32::<LittleEndian>()?;
+    freq.desired_lower_settle_bandwidth = Some(fid.read_f32::<LittleEndian>()?);
+    freq.desired_upper_bandwidth = fid.read_f32::<LittleEndian>()?;
     Ok(())
 }
 
-fn read_freq_settings(fid: &mut File, header: &mut HashMap<String, DataType>) -> Result<()> {
-    header.insert("dsp_enabled".to_string(), DataType::Int(fid.read_i16::<LittleEndian>()? as i32));
-    header.insert("actual_dsp_cutoff_frequency".to_string(), DataType::Float(fid.read_f32::<LittleEndian>()?));
-    header.insert( "actual_lower_bandwidth".to_string(), DataType::Float(fid.read_f32::<LittleEndian>()?));
-    header.insert("actual_lower_settle_bandwidth".to_string(), DataType::Float(fid.read_f32::<LittleEndian>()?));
-    header.insert("actual_upper_bandwidth".to_string(), DataType::Float(fid.read_f32::<LittleEndian>()?));
-    header.insert( "desired_dsp_cutoff_frequency".to_string(), DataType::Float(fid.read_f32::<LittleEndian>()?));
-    header.insert("desired_lower_bandwidth".to_string(), DataType::Float(fid.read_f32::<LittleEndian>()?));
-    header.insert("desired_lower_settle_bandwidth".to_string(),DataType::Float(fid.read_f32::<LittleEndian>()?));
-    header.insert("desired_upper_bandwidth".to_string(), DataType::Float(fid.read_f32::<LittleEndian>()?));
+fn read_rhd_freq_settings(fid: &mut File, header: &mut RhsHeader) -> Result<()> {
+    let freq = &mut header.frequency_parameters;
+    freq.dsp_enabled = fid.read_i16::<LittleEndian>()? != 0;
+    freq.actual_dsp_cutoff_frequency = fid.read_f32::<LittleEndian>()?;
+    freq.actual_lower_bandwidth = fid.read_f32::<LittleEndian>()?;
+    freq.actual_upper_bandwidth = fid.read_f32::<LittleEndian>()?;
+    freq.desired_dsp_cutoff_frequency = fid.read_f32::<LittleEndian>()?;
+    freq.desired_lower_bandwidth = fid.read_f32::<LittleEndian>()?;
+    freq.desired_upper_bandwidth = fid.read_f32::<LittleEndian>()?;
     Ok(())
 }
 
-fn read_notch_filter_frequency(fid: &mut File, header: &mut HashMap<String, DataType>) -> Result<()> {
-    let notch_filter_mode: i32 = fid.read_i16::<LittleEndian>()? as i32;
-    //file_data.insert("notch_filter_mode".to_string(), DataType::Int(notch_filter_mode));
-    
-    match notch_filter_mode {
-        1 => header.insert("notch_filter_frequency".to_string(), DataType::Int(50)),
-        2 => header.insert("notch_filter_frequency".to_string(), DataType::Int(60)),
-        _ => header.insert("notch_filter_frequency".to_string(), DataType::None),
-    };
+fn read_notch_filter_mode(fid: &mut File, header: &mut RhsHeader) -> Result<()> {
+    header.frequency_parameters.notch_filter_mode = NotchFilterMode::from_code(fid.read_i16::<LittleEndian>()?);
     Ok(())
 }
 
-fn read_impedance_test_frequencies(fid: &mut File, header: &mut HashMap<String, DataType>) -> Result<()> {
-    header.insert("desired_impedance_test_frequency".to_string(), DataType::Float(fid.read_f32::<LittleEndian>()?));
-    header.insert("actual_impedance_test_frequency".to_string(), DataType::Float(fid.read_f32::<LittleEndian>()?));
+fn read_impedance_test_frequencies(fid: &mut File, header: &mut RhsHeader) -> Result<()> {
+    header.frequency_parameters.desired_impedance_test_frequency = fid.read_f32::<LittleEndian>()?;
+    header.frequency_parameters.actual_impedance_test_frequency = fid.read_f32::<LittleEndian>()?;
     Ok(())
 }
 
-fn read_amp_settle_mode(fid: &mut File, header: &mut HashMap<String, DataType>) -> Result<()> {
-    header.insert("amp_settle_mode".to_string(), DataType::Int(fid.read_i16::<LittleEndian>()? as i32));
+fn read_amp_settle_mode(fid: &mut File, stim_parameters: &mut StimParameters) -> Result<()> {
+    let code = fid.read_i16::<LittleEndian>()?;
+    stim_parameters.amp_settle_mode = AmpSettleMode::from_code(code).ok_or_else(|| RhsError::header_field("amp_settle_mode", code))?;
     Ok(())
 }
 
-fn read_charge_recovery_mode(fid: &mut File, header: &mut HashMap<String, DataType>) -> Result<()> {
-    header.insert("charge_recovery_mode".to_string(), DataType::Int(fid.read_i16::<LittleEndian>()? as i32));
+fn read_charge_recovery_mode(fid: &mut File, stim_parameters: &mut StimParameters) -> Result<()> {
+    let code = fid.read_i16::<LittleEndian>()?;
+    stim_parameters.charge_recovery_mode = ChargeRecoveryMode::from_code(code).ok_or_else(|| RhsError::header_field("charge_recovery_mode", code))?;
     Ok(())
 }
 
 
-fn create_frequency_parameters(header: &mut HashMap<String, DataType>) -> Result<()> {
-    let mut freq: HashMap<String, DataType> = HashMap::new();
-    freq.insert("amplifier_sample_rate".to_string(), header.get("sample_rate").unwrap().clone());
-    freq.insert("board_adc_sample_rate".to_string(), header.get("sample_rate").unwrap().clone());
-    freq.insert("board_dig_in_sample_rate".to_string(), header.get("sample_rate").unwrap().clone());
-    
-    let keys: Vec<&str> = vec![
-        "desired_dsp_cutoff_frequency",
-        "actual_dsp_cutoff_frequency",
-        "dsp_enabled",
-        "desired_lower_bandwidth",
-        "desired_lower_settle_bandwidth",
-        "actual_lower_bandwidth",
-        "actual_lower_settle_bandwidth",
-        "desired_upper_bandwidth",
-        "actual_upper_bandwidth",
-        "notch_filter_frequency",
-        "desired_impedance_test_frequency",
-        "actual_impedance_test_frequency",
-    ];
+fn create_frequency_parameters(header: &mut RhsHeader) {
+    let sample_rate = header.sample_rate;
+    let freq = &mut header.frequency_parameters;
+    freq.amplifier_sample_rate = sample_rate;
+    freq.board_adc_sample_rate = sample_rate;
+    freq.board_dig_in_sample_rate = sample_rate;
+}
 
-    copy_from_header(header, &mut freq, keys)?;
+fn create_rhd_frequency_parameters(header: &mut RhsHeader) {
+    create_frequency_parameters(header);
 
-    header.insert("frequency_parameters".to_string(), DataType::HashMap(freq));
-    Ok(())
+    // Auxiliary inputs are sampled once every 4 amplifier samples, supply
+    // voltage and temperature sensors once per data block.
+    let sample_rate = header.sample_rate;
+    let num_samples_per_data_block = header.num_samples_per_data_block();
+    let freq = &mut header.frequency_parameters;
+    freq.aux_input_sample_rate = Some(sample_rate / 4.0);
+    freq.supply_voltage_sample_rate = Some(sample_rate / num_samples_per_data_block as f32);
 }
 
-fn copy_from_header(header: &mut HashMap<String, DataType>, freq: &mut HashMap<String, DataType>, keys: Vec<&str>) -> Result<()> {
-        for key in keys {
-            freq.insert(key.to_string(), header.get(key).unwrap().clone());
-        }
+fn read_stim_step_size(fid: &mut File, stim_parameters: &mut StimParameters) -> Result<()> {
+    stim_parameters.stim_step_size = fid.read_f32::<LittleEndian>()?;
     Ok(())
 }
 
-fn read_stim_step_size(fid: &mut File, header: &mut HashMap<String, DataType>) -> Result<()> {
-    header.insert("stim_step_size".to_string(), DataType::Float(fid.read_f32::<LittleEndian>()?));
+fn read_recovery_current_limit(fid: &mut File, stim_parameters: &mut StimParameters) -> Result<()> {
+    stim_parameters.charge_recovery_current_limit = fid.read_f32::<LittleEndian>()?;
     Ok(())
 }
 
-fn read_recovery_current_limit(fid: &mut File, header: &mut HashMap<String, DataType>) -> Result<()> {
-    header.insert("recovery_current_limit".to_string(), DataType::Float(fid.read_f32::<LittleEndian>()?));
+fn read_recovery_target_voltage(fid: &mut File, stim_parameters: &mut StimParameters) -> Result<()> {
+    stim_parameters.charge_recovery_target_voltage = fid.read_f32::<LittleEndian>()?;
     Ok(())
 }
 
-fn read_recovery_target_voltage(fid: &mut File, header: &mut HashMap<String, DataType>) -> Result<()> {
-    header.insert("recovery_target_voltage".to_string(), DataType::Float(fid.read_f32::<LittleEndian>()?));
+fn read_notes(fid: &mut File, header: &mut RhsHeader) -> Result<()> {
+    header.notes = [read_qstring(fid)?, read_qstring(fid)?, read_qstring(fid)?];
     Ok(())
 }
 
-fn read_notes(fid: &mut File, header: &mut HashMap<String, DataType>) -> Result<()> {
-    
-    let mut notes: HashMap<String, DataType> = HashMap::new();
-
-    notes.insert("note1".to_string(), DataType::String(read_qstring(fid)?));
-    notes.insert("note2".to_string(), DataType::String(read_qstring(fid)?));
-    notes.insert("note3".to_string(), DataType::String(read_qstring(fid)?));
 
-    header.insert("notes".to_string(), DataType::HashMap(notes));
-    
+fn read_dc_amp_saved(fid: &mut File, header: &mut RhsHeader) -> Result<()> {
+    header.dc_amplifier_data_saved = fid.read_i16::<LittleEndian>()? != 0;
     Ok(())
 }
 
+fn read_eval_board_mode(fid: &mut File, header: &mut RhsHeader) -> Result<()> {
+    header.eval_board_mode = fid.read_i16::<LittleEndian>()? as i32;
 
-fn read_dc_amp_saved(fid: &mut File, header: &mut HashMap<String, DataType>) -> Result<()> {
-    header.insert("dc_amplifier_data_saved".to_string(), DataType::Int(fid.read_i16::<LittleEndian>()? as i32));
     Ok(())
 }
 
-fn read_eval_board_mode(fid: &mut File, header: &mut HashMap<String, DataType>) -> Result<()> {
-    header.insert("eval_board_mode".to_string(), DataType::Int(fid.read_i16::<LittleEndian>()? as i32));
-
+fn read_reference_channel(fid: &mut File, header: &mut RhsHeader) -> Result<()> {
+    header.reference_channel = read_qstring(fid)?;
     Ok(())
 }
 
-fn read_reference_channel(fid: &mut File, header: &mut HashMap<String, DataType>) -> Result<()> {
-    header.insert("reference_channel".to_string(), DataType::String(read_qstring(fid)?));
+fn read_num_temp_sensor_channels(fid: &mut File, header: &mut RhsHeader) -> Result<()> {
+    // Temperature sensor channels were added to the RHD header in version 1.1.
+    if header.version_at_least(1, 1) {
+        header.num_temp_sensor_channels = fid.read_i16::<LittleEndian>()?.max(0) as usize;
+    }
     Ok(())
 }
 
-fn initialize_channels(header: &mut HashMap<String, DataType>) -> Result<()> {
-    header.insert("spike_triggers".to_string(), DataType::VecChannel(Vec::new()));
-    header.insert("amplifier_channels".to_string(), DataType::VecChannel(Vec::new()));
-    header.insert("board_adc_channels".to_string(), DataType::VecChannel(Vec::new()));
-    header.insert("board_dac_channels".to_string(), DataType::VecChannel(Vec::new()));
-    header.insert("board_dig_in_channels".to_string(), DataType::VecChannel(Vec::new()));
-    header.insert("board_dig_out_channels".to_string(), DataType::VecChannel(Vec::new()));
+fn read_rhd_eval_board_mode(fid: &mut File, header: &mut RhsHeader) -> Result<()> {
+    // Board mode was added to the RHD header in version 1.3; older files
+    // keep the default of 0.
+    if header.version_at_least(1, 3) {
+        read_eval_board_mode(fid, header)?;
+    }
     Ok(())
+}
 
+fn read_rhd_reference_channel(fid: &mut File, header: &mut RhsHeader) -> Result<()> {
+    // Reference channel was added to the RHD header in version 2.0; older
+    // files keep an empty name.
+    if header.version_at_least(2, 0) {
+        read_reference_channel(fid, header)?;
+    }
+    Ok(())
 }
 
-fn read_signal_summary(fid: &mut File, header: &mut HashMap<String, DataType>) -> Result<()> {
+fn read_signal_summary(fid: &mut File, header: &mut RhsHeader) -> Result<()> {
     let mut buffer = [0; 2];
     fid.read_exact(&mut buffer)?;
     let number_of_signal_groups: i16 = i16::from_le_bytes(buffer);
@@ -377,13 +722,11 @@ fn read_signal_summary(fid: &mut File, header: &mut HashMap<String, DataType>) -
     for signal_group in 1..=number_of_signal_groups {
         add_signal_group_information(header, fid, signal_group)?;
     }
-    add_num_channels(header);
-    print_header_summary(header);
 
     Ok(())
 }
 
-fn add_signal_group_information(header: &mut HashMap<String, DataType>, fid: &mut File, signal_group:i16) -> Result<()> {
+fn add_signal_group_information(header: &mut RhsHeader, fid: &mut File, signal_group:i16) -> Result<()> {
     let signal_group_name: String = read_qstring(fid)?;
     let signal_group_prefix: String = read_qstring(fid)?;
 
@@ -401,39 +744,39 @@ fn add_signal_group_information(header: &mut HashMap<String, DataType>, fid: &mu
     Ok(())
 }
 
-fn add_channel_information(header: &mut HashMap<String, DataType>, fid: &mut File, signal_group_name: &str, signal_group_prefix: &str, signal_group: i16) -> std::result::Result<(), std::io::Error> {
-    let (mut new_channel, mut new_trigger_channel, channel_enabled, signal_type) = read_new_channel(fid, signal_group_name, signal_group_prefix, signal_group)?;
-    append_new_channel(header, &mut new_channel, &mut new_trigger_channel, channel_enabled, signal_type)
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))
+fn add_channel_information(header: &mut RhsHeader, fid: &mut File, signal_group_name: &str, signal_group_prefix: &str, signal_group: i16) -> Result<()> {
+    if let Some((new_channel, new_trigger_channel)) = read_new_channel(fid, signal_group_name, signal_group_prefix, signal_group, header.file_type)? {
+        append_new_channel(header, new_channel, new_trigger_channel);
+    }
+    Ok(())
 }
 
-fn read_new_channel(fid: &mut File, signal_group_name: &str, signal_group_prefix: &str, signal_group: i16) -> Result<(HashMap<String, DataType>, HashMap<String, DataType>, i16, i16)> {
-    let mut new_channel = HashMap::new();
-    new_channel.insert("port_name".to_string(), DataType::String(signal_group_name.to_string()));
-    new_channel.insert("port_prefix".to_string(), DataType::String(signal_group_prefix.to_string()));
-    new_channel.insert("port_number".to_string(), DataType::Int(signal_group as i32));
+type NewChannel = (Channel, SpikeTrigger);
 
-    new_channel.insert("native_channel_name".to_string(), DataType::String(read_qstring(fid)?));
-    new_channel.insert("custom_channel_name".to_string(), DataType::String(read_qstring(fid)?));
+// Read one channel of a signal group. Disabled channels have no data in the
+// file and are skipped, returning `None`.
+fn read_new_channel(fid: &mut File, signal_group_name: &str, signal_group_prefix: &str, signal_group: i16, file_type: FileType) -> Result<Option<NewChannel>> {
+    let offset = fid.stream_position()?;
+    let native_channel_name = read_qstring(fid)?;
+    let custom_channel_name = read_qstring(fid)?;
 
-    let mut buffer = [0; 14];
+    let mut buffer = [0; 10];
     fid.read_exact(&mut buffer)?;
-    let (native_order, custom_order, signal_type, channel_enabled, chip_channel, _, board_stream) = (
+    let (native_order, custom_order, signal_type, channel_enabled, chip_channel) = (
         i16::from_le_bytes([buffer[0], buffer[1]]),
         i16::from_le_bytes([buffer[2], buffer[3]]),
         i16::from_le_bytes([buffer[4], buffer[5]]),
         i16::from_le_bytes([buffer[6], buffer[7]]),
         i16::from_le_bytes([buffer[8], buffer[9]]),
-        i16::from_le_bytes([buffer[10], buffer[11]]),
-        i16::from_le_bytes([buffer[12], buffer[13]]),
     );
 
-    new_channel.insert("native_order".to_string(), DataType::Int(native_order as i32));
-    new_channel.insert("custom_order".to_string(), DataType::Int(custom_order as i32));
-    new_channel.insert("chip_channel".to_string(), DataType::Int(chip_channel as i32));
-    new_channel.insert("board_stream".to_string(), DataType::Int(board_stream as i32));
+    // RHS channels carry a command stream before the board stream; RHD
+    // channels only have the board stream.
+    if file_type == FileType::Rhs {
+        let _command_stream = fid.read_i16::<LittleEndian>()?;
+    }
+    let board_stream = fid.read_i16::<LittleEndian>()?;
 
-    let mut new_trigger_channel = HashMap::new();
     let mut buffer = [0; 8];
     fid.read_exact(&mut buffer)?;
     let (voltage_trigger_mode, voltage_threshold, digital_trigger_channel, digital_edge_polarity) = (
@@ -443,10 +786,12 @@ fn read_new_channel(fid: &mut File, signal_group_name: &str, signal_group_prefix
         i16::from_le_bytes([buffer[6], buffer[7]]),
     );
 
-    new_trigger_channel.insert("voltage_trigger_mode".to_string(), DataType::Int(voltage_trigger_mode as i32));
-    new_trigger_channel.insert("voltage_threshold".to_string(), DataType::Int(voltage_threshold as i32));
-    new_trigger_channel.insert("digital_trigger_channel".to_string(), DataType::Int(digital_trigger_channel as i32));
-    new_trigger_channel.insert("digital_edge_polarity".to_string(), DataType::Int(digital_edge_polarity as i32));
+    let new_trigger_channel = SpikeTrigger {
+        voltage_trigger_mode: voltage_trigger_mode as i32,
+        voltage_threshold: voltage_threshold as i32,
+        digital_trigger_channel: digital_trigger_channel as i32,
+        digital_edge_polarity: digital_edge_polarity as i32,
+    };
 
     let mut buffer = [0; 8];
     fid.read_exact(&mut buffer)?;
@@ -455,166 +800,83 @@ fn read_new_channel(fid: &mut File, signal_group_name: &str, signal_group_prefix
         f32::from_le_bytes([buffer[4], buffer[5], buffer[6], buffer[7]]),
     );
 
-    new_channel.insert("electrode_impedance_magnitude".to_string(), DataType::Float(electrode_impedance_magnitude));
-    new_channel.insert("electrode_impedance_phase".to_string(), DataType::Float(electrode_impedance_phase));
-
-    Ok((new_channel, new_trigger_channel, channel_enabled, signal_type))
-}
-
-
-fn append_new_channel(header: &mut HashMap<String, DataType>, new_channel: &mut HashMap<String, DataType>, new_trigger_channel: &mut HashMap<String, DataType>, channel_enabled: i16, signal_type: i16) -> std::result::Result<(), UnknownChannelTypeError> {
     if channel_enabled == 0 {
-        return Ok(());
-    }
-
-    match signal_type {
-        0 => {
-            if let DataType::VecChannel(ref mut vec) = header.get_mut("amplifier_channels").unwrap() {
-                vec.push(new_channel.clone());
-            }
-            if let DataType::VecChannel(ref mut vec) = header.get_mut("spike_triggers").unwrap() {
-                vec.push(new_trigger_channel.clone());
-            }
-        },
-        1 => return Err(UnknownChannelTypeError::AuxInputSignals),
-        2 => return Err(UnknownChannelTypeError::VddSignals),
-        3 => {
-            if let DataType::VecChannel(ref mut vec) = header.get_mut("board_adc_channels").unwrap() {
-                vec.push(new_channel.clone());
-            }
-        },
-        4 => {
-            if let DataType::VecChannel(ref mut vec) = header.get_mut("board_dac_channels").unwrap() {
-                vec.push(new_channel.clone());
-            }
-        },
-        5 => {
-            if let DataType::VecChannel(ref mut vec) = header.get_mut("board_dig_in_channels").unwrap() {
-                vec.push(new_channel.clone());
-            }
-        },
-        6 => {
-            if let DataType::VecChannel(ref mut vec) = header.get_mut("board_dig_out_channels").unwrap() {
-                vec.push(new_channel.clone());
-            }
-        },
-        _ => return Err(UnknownChannelTypeError::UnknownChannelType),
-    }
+        return Ok(None);
+    }
+
+    // Auxiliary input and supply voltage channels only exist in RHD files.
+    let signal_type = SignalType::from_code(file_type, signal_type)
+        .ok_or(RhsError::UnknownChannelTypeError { offset, signal_type })?;
+
+    let new_channel = Channel {
+        signal_type,
+        port_name: signal_group_name.to_string(),
+        port_prefix: signal_group_prefix.to_string(),
+        port_number: signal_group as i32,
+        native_channel_name,
+        custom_channel_name,
+        native_order: native_order as i32,
+        custom_order: custom_order as i32,
+        chip_channel: chip_channel as i32,
+        board_stream: board_stream as i32,
+        electrode_impedance_magnitude,
+        electrode_impedance_phase,
+    };
 
-    Ok(())
+    Ok(Some((new_channel, new_trigger_channel)))
 }
 
 
-fn add_num_channels(header: &mut HashMap<String, DataType>) {
-    if let DataType::VecChannel(ref vec) = header.get("amplifier_channels").unwrap() {
-        header.insert("num_amplifier_channels".to_string(), DataType::Int(vec.len() as i32));
-    }
-    if let DataType::VecChannel(ref vec) = header.get("board_adc_channels").unwrap() {
-        header.insert("num_board_adc_channels".to_string(), DataType::Int(vec.len() as i32));
-    }
-    if let DataType::VecChannel(ref vec) = header.get("board_dac_channels").unwrap() {
-        header.insert("num_board_dac_channels".to_string(), DataType::Int(vec.len() as i32));
-    }
-    if let DataType::VecChannel(ref vec) = header.get("board_dig_in_channels").unwrap() {
-        header.insert("num_board_dig_in_channels".to_string(), DataType::Int(vec.len() as i32));
-    }
-    if let DataType::VecChannel(ref vec) = header.get("board_dig_out_channels").unwrap() {
-        header.insert("num_board_dig_out_channels".to_string(), DataType::Int(vec.len() as i32));
+fn append_new_channel(header: &mut RhsHeader, new_channel: Channel, new_trigger_channel: SpikeTrigger) {
+    if new_channel.signal_type == SignalType::Amplifier {
+        header.spike_triggers.push(new_trigger_channel);
     }
+    header.channels_mut(new_channel.signal_type).push(new_channel);
 }
 
 
-fn header_to_result(header: &HashMap<String, DataType>, result_out: &mut HashMap<String, DataType>) {
-    let mut stim_parameters = HashMap::new();
-    stim_parameters.insert("stim_step_size".to_string(), header["stim_step_size"].clone());
-    stim_parameters.insert("charge_recovery_current_limit".to_string(), header["recovery_current_limit"].clone());
-    stim_parameters.insert("charge_recovery_target_voltage".to_string(), header["recovery_target_voltage"].clone());
-    stim_parameters.insert("amp_settle_mode".to_string(), header["amp_settle_mode"].clone());
-    stim_parameters.insert("charge_recovery_mode".to_string(), header["charge_recovery_mode"].clone());
-    result_out.insert("stim_parameters".to_string(), DataType::HashMap(stim_parameters));
-    
-    result_out.insert("notes".to_string(), header["notes"].clone());
-
-    if let DataType::Int(num_amplifier_channels) = header["num_amplifier_channels"] {
-        if num_amplifier_channels > 0 {
-            result_out.insert("spike_triggers".to_string(), header["spike_triggers"].clone());
-            result_out.insert("amplifier_channels".to_string(), header["amplifier_channels"].clone());
-        }
-    }
-
-    result_out.insert("notes".to_string(), header["notes"].clone());
-    result_out.insert("frequency_parameters".to_string(), header["frequency_parameters"].clone());
-    result_out.insert("reference_channel".to_string(), header["reference_channel"].clone());
-
-    if let DataType::Int(num_board_adc_channels) = header["num_board_adc_channels"] {
-        if num_board_adc_channels > 0 {
-            result_out.insert("board_adc_channels".to_string(), header["board_adc_channels"].clone());
-        }
-    }
-    
-    if let DataType::Int(num_board_dac_channels) = header["num_board_dac_channels"] {
-        if num_board_dac_channels > 0 {
-            result_out.insert("board_dac_channels".to_string(), header["board_dac_channels"].clone());
-        }
-    }
+pub(crate) fn print_header_summary(header: &RhsHeader) {
+    let file_type_name = if header.is_rhd() { "RHD2000" } else { "RHS" };
+    println!("\nReading Intan Technologies {} Data File, Version {}.{}\n", file_type_name, header.version.major, header.version.minor);
 
-    if let DataType::Int(num_board_dig_in_channels) = header["num_board_dig_in_channels"] {
-        if num_board_dig_in_channels > 0 {
-            result_out.insert("board_dig_in_channels".to_string(), header["board_dig_in_channels"].clone());
-        }
-    }
+    let num_amplifier_channels = header.amplifier_channels.len();
+    println!("Found {} amplifier channel{}.", num_amplifier_channels, plural(num_amplifier_channels));
 
-    if let DataType::Int(num_board_dig_out_channels) = header["num_board_dig_out_channels"] {
-        if num_board_dig_out_channels > 0 {
-            result_out.insert("board_dig_out_channels".to_string(), header["board_dig_out_channels"].clone());
-        }
+    if header.dc_amplifier_data_saved {
+        println!("Found {} DC amplifier channel{}.", num_amplifier_channels, plural(num_amplifier_channels));
     }
-}
-
 
-fn print_header_summary(header: &HashMap<String, DataType>) {
-    let num_amplifier_channels = match header.get("num_amplifier_channels") {
-        Some(DataType::Int(n)) => *n,
-        _ => 0,
-    };
-    println!("Found {} amplifier channel{}.", num_amplifier_channels, plural(num_amplifier_channels));
+    if header.is_rhd() {
+        let num_aux_input_channels = header.aux_input_channels.len();
+        println!("Found {} auxiliary input channel{}.", num_aux_input_channels, plural(num_aux_input_channels));
 
-    let dc_amplifier_data_saved = match header.get("dc_amplifier_data_saved") {
-        Some(DataType::Int(n)) => *n > 0,
-        _ => false,
-    };
-    if dc_amplifier_data_saved {
-        println!("Found {} DC amplifier channel{}.", num_amplifier_channels, plural(num_amplifier_channels));
+        let num_supply_voltage_channels = header.supply_voltage_channels.len();
+        println!("Found {} supply voltage channel{}.", num_supply_voltage_channels, plural(num_supply_voltage_channels));
     }
 
-    let num_board_adc_channels = match header.get("num_board_adc_channels") {
-        Some(DataType::Int(n)) => *n,
-        _ => 0,
-    };
+    let num_board_adc_channels = header.board_adc_channels.len();
     println!("Found {} board ADC channel{}.", num_board_adc_channels, plural(num_board_adc_channels));
 
-    let num_board_dac_channels = match header.get("num_board_dac_channels") {
-        Some(DataType::Int(n)) => *n,
-        _ => 0,
-    };
-    println!("Found {} board DAC channel{}.", num_board_dac_channels, plural(num_board_dac_channels));
+    if !header.is_rhd() {
+        let num_board_dac_channels = header.board_dac_channels.len();
+        println!("Found {} board DAC channel{}.", num_board_dac_channels, plural(num_board_dac_channels));
+    }
 
-    let num_board_dig_in_channels = match header.get("num_board_dig_in_channels") {
-        Some(DataType::Int(n)) => *n,
-        _ => 0,
-    };
+    let num_board_dig_in_channels = header.board_dig_in_channels.len();
     println!("Found {} board digital input channel{}.", num_board_dig_in_channels, plural(num_board_dig_in_channels));
 
-    let num_board_dig_out_channels = match header.get("num_board_dig_out_channels") {
-        Some(DataType::Int(n)) => *n,
-        _ => 0,
-    };
+    let num_board_dig_out_channels = header.board_dig_out_channels.len();
     println!("Found {} board digital output channel{}.", num_board_dig_out_channels, plural(num_board_dig_out_channels));
 
-    println!("");
+    if header.is_rhd() {
+        let num_temp_sensor_channels = header.num_temp_sensor_channels;
+        println!("Found {} temperature sensor channel{}.", num_temp_sensor_channels, plural(num_temp_sensor_channels));
+    }
+
+    println!();
 }
 
-fn plural(n: i32) -> &'static str {
+fn plural(n: usize) -> &'static str {
     if n != 1 {
         "s"
     } else {
@@ -622,375 +884,367 @@ fn plural(n: i32) -> &'static str {
     }
 }
 
-fn get_bytes_per_data_block(header: &HashMap<String, DataType>) -> std::result::Result<usize, Box<dyn std::error::Error>> {
-    
+pub(crate) fn get_bytes_per_data_block(header: &RhsHeader) -> usize {
+    if header.is_rhd() {
+        return get_rhd_bytes_per_data_block(header);
+    }
+
     // RHS files always have 128 samples per data block.
     // Use this number along with number of channels to accrue a sum of how
     // many bytes each data block should contain
 
-    let num_samples_per_data_block = 128;
+    let num_samples_per_data_block = header.num_samples_per_data_block();
 
     // Timestamps(one channel always present): start with 4 bytes per sample
 
     let mut bytes_per_block = bytes_per_signal_type(num_samples_per_data_block, 1, 4);
 
     // Amplifier data: Add 2 bytes per sample per enabled amplifier channel
-    if let DataType::Int(num_amplifier_channels) = header.get("num_amplifier_channels").unwrap() {
-        bytes_per_block += bytes_per_signal_type(num_samples_per_data_block, *num_amplifier_channels as usize, 2);
-    }
+    let num_amplifier_channels = header.amplifier_channels.len();
+    bytes_per_block += bytes_per_signal_type(num_samples_per_data_block, num_amplifier_channels, 2);
 
     // DC Amplifier data (absent if flag was off).
-    if let DataType::Int(dc_amplifier_data_saved) = header.get("dc_amplifier_data_saved").unwrap() {
-        if *dc_amplifier_data_saved != 0 {
-            if let DataType::Int(num_amplifier_channels) = header.get("num_amplifier_channels").unwrap() {
-                bytes_per_block += bytes_per_signal_type(num_samples_per_data_block, *num_amplifier_channels as usize, 2);
-            }
-        }
+    if header.dc_amplifier_data_saved {
+        bytes_per_block += bytes_per_signal_type(num_samples_per_data_block, num_amplifier_channels, 2);
     }
 
     // Stimulation data: Add 2 bytes per sample per enabled amplifier channel. 
-    if let DataType::Int(num_amplifier_channels) = header.get("num_amplifier_channels").unwrap() {
-        bytes_per_block += bytes_per_signal_type(num_samples_per_data_block, *num_amplifier_channels as usize, 2);
-    }
+    bytes_per_block += bytes_per_signal_type(num_samples_per_data_block, num_amplifier_channels, 2);
 
     // Analog inputs: Add 2 bytes per sample per enabled analog input channel. 
-    if let DataType::Int(num_board_adc_channels) = header.get("num_board_adc_channels").unwrap() {
-        bytes_per_block += bytes_per_signal_type(num_samples_per_data_block, *num_board_adc_channels as usize, 2);
-    }
+    bytes_per_block += bytes_per_signal_type(num_samples_per_data_block, header.board_adc_channels.len(), 2);
 
     // Analog outputs: Add 2 bytes per sample per enabled analog output channel.
-    if let DataType::Int(num_board_dac_channels) = header.get("num_board_dac_channels").unwrap() {
-        bytes_per_block += bytes_per_signal_type(num_samples_per_data_block, *num_board_dac_channels as usize, 2);
-    }
+    bytes_per_block += bytes_per_signal_type(num_samples_per_data_block, header.board_dac_channels.len(), 2);
 
     // Digital inputs: Add 2 bytes per sample.
     // Note that if at least 1 channel is enabled, a single 16-but sample
     // is saved, with each bit corresponding to an individual channel.
-    if let DataType::Int(num_board_dig_in_channels) = header.get("num_board_dig_in_channels").unwrap() {
-        if *num_board_dig_in_channels > 0 {
-            bytes_per_block += bytes_per_signal_type(num_samples_per_data_block, 1, 2);
-        }
+    if !header.board_dig_in_channels.is_empty() {
+        bytes_per_block += bytes_per_signal_type(num_samples_per_data_block, 1, 2);
     }
 
     // Digital outputs: Add 2 bytes per sample.
     // Note that if at least 1 channel is enabled, a single 16-bit sample
     // is saved, with each bit corresponding to an individual channel.
-    if let DataType::Int(num_board_dig_out_channels) = header.get("num_board_dig_out_channels").unwrap() {
-        if *num_board_dig_out_channels > 0 {
-            bytes_per_block += bytes_per_signal_type(num_samples_per_data_block, 1, 2);
-        }
+    if !header.board_dig_out_channels.is_empty() {
+        bytes_per_block += bytes_per_signal_type(num_samples_per_data_block, 1, 2);
     }
 
-    Ok(bytes_per_block)
+    bytes_per_block
+}
+
+fn get_rhd_bytes_per_data_block(header: &RhsHeader) -> usize {
+
+    // RHD files have 60 (version 1.x) or 128 samples per data block. Auxiliary
+    // inputs are sampled at 1/4 of this rate and supply voltage and
+    // temperature sensors once per block.
+
+    let num_samples_per_data_block = header.num_samples_per_data_block();
+
+    // Timestamps(one channel always present): start with 4 bytes per sample
+    let mut bytes_per_block = bytes_per_signal_type(num_samples_per_data_block, 1, 4);
+
+    // Amplifier data: Add 2 bytes per sample per enabled amplifier channel
+    bytes_per_block += bytes_per_signal_type(num_samples_per_data_block, header.amplifier_channels.len(), 2);
+
+    // Auxiliary inputs: Add 2 bytes per sample per enabled aux input channel.
+    bytes_per_block += bytes_per_signal_type(num_samples_per_data_block / 4, header.aux_input_channels.len(), 2);
+
+    // Supply voltage: Add 2 bytes per block per enabled supply voltage channel.
+    bytes_per_block += bytes_per_signal_type(1, header.supply_voltage_channels.len(), 2);
+
+    // Temperature sensors: Add 2 bytes per block per enabled temperature sensor.
+    bytes_per_block += bytes_per_signal_type(1, header.num_temp_sensor_channels, 2);
+
+    // Analog inputs: Add 2 bytes per sample per enabled analog input channel.
+    bytes_per_block += bytes_per_signal_type(num_samples_per_data_block, header.board_adc_channels.len(), 2);
+
+    // Digital inputs and outputs: Add 2 bytes per sample each if at least one
+    // channel is enabled, with each bit corresponding to an individual channel.
+    if !header.board_dig_in_channels.is_empty() {
+        bytes_per_block += bytes_per_signal_type(num_samples_per_data_block, 1, 2);
+    }
+    if !header.board_dig_out_channels.is_empty() {
+        bytes_per_block += bytes_per_signal_type(num_samples_per_data_block, 1, 2);
+    }
+
+    bytes_per_block
 }
 
 fn bytes_per_signal_type(num_samples: usize, num_channels: usize, bytes_per_sample: usize) -> usize {
     num_samples * num_channels * bytes_per_sample
 }
 
-fn read_one_data_block(data: &mut HashMap<String, Arrays>, header: &HashMap<String, DataType>, index: &mut u64, fid: &mut File) -> std::result::Result<(), Box<dyn std::error::Error>> {
-    let samples_per_block: u64;
-    if let DataType::Int(num_samples_per_data_block) = header.get("num_samples_per_data_block").unwrap() {
-        samples_per_block = *num_samples_per_data_block as u64;
-    } else {
-        return Err(Box::new(std::io::Error::new(std::io::ErrorKind::InvalidData, "num_samples_per_data_block is not an integer")));
-    }
+fn read_one_data_block(data: &mut HashMap<String, Arrays>, header: &RhsHeader, channel_rows: Option<&ChannelRows>, index: &mut i64, fid: &mut File) -> std::result::Result<(), RhsError> {
+    let samples_per_block = header.num_samples_per_data_block() as u64;
     read_timestamps(fid, data, *index, samples_per_block)?;
-    read_analog_signals(fid, data, *index, samples_per_block, header)?;
+    read_analog_signals(fid, data, *index, samples_per_block, header, channel_rows)?;
     read_digital_signals(fid, data, *index, samples_per_block, header)?;
 
     Ok(())
 }
 
 
-fn read_timestamps(fid: &mut File, data: &mut HashMap<String, Arrays>, index: u64, num_samples: u64) -> std::result::Result<(), Box<dyn std::error::Error>> {
-    let start = index as usize;
-    let end = start + num_samples as usize;
-
+fn read_timestamps(fid: &mut File, data: &mut HashMap<String, Arrays>, index: i64, num_samples: u64) -> std::result::Result<(), RhsError> {
     let mut buffer = vec![0; num_samples as usize * 4];
     fid.read_exact(&mut buffer)?;
 
-    let timestamps: Vec<i32> = buffer.chunks_exact(4).map(|bytes| i32::from_le_bytes(bytes.try_into().unwrap())).collect();
+    let timestamps: Vec<i32> = buffer.chunks_exact(4).map(LittleEndian::read_i32).collect();
 
     if let Some(Arrays::ArrayOne(t)) = data.get_mut("t") {
-        let mut t_slice = t.slice_mut(s![start..end]);
-        t_slice.assign(&ArrayView1::from(&timestamps));
+        let (source, start) = clip_block(index, num_samples as usize, t.len());
+        let mut t_slice = t.slice_mut(s![start..start + source.len()]);
+        t_slice.assign(&ArrayView1::from(&timestamps[source]));
     } else {
-        return Err(Box::new(std::io::Error::new(std::io::ErrorKind::InvalidData, "t key not found in data HashMap")));
+        return Err(RhsError::missing_array("t"));
     }
     
     Ok(())
 }
 
 
-fn read_analog_signals(fid: &mut File, data: &mut HashMap<String, Arrays>, index: u64, samples_per_block: u64, header: &HashMap<String, DataType>) -> std::result::Result<(), Box<dyn std::error::Error>> {
-    let num_amplifier_channels = match header.get("num_amplifier_channels") {
-        Some(DataType::Int(n)) => *n,
-        _ => return Err(Box::new(std::io::Error::new(std::io::ErrorKind::InvalidData, "'num_amplifier_channels' is not an Int in 'header'"))),
-    };
+fn read_analog_signals(fid: &mut File, data: &mut HashMap<String, Arrays>, index: i64, samples_per_block: u64, header: &RhsHeader, channel_rows: Option<&ChannelRows>) -> std::result::Result<(), RhsError> {
+    if header.is_rhd() {
+        return read_rhd_analog_signals(fid, data, index, samples_per_block, header, channel_rows);
+    }
+
+    let num_amplifier_channels = header.amplifier_channels.len() as i32;
 
     read_analog_signal_type(fid,
-                            data.get_mut("amplifier_data").ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "'amplifier_data' is not in 'data'"))?,
+                            data_array(data, "amplifier_data")?,
                             index,
                             samples_per_block,
-                            num_amplifier_channels)?;
-
-    if let Some(DataType::Int(n)) = header.get("dc_amplifier_data_saved") {
-        if *n > 0 {
-            read_analog_signal_type(fid,
-                                    data.get_mut("dc_amplifier_data").ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "'dc_amplifier_data' is not in 'data'"))?,
-                                    index,
-                                    samples_per_block,
-                                    num_amplifier_channels)?;
-        }
+                            num_amplifier_channels,
+                            rows_for(channel_rows, "amplifier_data"))?;
+
+    if header.dc_amplifier_data_saved {
+        read_analog_signal_type(fid,
+                                data_array(data, "dc_amplifier_data")?,
+                                index,
+                                samples_per_block,
+                                num_amplifier_channels,
+                                rows_for(channel_rows, "dc_amplifier_data"))?;
     }
 
     read_analog_signal_type(fid,
-                            data.get_mut("stim_data_raw").ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "'stim_data_raw' is not in 'data'"))?,
+                            data_array(data, "stim_data_raw")?,
                             index,
                             samples_per_block,
-                            num_amplifier_channels)?;
-
-    let num_board_adc_channels = match header.get("num_board_adc_channels") {
-        Some(DataType::Int(n)) => *n,
-        _ => return Err(Box::new(std::io::Error::new(std::io::ErrorKind::InvalidData, "'num_board_adc_channels' is not an Int in 'header'"))),
-    };
+                            num_amplifier_channels,
+                            rows_for(channel_rows, "stim_data_raw"))?;
 
     read_analog_signal_type(fid,
-                            data.get_mut("board_adc_data").ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "'board_adc_data' is not in 'data'"))?,
+                            data_array(data, "board_adc_data")?,
                             index,
                             samples_per_block,
-                            num_board_adc_channels)?;
+                            header.board_adc_channels.len() as i32,
+                            rows_for(channel_rows, "board_adc_data"))?;
 
     read_analog_signal_type(fid,
-                            data.get_mut("board_dac_data").ok_or_else(|| Box::new(std::io::Error::new(std::io::ErrorKind::InvalidData, "'board_dac_data' is not in 'data'")))?,
+                            data_array(data, "board_dac_data")?,
                             index,
                             samples_per_block,
-                            match header.get("num_board_dac_channels") {
-                                Some(DataType::Int(n)) => *n,
-                                _ => return Err(Box::new(std::io::Error::new(std::io::ErrorKind::InvalidData, "'num_board_dac_channels' is not an Int in 'header'"))),
-                            })?;
+                            header.board_dac_channels.len() as i32,
+                            rows_for(channel_rows, "board_dac_data"))?;
+
+    Ok(())
+}
+
+fn read_rhd_analog_signals(fid: &mut File, data: &mut HashMap<String, Arrays>, index: i64, samples_per_block: u64, header: &RhsHeader, channel_rows: Option<&ChannelRows>) -> std::result::Result<(), RhsError> {
+    // Auxiliary inputs advance by a quarter of a block's samples, supply
+    // voltage and temperature sensors by a single sample per block.
+    let aux_input_index = index / 4;
+    let supply_voltage_index = index / samples_per_block as i64;
+
+    let signals: [(&str, i64, u64, usize); 5] = [
+        ("amplifier_data", index, samples_per_block, header.amplifier_channels.len()),
+        ("aux_input_data", aux_input_index, samples_per_block / 4, header.aux_input_channels.len()),
+        ("supply_voltage_data", supply_voltage_index, 1, header.supply_voltage_channels.len()),
+        ("temp_sensor_data", supply_voltage_index, 1, header.num_temp_sensor_channels),
+        ("board_adc_data", index, samples_per_block, header.board_adc_channels.len()),
+    ];
+
+    for (data_key, start, num_samples, num_channels) in signals {
+        read_analog_signal_type(fid,
+                                data_array(data, data_key)?,
+                                start,
+                                num_samples,
+                                num_channels as i32,
+                                rows_for(channel_rows, data_key))?;
+    }
 
     Ok(())
 }
 
-fn read_analog_signal_type(fid: &mut File, dest: &mut Arrays, start: u64, num_samples: u64, num_channels: i32) -> std::result::Result<(), Box<dyn std::error::Error>> {
+fn data_array<'a>(data: &'a mut HashMap<String, Arrays>, data_key: &str) -> std::result::Result<&'a mut Arrays, RhsError> {
+    data.get_mut(data_key).ok_or_else(|| RhsError::missing_array(data_key))
+}
+
+fn read_analog_signal_type(fid: &mut File, dest: &mut Arrays, index: i64, num_samples: u64, num_channels: i32, rows: Option<&[usize]>) -> std::result::Result<(), RhsError> {
     if num_channels < 1 {
         return Ok(());
     }
-    let start = start as usize;
     let num_samples = num_samples as usize;
     let num_channels = num_channels as usize;
-    let end = start + num_samples;
 
     let mut buffer = vec![0; num_samples * num_channels * 2];
     fid.read_exact(&mut buffer)?;
 
-    let analog_signals: Vec<u16> = buffer.chunks_exact(2).map(|bytes| u16::from_le_bytes(bytes.try_into().unwrap())).collect();
-    let analog_signals_i32: Vec<i32> = analog_signals.iter().map(|&x| x as i32).collect();
+    let analog_signals: Vec<u16> = buffer.chunks_exact(2).map(LittleEndian::read_u16).collect();
 
-    if let Arrays::ArrayTwo(t) = dest {
-        let end = std::cmp::min(end, t.len_of(Axis(1)));
-        let mut t_slice = t.slice_mut(s![.., start..end]);
-        let reshaped_signals = ArrayView2::from_shape((num_channels, num_samples), &analog_signals_i32).unwrap();
-        t_slice.assign(&reshaped_signals);
+    if let Arrays::ArrayTwoU16(t) = dest {
+        let (source, start) = clip_block(index, num_samples, t.len_of(Axis(1)));
+        let mut t_slice = t.slice_mut(s![.., start..start + source.len()]);
+        let reshaped_signals = ArrayView2::from_shape((num_channels, num_samples), &analog_signals)?;
+        match rows {
+            Some(rows) => {
+                for (dest_row, &row) in rows.iter().enumerate() {
+                    t_slice.row_mut(dest_row).assign(&reshaped_signals.slice(s![row, source.clone()]));
+                }
+            },
+            None => t_slice.assign(&reshaped_signals.slice(s![.., source])),
+        }
     } else {
-        return Err(Box::new(std::io::Error::new(std::io::ErrorKind::InvalidData, "Expected ArrayTwo")));
+        return Err(RhsError::DataArrayError("Expected ArrayTwoU16".to_string()));
     }
 
     Ok(())
 }
 
-fn read_digital_signals(fid: &mut File, data: &mut HashMap<String, Arrays>, index: u64, samples_per_block: u64, header: &HashMap<String, DataType>) -> std::result::Result<(), Box<dyn std::error::Error>> {
-    let num_board_dig_in_channels = match header.get("num_board_dig_in_channels") {
-        Some(DataType::Int(n)) => *n,
-        _ => return Err(Box::new(std::io::Error::new(std::io::ErrorKind::InvalidData, "'num_board_dig_in_channels' is not an Int in 'header'"))),
-    };
+fn read_digital_signals(fid: &mut File, data: &mut HashMap<String, Arrays>, index: i64, samples_per_block: u64, header: &RhsHeader) -> std::result::Result<(), RhsError> {
+    let num_board_dig_in_channels = header.board_dig_in_channels.len() as i32;
 
     if num_board_dig_in_channels > 0 {
         read_digital_signal_type(fid,
-                                 data.get_mut("board_dig_in_raw").ok_or_else(|| Box::new(std::io::Error::new(std::io::ErrorKind::InvalidData, "'board_dig_in_raw' is not in 'data'")))?,
+                                 data_array(data, "board_dig_in_raw")?,
                                  index,
                                  samples_per_block,
-                                 num_board_dig_in_channels);
+                                 num_board_dig_in_channels)?;
     }
 
-    let num_board_dig_out_channels = match header.get("num_board_dig_out_channels") {
-        Some(DataType::Int(n)) => *n,
-        _ => return Err(Box::new(std::io::Error::new(std::io::ErrorKind::InvalidData, "'num_board_dig_out_channels' is not an Int in 'header'"))),
-    };
+    let num_board_dig_out_channels = header.board_dig_out_channels.len() as i32;
 
     if num_board_dig_out_channels > 0 {
         read_digital_signal_type(fid,
-                                 data.get_mut("board_dig_out_raw").ok_or_else(|| Box::new(std::io::Error::new(std::io::ErrorKind::InvalidData, "'board_dig_out_raw' is not in 'data'")))?,
+                                 data_array(data, "board_dig_out_raw")?,
                                  index,
                                  samples_per_block,
-                                 num_board_dig_out_channels);
+                                 num_board_dig_out_channels)?;
     }
 
     Ok(())
 }
 
 
-fn read_digital_signal_type(fid: &mut File, dest: &mut Arrays, start: u64, num_samples: u64, num_channels: i32) {
+fn read_digital_signal_type(fid: &mut File, dest: &mut Arrays, index: i64, num_samples: u64, num_channels: i32) -> std::result::Result<(), RhsError> {
     if num_channels < 1 {
-        return;
+        return Ok(());
     }
-    let start = start as usize;
     let num_samples = num_samples as usize;
-    let num_channels = num_channels as usize;
-    let end = start + num_samples;
 
-    let mut buffer = vec![0; num_samples * num_channels * 2];
-    fid.read_exact(&mut buffer).unwrap();
+    // All enabled digital channels share a single 16-bit word per sample,
+    // with each bit corresponding to an individual channel.
+    let mut buffer = vec![0; num_samples * 2];
+    fid.read_exact(&mut buffer)?;
 
-    let digital_signals: Vec<i32> = buffer.chunks_exact(2).map(|bytes| LittleEndian::read_u16(bytes) as i32).collect();
+    let digital_signals: Vec<u16> = buffer.chunks_exact(2).map(LittleEndian::read_u16).collect();
 
     match dest {
-        Arrays::ArrayTwo(t) => {
-            let mut t_slice = t.slice_mut(s![.., start..end]);
-            let num_samples = t_slice.dim().1;
-            let reshaped_signals = ArrayView2::from_shape((num_channels, num_samples), &digital_signals[..num_channels * num_samples]).unwrap();
-            t_slice.assign(&reshaped_signals);
+        // No row is allocated when none of the channels were selected.
+        Arrays::ArrayTwoU16(t) if t.nrows() == 0 => {},
+        Arrays::ArrayTwoU16(t) => {
+            let (source, start) = clip_block(index, num_samples, t.len_of(Axis(1)));
+            let mut t_slice = t.slice_mut(s![0, start..start + source.len()]);
+            t_slice.assign(&ArrayView1::from(&digital_signals[source]));
         },
-        _ => eprintln!("Expected ArrayTwo"),
+        _ => return Err(RhsError::DataArrayError("Expected ArrayTwoU16".to_string())),
     }
 
+    Ok(())
 }
 
-fn data_to_result(header: &HashMap<String, DataType>, data: &mut HashMap<String, Arrays>, result_out: &mut HashMap<String, DataType>) {
-    result_out.insert("t".to_string(), DataType::Array(data.remove("t").unwrap()));
-    result_out.insert("stim_data".to_string(), DataType::Array(data.remove("stim_data").unwrap()));
+// A data block of `num_samples` samples belongs at `index` in the output
+// arrays. When only part of the recording is loaded, the first and last blocks
+// hang over the edges of the output; return the range of the block's samples
+// to keep and where the first of them goes.
+pub(crate) fn clip_block(index: i64, num_samples: usize, len: usize) -> (Range<usize>, usize) {
+    let first = (-index).clamp(0, num_samples as i64) as usize;
+    let start = index.max(0) as usize;
+    let last = num_samples.min(len.saturating_sub(start) + first);
+    (first..last.max(first), start.min(len))
+}
 
-    if let DataType::Bool(dc_amplifier_data_saved) = header["dc_amplifier_data_saved"] {
-        if dc_amplifier_data_saved {
-            result_out.insert("dc_amplifier_data".to_string(), DataType::Array(data.remove("dc_amplifier_data").unwrap()));
+pub(crate) fn data_to_result(header: &RhsHeader, data: &mut HashMap<String, Arrays>, result_out: &mut HashMap<String, DataType>) {
+    move_array(data, result_out, "t");
+
+    if header.is_rhd() {
+        rhd_data_to_result(header, data, result_out);
+    } else {
+        move_array(data, result_out, "stim_data");
+
+        if header.dc_amplifier_data_saved {
+            move_array(data, result_out, "dc_amplifier_data");
         }
-    }
 
-    if let DataType::Int(num_amplifier_channels) = header["num_amplifier_channels"] {
-        if num_amplifier_channels > 0 {
-            result_out.insert("compliance_limit_data".to_string(), DataType::Array(data.remove("compliance_limit_data").unwrap()));
-            result_out.insert("charge_recovery_data".to_string(), DataType::Array(data.remove("charge_recovery_data").unwrap()));
-            result_out.insert("amp_settle_data".to_string(), DataType::Array(data.remove("amp_settle_data").unwrap()));
-            result_out.insert("amplifier_data".to_string(), DataType::Array(data.remove("amplifier_data").unwrap()));
+        if !header.amplifier_channels.is_empty() {
+            move_array(data, result_out, "compliance_limit_data");
+            move_array(data, result_out, "charge_recovery_data");
+            move_array(data, result_out, "amp_settle_data");
+            move_array(data, result_out, "amplifier_data");
         }
     }
 
-    if let DataType::Int(num_board_adc_channels) = header["num_board_adc_channels"] {
-        if num_board_adc_channels > 0 {
-            result_out.insert("board_adc_data".to_string(), DataType::Array(data.remove("board_adc_data").unwrap()));
-        }
+    if !header.board_adc_channels.is_empty() {
+        move_array(data, result_out, "board_adc_data");
     }
 
-    if let DataType::Int(num_board_dac_channels) = header["num_board_dac_channels"] {
-        if num_board_dac_channels > 0 {
-            result_out.insert("board_dac_data".to_string(), DataType::Array(data.remove("board_dac_data").unwrap()));
-        }
+    if !header.board_dac_channels.is_empty() {
+        move_array(data, result_out, "board_dac_data");
     }
 
-    if let DataType::Int(num_board_dig_in_channels) = header["num_board_dig_in_channels"] {
-        if num_board_dig_in_channels > 0 {
-            result_out.insert("board_dig_in_data".to_string(), DataType::Array(data.remove("board_dig_in_data").unwrap()));
-        }
+    if !header.board_dig_in_channels.is_empty() {
+        move_array(data, result_out, "board_dig_in_raw");
     }
 
-    if let DataType::Int(num_board_dig_out_channels) = header["num_board_dig_out_channels"] {
-        if num_board_dig_out_channels > 0 {
-            result_out.insert("board_dig_out_data".to_string(), DataType::Array(data.remove("board_dig_out_data").unwrap()));
-        }
+    if !header.board_dig_out_channels.is_empty() {
+        move_array(data, result_out, "board_dig_out_raw");
     }
 }
 
 
-/* 
-fn plot_channel(channel_name: &str, result_out: &HashMap<String, DataType>) -> std::result::Result<(), Box<dyn std::error::Error>> {
-    let (channel_found, signal_type, signal_index) = find_channel_in_header(channel_name, result_out);
-
-    if channel_found {
-        let root = BitMapBackend::new("plot.png", (640, 480)).into_drawing_area();
-        root.fill(&WHITE)?;
+// Move a signal array from 'data' to 'result_out', if it was allocated.
+fn move_array(data: &mut HashMap<String, Arrays>, result_out: &mut HashMap<String, DataType>, data_key: &str) {
+    if let Some(array) = data.remove(data_key) {
+        result_out.insert(data_key.to_string(), DataType::Array(array));
+    }
+}
 
-        let mut chart = ChartBuilder::on(&root)
-            .caption(channel_name, ("sans-serif", 50).into_font())
-            .margin(5)
-            .x_label_area_size(30)
-            .y_label_area_size(30)
-            .build_cartesian_2d(0f32..1f32, 0f32..1f32)?;
+fn rhd_data_to_result(header: &RhsHeader, data: &mut HashMap<String, Arrays>, result_out: &mut HashMap<String, DataType>) {
+    if !header.amplifier_channels.is_empty() {
+        move_array(data, result_out, "amplifier_data");
+    }
 
-        chart.configure_mesh().draw()?;
+    if !header.aux_input_channels.is_empty() {
+        move_array(data, result_out, "t_aux_input");
+        move_array(data, result_out, "aux_input_data");
+    }
 
-        let signal_data_name;
-        let ylabel;
+    let num_supply_voltage_channels = header.supply_voltage_channels.len();
+    let num_temp_sensor_channels = header.num_temp_sensor_channels;
 
-        match signal_type.as_str() {
-            "amplifier_channels" => {
-                ylabel = "Voltage (microVolts)";
-                signal_data_name = "amplifier_data";
-            }
-            "dc_amplifier_channels" => {
-                ylabel = "Voltage (Volts)";
-                signal_data_name = "dc_amplifier_data";
-            }
-            "stim_channels" => {
-                ylabel = "Current (microAmps)";
-                signal_data_name = "stim_data";
-            }
-            "amp_settle_channels" => {
-                ylabel = "Amp Settle Events (High or Low)";
-                signal_data_name = "amp_settle_data";
-            }
-            "charge_recovery_channels" => {
-                ylabel = "Charge Recovery Events (High or Low)";
-                signal_data_name = "charge_recovery_data";
-            }
-            "compliance_limit_channels" => {
-                ylabel = "Compliance Limit Events (High or Low)";
-                signal_data_name = "compliance_limit_data";
-            }
-            "board_adc_channels" => {
-                ylabel = "Voltage (Volts)";
-                signal_data_name = "board_adc_data";
-            }
-            "board_dac_channels" => {
-                ylabel = "Voltage (Volts)";
-                signal_data_name = "board_dac_data";
-            }
-            "board_dig_in_channels" => {
-                ylabel = "Digital In Events (High or Low)";
-                signal_data_name = "board_dig_in_data";
-            }
-            "board_dig_out_channels" => {
-                ylabel = "Digital Out Events (High or Low)";
-                signal_data_name = "board_dig_out_data";
-            }
-            _ => return Err(Box::new(RhsError::ChannelNotFoundError)),
-        }
-
-        let signal_data = result_out.get(signal_data_name).unwrap();
-        let time_data = result_out.get("t").unwrap();
-
-        if let DataType::Array(Arrays::ArrayOne(time_data)) = time_data {
-            if let DataType::VecChannel(signal_data) = signal_data {
-                if let Some(DataType::Float(signal_value)) = signal_data[signal_index].get("signal_value") {
-                    let signal_value_vec: Vec<f32> = vec![*signal_value; time_data.len()];
-                    chart.draw_series(LineSeries::new(
-                        time_data.iter().zip(signal_value_vec.iter()).map(|(x, y)| (*x as f32, *y as f32)),
-                        &RED,
-                    ))?;
-                }
-            }
-        }
-        chart.configure_series_labels().draw()?;
-    } else {
-        return Err(Box::new(RhsError::ChannelNotFoundError));
+    if num_supply_voltage_channels > 0 || num_temp_sensor_channels > 0 {
+        move_array(data, result_out, "t_supply_voltage");
+    }
+    if num_supply_voltage_channels > 0 {
+        move_array(data, result_out, "supply_voltage_data");
+    }
+    if num_temp_sensor_channels > 0 {
+        move_array(data, result_out, "temp_sensor_data");
     }
-
-    Ok(())
 }
-*/
 
-fn read_qstring(fid: &mut File) -> std::result::Result<String, IOError> {
+fn read_qstring(fid: &mut File) -> Result<String> {
     let length: u32 = fid.read_u32::<LittleEndian>()?;
     
     // if length set to 0xFFFFFFFF, return empty string
@@ -998,13 +1252,13 @@ fn read_qstring(fid: &mut File) -> std::result::Result<String, IOError> {
         return Ok(String::new());
     }
 
-    let current_position = fid.seek(SeekFrom::Current(0))?;
+    let current_position = fid.stream_position()?;
     let file_length = fid.seek(SeekFrom::End(0))?;
     fid.seek(SeekFrom::Start(current_position))?;
 
 
     if length as u64 > file_length - current_position + 1 {
-        return Err(IOError::new(std::io::ErrorKind::InvalidData, "Length too long."));
+        return Err(RhsError::QStringError { offset: current_position - 4, length });
     }
 
     // Convert length from bytes to 16-bit Unicode words.
@@ -1028,48 +1282,67 @@ fn read_qstring(fid: &mut File) -> std::result::Result<String, IOError> {
     Ok(a)
 }
 
-fn calculate_data_size(header: &mut HashMap<String, DataType>, filename: &str, fid: &mut File) -> std::result::Result<(bool, u64, u64, u64), Box<dyn std::error::Error>> {
-    let bytes_per_block = get_bytes_per_data_block(header)?;
+// How much data follows the header of a traditional file.
+pub(crate) struct DataSize {
+    pub(crate) data_present: bool,
+    // Size of the file up to the end of its last complete data block.
+    filesize: u64,
+    num_blocks: u64,
+    pub(crate) num_samples: u64,
+    // Bytes after the last complete data block, only dropped in lenient mode.
+    dropped_bytes: u64,
+}
+
+pub(crate) fn calculate_data_size(header: &RhsHeader, filename: &str, lenient: bool, fid: &mut File) -> std::result::Result<DataSize, RhsError> {
+    let bytes_per_block = get_bytes_per_data_block(header);
 
     // Determine filesize and if any data is present.
     let metadata = metadata(filename)?;
-    let filesize = metadata.len();
+    let mut filesize = metadata.len();
     let mut data_present: bool = false;
-    let bytes_remaining = filesize - fid.seek(SeekFrom::Current(0))?;
+    let mut bytes_remaining = filesize - fid.stream_position()?;
     if bytes_remaining > 0 {
         data_present = true;
     }
 
     // If the file size is somehow different than expected, raise an error.
-    if bytes_remaining % bytes_per_block as u64 != 0 {
-        return Err(Box::new(std::io::Error::new(std::io::ErrorKind::InvalidData, "Something is wrong with file size : should have a whole number of data blocks")));
+    // In lenient mode, drop the partial block at the end of the file instead
+    // and only read up to the last complete block.
+    let bytes_per_block = bytes_per_block as u64;
+    let dropped_bytes = bytes_remaining % bytes_per_block;
+    if dropped_bytes != 0 && !lenient {
+        return Err(RhsError::FileSizeError {
+            context: format!("Data following the header at byte {}", filesize - bytes_remaining),
+            size: bytes_remaining,
+            expected: bytes_remaining.div_ceil(bytes_per_block) * bytes_per_block,
+        });
+    }
+    if dropped_bytes != 0 {
+        filesize -= dropped_bytes;
+        bytes_remaining -= dropped_bytes;
+        data_present = bytes_remaining > 0;
     }
 
     // Calculate how many data blocks are present.
-    let num_blocks = bytes_remaining / bytes_per_block as u64;
+    let num_blocks = bytes_remaining / bytes_per_block;
     
-    let num_samples = calculate_num_samples(header, num_blocks)?;
+    let num_samples = calculate_num_samples(header, num_blocks);
 
-    let sample_rate: f32;
-    match header.get("sample_rate") {
-        Some(DataType::Float(rate)) => sample_rate = *rate,
-        _ => return Err(Box::new(std::io::Error::new(std::io::ErrorKind::InvalidData, "sample_rate is not a float"))),
-    }
-
-    print_record_time_summary(num_samples, sample_rate, data_present);
+    Ok(DataSize { data_present, filesize, num_blocks, num_samples, dropped_bytes })
+}
 
-    Ok((data_present, filesize, num_blocks, num_samples))
+fn calculate_num_samples(header: &RhsHeader, num_data_blocks: u64) -> u64 {
+    header.num_samples_per_data_block() as u64 * num_data_blocks
 }
 
-fn calculate_num_samples(header: &mut HashMap<String, DataType>, num_data_blocks: u64) -> std::result::Result<u64, Box<dyn std::error::Error>> {
-    if let DataType::Int(num_samples_per_data_block) = header.get("num_samples_per_data_block").unwrap() {
-        Ok((*num_samples_per_data_block as u64) * num_data_blocks)
-    } else {
-        Err(Box::new(std::io::Error::new(std::io::ErrorKind::InvalidData, "num_samples_per_data_block is not an integer")))
+pub(crate) fn print_data_size(header: &RhsHeader, data_size: &DataSize) {
+    if data_size.dropped_bytes != 0 {
+        println!("Warning: file ends partway through a data block. Dropping the last {} bytes.", data_size.dropped_bytes);
     }
+    print_record_time_summary(data_size.num_samples, header.sample_rate, data_size.data_present);
 }
 
-fn print_record_time_summary(num_amp_samples: u64, sample_rate: f32, data_present: bool) {
+pub(crate) fn print_record_time_summary(num_amp_samples: u64, sample_rate: f32, data_present: bool) {
     let record_time = num_amp_samples as f32 / sample_rate;
 
     if data_present {
@@ -1079,211 +1352,438 @@ fn print_record_time_summary(num_amp_samples: u64, sample_rate: f32, data_presen
     }
 }
 
-fn read_all_data_blocks(header: &mut HashMap<String, DataType>, num_samples: u64, num_blocks: u64, fid: &mut File) -> std::result::Result<HashMap<String, Arrays>, Box<dyn std::error::Error>> {
-    let (mut data, mut index) = initialize_memory(header, num_samples)?;
-    println!("Reading data from file...");
-    let print_step = 10;
+pub(crate) fn file_sample_range(header: &RhsHeader, options: &LoadOptions, num_samples: u64) -> std::result::Result<Range<u64>, RhsError> {
+    let sample_range = options.sample_range(num_samples, header.sample_rate)?;
+
+    // RHD files sample auxiliary inputs, supply voltage and temperature
+    // sensors at lower rates within each block, so their ranges are widened
+    // to whole data blocks.
+    if header.is_rhd() {
+        let samples_per_block = header.num_samples_per_data_block() as u64;
+        let start = sample_range.start / samples_per_block * samples_per_block;
+        let stop = (sample_range.end.div_ceil(samples_per_block) * samples_per_block).min(num_samples);
+        return Ok(start..stop);
+    }
+
+    Ok(sample_range)
+}
+
+fn read_all_data_blocks(header: &RhsHeader, selected_header: &RhsHeader, channel_rows: Option<&ChannelRows>, sample_range: &Range<u64>, options: &LoadOptions, fid: &mut File) -> std::result::Result<HashMap<String, Arrays>, RhsError> {
+    if options.verbose {
+        println!("\nAllocating memory for data...");
+    }
+    let (mut data, _) = initialize_memory(selected_header, sample_range.end - sample_range.start);
+
+    if options.verbose {
+        println!("Reading data from file...");
+    }
+    let pool = options.num_threads.map(thread_pool).transpose()?;
+    read_data_blocks(&mut data, header, channel_rows, sample_range, pool.as_ref(), options.verbose, fid)?;
+    Ok(data)
+}
+
+/// Read the samples in `sample_range` into `data`, allocated for exactly that
+/// range, with `fid` at the first data block of the file. Blocks are decoded
+/// on `pool` if given, and one at a time otherwise.
+pub(crate) fn read_data_blocks(data: &mut HashMap<String, Arrays>, header: &RhsHeader, channel_rows: Option<&ChannelRows>, sample_range: &Range<u64>, pool: Option<&ThreadPool>, progress: bool, fid: &mut File) -> std::result::Result<(), RhsError> {
+    // Data blocks have a fixed size, so seek straight to the block holding the
+    // first requested sample. The samples before it in that block are dropped
+    // by placing the block at a negative index.
+    let samples_per_block = header.num_samples_per_data_block() as u64;
+    let first_block = sample_range.start / samples_per_block;
+    let num_blocks = sample_range.end.div_ceil(samples_per_block) - first_block;
+    fid.seek(SeekFrom::Current((first_block * get_bytes_per_data_block(header) as u64) as i64))?;
+    let mut index = -((sample_range.start - first_block * samples_per_block) as i64);
+
     let num_blocks = num_blocks as usize;
+    if let Some(pool) = pool {
+        return read_data_blocks_parallel(data, header, channel_rows, index, num_blocks, pool, progress, fid);
+    }
+
+    let print_step = 10;
     let mut percent_done = print_step;
 
     for i in 0..num_blocks {
-        read_one_data_block(&mut data, header, &mut index, fid)?;
-        if let DataType::Int(num_samples_per_data_block) = header.get("num_samples_per_data_block").unwrap() {
-            index = advance_index(index, *num_samples_per_data_block as u64);
-        } else {
-            return Err(Box::new(std::io::Error::new(std::io::ErrorKind::InvalidData, "num_samples_per_data_block is not an integer")));
+        let offset = fid.stream_position()?;
+        read_one_data_block(data, header, channel_rows, &mut index, fid)
+            .map_err(|e| e.at(format!("reading data block {}", first_block as usize + i), Some(offset)))?;
+        index = advance_index(index, samples_per_block as i64);
+        if progress {
+            percent_done = print_progress(i, num_blocks, print_step, percent_done);
         }
-        percent_done = print_progress(i, num_blocks, print_step, percent_done);
     }
-    Ok(data)
+    Ok(())
 }
 
 
-fn initialize_memory(header: &HashMap<String, DataType>, num_samples: u64) -> std::result::Result<(HashMap<String, Arrays>, u64), Box<dyn std::error::Error>> {
-    println!("\nAllocating memory for data...");
+pub(crate) fn initialize_memory(header: &RhsHeader, num_samples: u64) -> (HashMap<String, Arrays>, i64) {
     let mut data: HashMap<String, Arrays> = HashMap::new();
 
     // Create zero array for timestamps.
     data.insert("t".to_string(), Arrays::ArrayOne(Array1::zeros(num_samples as usize,)));
 
-    // Create zero array for amplifier data.
-    if let DataType::Int(num_amplifier_channels) = header.get("num_amplifier_channels").unwrap() {
-        data.insert("amplifier_data".to_string(), Arrays::ArrayTwo(Array2::zeros((*num_amplifier_channels as usize, num_samples as usize))));
-    } else {
-        return Err(Box::new(std::io::Error::new(std::io::ErrorKind::InvalidData, "num_amplifier_channels is not an integer")));
+    if header.is_rhd() {
+        initialize_rhd_memory(header, num_samples, &mut data);
+        return (data, 0);
     }
 
+    let num_samples = num_samples as usize;
+
+    // Create zero array for amplifier data.
+    let num_amplifier_channels = header.amplifier_channels.len();
+    data.insert("amplifier_data".to_string(), Arrays::ArrayTwoU16(Array2::zeros((num_amplifier_channels, num_samples))));
+
     // Create zero array for DC amplifier data.
-    if let Some(DataType::Int(dc_amplifier_data_saved)) = header.get("dc_amplifier_data_saved") {
-        if *dc_amplifier_data_saved == 1 {
-            if let Some(DataType::Int(num_amplifier_channels)) = header.get("num_amplifier_channels") {
-                data.insert("dc_amplifier_data".to_string(), Arrays::ArrayTwo(Array2::zeros((*num_amplifier_channels as usize, num_samples as usize))));
-            } else {
-                return Err(Box::new(std::io::Error::new(std::io::ErrorKind::InvalidData, "num_amplifier_channels is not an integer")));
-            }
-        }
-    } else {
-        return Err(Box::new(std::io::Error::new(std::io::ErrorKind::InvalidData, "dc_amplifier_data_saved is not an integer")));
+    if header.dc_amplifier_data_saved {
+        data.insert("dc_amplifier_data".to_string(), Arrays::ArrayTwoU16(Array2::zeros((num_amplifier_channels, num_samples))));
     }
 
     // Create zero array for stim data.
-    if let DataType::Int(num_amplifier_channels) = header.get("num_amplifier_channels").unwrap() {
-        data.insert("stim_data_raw".to_string(), Arrays::ArrayTwo(Array2::zeros((*num_amplifier_channels as usize, num_samples as usize))));
-        data.insert("stim_data".to_string(), Arrays::ArrayTwo(Array2::zeros((*num_amplifier_channels as usize, num_samples as usize))));
-    } else {
-        return Err(Box::new(std::io::Error::new(std::io::ErrorKind::InvalidData, "num_amplifier_channels is not an integer")));
-    }
+    data.insert("stim_data_raw".to_string(), Arrays::ArrayTwoU16(Array2::zeros((num_amplifier_channels, num_samples))));
+    data.insert("stim_data".to_string(), Arrays::ArrayTwo(Array2::zeros((num_amplifier_channels, num_samples))));
 
     // Create zero array for board ADC data.
-    if let DataType::Int(num_board_adc_channels) = header.get("num_board_adc_channels").unwrap() {
-        data.insert("board_adc_data".to_string(), Arrays::ArrayTwo(Array2::zeros((*num_board_adc_channels as usize, num_samples as usize))));
-    } else {
-        return Err(Box::new(std::io::Error::new(std::io::ErrorKind::InvalidData, "num_board_adc_channels is not an integer")));
-    }
+    let num_board_adc_channels = header.board_adc_channels.len();
+    data.insert("board_adc_data".to_string(), Arrays::ArrayTwoU16(Array2::zeros((num_board_adc_channels, num_samples))));
 
     // Create zero array for board DAC data.
-    if let DataType::Int(num_board_dac_channels) = header.get("num_board_dac_channels").unwrap() {
-        data.insert("board_dac_data".to_string(), Arrays::ArrayTwo(Array2::zeros((*num_board_dac_channels as usize, num_samples as usize))));
-    } else {
-        return Err(Box::new(std::io::Error::new(std::io::ErrorKind::InvalidData, "num_board_dac_channels is not an integer")));
-    }
+    let num_board_dac_channels = header.board_dac_channels.len();
+    data.insert("board_dac_data".to_string(), Arrays::ArrayTwoU16(Array2::zeros((num_board_dac_channels, num_samples))));
 
-    // Create zero array for digital in data.
-    if let DataType::Int(num_board_dig_in_channels) = header.get("num_board_dig_in_channels").unwrap() {
-        data.insert("board_dig_in_data".to_string(), Arrays::ArrayTwo(Array2::zeros((*num_board_dig_in_channels as usize, num_samples as usize))));
-        data.insert("board_dig_in_raw".to_string(), Arrays::ArrayTwo(Array2::zeros((*num_board_dig_in_channels as usize, num_samples as usize))));
-    } else {
-        return Err(Box::new(std::io::Error::new(std::io::ErrorKind::InvalidData, "num_board_dig_in_channels is not an integer")));
-    }
-
-    // Create zero array for digital out data.
-    if let DataType::Int(num_board_dig_out_channels) = header.get("num_board_dig_out_channels").unwrap() {
-        data.insert("board_dig_out_data".to_string(), Arrays::ArrayTwo(Array2::zeros((*num_board_dig_out_channels as usize, num_samples as usize))));
-        data.insert("board_dig_out_raw".to_string(), Arrays::ArrayTwo(Array2::zeros((*num_board_dig_out_channels as usize, num_samples as usize))));
-    } else {
-        return Err(Box::new(std::io::Error::new(std::io::ErrorKind::InvalidData, "num_board_dig_out_channels is not an integer")));
-    }
+    // Create zero arrays for the digital in and out words.
+    initialize_digital_memory(header, num_samples, &mut data);
 
     // Set index representing position of data (shared across all signal types
     // for RHS file) to 0
     let index = 0;
 
-    Ok((data, index))
+    (data, index)
+}
+
+fn initialize_rhd_memory(header: &RhsHeader, num_samples: u64, data: &mut HashMap<String, Arrays>) {
+    let num_samples = num_samples as usize;
+    let num_aux_input_samples = num_samples / 4;
+    let num_supply_voltage_samples = num_samples / header.num_samples_per_data_block();
+
+    // Create zero arrays for each analog signal type at its own sample rate.
+    let signals: [(&str, usize, usize); 5] = [
+        ("amplifier_data", header.amplifier_channels.len(), num_samples),
+        ("aux_input_data", header.aux_input_channels.len(), num_aux_input_samples),
+        ("supply_voltage_data", header.supply_voltage_channels.len(), num_supply_voltage_samples),
+        ("temp_sensor_data", header.num_temp_sensor_channels, num_supply_voltage_samples),
+        ("board_adc_data", header.board_adc_channels.len(), num_samples),
+    ];
+    for (data_key, num_channels, length) in signals {
+        data.insert(data_key.to_string(), Arrays::ArrayTwoU16(Array2::zeros((num_channels, length))));
+    }
+
+    // Create zero arrays for the digital in and out words.
+    initialize_digital_memory(header, num_samples, data);
 }
 
-fn advance_index(index: u64, samples_per_block: u64) -> u64 {
+fn initialize_digital_memory(header: &RhsHeader, num_samples: usize, data: &mut HashMap<String, Arrays>) {
+    // Digital channels are saved as one 16-bit word per sample, shared by all
+    // enabled channels, and are kept that way; each channel is the bit given
+    // by its native order.
+    for (channels, data_key) in [(&header.board_dig_in_channels, "board_dig_in_raw"), (&header.board_dig_out_channels, "board_dig_out_raw")] {
+        let num_words = if channels.is_empty() { 0 } else { 1 };
+        data.insert(data_key.to_string(), Arrays::ArrayTwoU16(Array2::zeros((num_words, num_samples))));
+    }
+}
+
+fn advance_index(index: i64, samples_per_block: i64) -> i64 {
     // For RHS, all signals sampled at the same sample rate:
     // Index should be incremented by samples_per_block every data block.
-    let index = index + samples_per_block;
-    index
+    index + samples_per_block
+}
+
+// Find the data blocks whose timestamps break the sequence in 't', counting
+// blocks from the start of the file. A block is intact if its timestamps
+// count up by one and follow on from the last intact block; gaps between
+// intact blocks are left to `check_timestamp_gaps`.
+fn find_corrupted_blocks(header: &RhsHeader, data: &HashMap<String, Arrays>, first_sample: u64) -> std::result::Result<Vec<u64>, RhsError> {
+    let Some(Arrays::ArrayOne(t)) = data.get("t") else {
+        return Err(RhsError::missing_array("t"));
+    };
+    let samples_per_block = header.num_samples_per_data_block();
+
+    // A range may start partway through its first block.
+    let mut block = first_sample / samples_per_block as u64;
+    let mut start = 0;
+    let mut end = samples_per_block - first_sample as usize % samples_per_block;
+    let mut last_timestamp: Option<i64> = None;
+    let mut corrupted_blocks = Vec::new();
+    while start < t.len() {
+        let timestamps = t.slice(s![start..end.min(t.len())]);
+        let consecutive = timestamps.windows(2).into_iter().all(|window| window[1].wrapping_sub(window[0]) == 1);
+        let advancing = last_timestamp.is_none_or(|last| timestamp_sample(header, timestamps[0]) > last);
+        if consecutive && advancing {
+            last_timestamp = Some(timestamp_sample(header, timestamps[timestamps.len() - 1]));
+        } else {
+            corrupted_blocks.push(block);
+        }
+        block += 1;
+        start = end;
+        end += samples_per_block;
+    }
+
+    Ok(corrupted_blocks)
 }
 
-fn check_end_of_file(filesize: u64, fid: &mut File) -> io::Result<()> {
-    let current_position = fid.seek(SeekFrom::Current(0))?;
-    let bytes_remaining = filesize - current_position;
-    if bytes_remaining != 0 {
-        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Error: End of file not reached. Current position: {}, File size: {}, Bytes remaining: {}", current_position, filesize, bytes_remaining)));
+// The header and data blocks should account for every byte of the file, or
+// every byte up to the partial block dropped in lenient mode.
+fn check_end_of_file(filesize: u64, fid: &mut File) -> Result<()> {
+    let current_position = fid.stream_position()?;
+    if current_position != filesize {
+        return Err(RhsError::FileSizeError { context: "File read through the last data block".to_string(), size: current_position, expected: filesize });
     }
     Ok(())
 }
 
-fn parse_data(header: &mut HashMap<String, DataType>, data: &mut HashMap<String, Arrays>) {
-    println!("Parsing data...");
-    extract_digital_data(header, data);
-    extract_stim_data(data);
-    scale_analog_data(header, data);
-    scale_timestamps(header, data);
+pub(crate) fn parse_data_to_result(header: &RhsHeader, data: &mut HashMap<String, Arrays>, options: &LoadOptions, result_out: &mut HashMap<String, DataType>) -> Result<()> {
+    if options.verbose {
+        println!("Parsing data...");
+        check_timestamp_gaps(data);
+    }
+    parse_data(header, data, options.raw);
+    if options.raw {
+        result_out.insert("scale_factors".to_string(), scale_factors_to_result(header));
+    }
+
+    // Save recorded data in 'data' to 'result_out' HashMap.
+    data_to_result(header, data, result_out);
+
+    if !options.raw {
+        filter_amplifier_data(header, options, result_out)?;
+    }
+    Ok(())
 }
 
+// Remove stimulation artifacts from the scaled amplifier data in
+// 'result_out', notch filter it and derive its bands, as set in 'options'.
+pub(crate) fn filter_amplifier_data(header: &RhsHeader, options: &LoadOptions, result_out: &mut HashMap<String, DataType>) -> Result<()> {
+    let artifact_mask = remove_stim_artifacts(header, result_out, &options.artifacts, options.verbose)?;
+    apply_notch_filter(header, result_out, &options.notch, options.verbose)?;
+    derive_bands(header, options, result_out)?;
+    if let (Some(mask), ArtifactMethod::Nan) = (artifact_mask, options.artifacts.method) {
+        let names = std::iter::once("amplifier_data").chain(options.bands.iter().map(|band| band.name.as_str()));
+        for name in names {
+            if let Some(DataType::Array(Arrays::ArrayTwoFloat(signal))) = result_out.get_mut(name) {
+                remove_artifacts(signal, mask.view(), ArtifactMethod::Nan)?;
+            }
+        }
+    }
+    Ok(())
+}
 
-fn scale_timestamps(header: &mut HashMap<String, DataType>, data: &mut HashMap<String, Arrays>) {
-    // Check for gaps in timestamps.
-    if let Some(Arrays::ArrayOne(t)) = data.get_mut("t") {
-        let num_gaps = t.windows(2).into_iter().filter(|window| window[1] - window[0] != 1).count();
+// Remove stimulation artifacts from the scaled amplifier data in
+// 'result_out', returning the samples treated. NaN blanking is left until
+// after filtering, so those samples are zeroed here instead.
+fn remove_stim_artifacts(header: &RhsHeader, result_out: &mut HashMap<String, DataType>, artifacts: &ArtifactOptions, verbose: bool) -> Result<Option<Array2<bool>>> {
+    if artifacts.method == ArtifactMethod::Keep {
+        return Ok(None);
+    }
+    let (pre_samples, post_samples) = artifacts.window_samples(header)?;
+    let (
+        Some(DataType::Array(Arrays::ArrayTwoBool(amp_settle_data))),
+        Some(DataType::Array(Arrays::ArrayTwoBool(charge_recovery_data))),
+        Some(DataType::Array(Arrays::ArrayTwoFloat(stim_data))),
+    ) = (result_out.get("amp_settle_data"), result_out.get("charge_recovery_data"), result_out.get("stim_data")) else {
+        return Ok(None);
+    };
+    let stimulating = stim_data.mapv(|current| current != 0.0);
+    let mask = artifact_mask(&[amp_settle_data.view(), charge_recovery_data.view(), stimulating.view()], pre_samples, post_samples, artifacts.all_channels)?;
+
+    if verbose {
+        println!("Removing stimulation artifacts ({:?})...", artifacts.method);
+    }
+    let method = if artifacts.method == ArtifactMethod::Nan { ArtifactMethod::Zero } else { artifacts.method };
+    if let Some(DataType::Array(Arrays::ArrayTwoFloat(amplifier_data))) = result_out.get_mut("amplifier_data") {
+        remove_artifacts(amplifier_data, mask.view(), method)?;
+    }
+    Ok(Some(mask))
+}
+
+// Filter the amplifier data in 'result_out' into each band in 'options',
+// saving each band under its own name.
+fn derive_bands(header: &RhsHeader, options: &LoadOptions, result_out: &mut HashMap<String, DataType>) -> Result<()> {
+    let band_filters = options.band_filters(header.sample_rate)?;
+    let Some(DataType::Array(Arrays::ArrayTwoFloat(amplifier_data))) = result_out.get("amplifier_data") else {
+        return Ok(());
+    };
+    let pool = options.num_threads.map(thread_pool).transpose()?;
+
+    let mut bands = Vec::new();
+    for (band, filter) in band_filters {
+        if result_out.contains_key(&band.name) {
+            return Err(RhsError::InvalidArgumentError(format!("Band name '{}' is already used for other data", band.name)));
+        }
+        if options.verbose {
+            println!("Filtering amplifier data into {} ({:?})...", band.name, band.band);
+        }
+        let mut band_data = amplifier_data.clone();
+        let mut filter_band = || {
+            if band.zero_phase {
+                filter_channels(&mut band_data, |signal| filter.filter_zero_phase(signal));
+            } else {
+                filter_channels(&mut band_data, |signal| filter.filter(signal));
+            }
+        };
+        match &pool {
+            Some(pool) => pool.install(filter_band),
+            None => filter_band(),
+        }
+        bands.push((band.name.clone(), band_data));
+    }
+
+    for (name, band_data) in bands {
+        result_out.insert(name, DataType::Array(Arrays::ArrayTwoFloat(band_data)));
+    }
+    Ok(())
+}
+
+// Decode stimulation words and scale analog samples and timestamps to
+// physical units. In raw mode, analog samples and timestamps are left exactly
+// as they were stored in the file. Digital words are always left as they are.
+pub(crate) fn parse_data(header: &RhsHeader, data: &mut HashMap<String, Arrays>, raw: bool) {
+    extract_stim_data(data);
+    if !raw {
+        scale_analog_data(header, data);
+        scale_timestamps(header, data);
+    }
+    add_rhd_timestamps(header, data);
+}
+
+fn check_timestamp_gaps(data: &HashMap<String, Arrays>) {
+    if let Some(Arrays::ArrayOne(t)) = data.get("t") {
+        let num_gaps = t.windows(2).into_iter().filter(|window| window[1].wrapping_sub(window[0]) != 1).count();
         if num_gaps == 0 {
             println!("No missing timestamps in data.");
         } else {
             println!("Warning: {} gaps in timestamp data found. Time scale will not be uniform!", num_gaps);
         }
-
-        // Scale time steps (units = seconds).
-        if let DataType::Float(sample_rate) = header["sample_rate"] {
-            *t = t.mapv(|x| (x as f32 / sample_rate) as i32);
-        }
     }
 }
 
-fn scale_analog_data(header: &mut HashMap<String, DataType>, data: &mut HashMap<String, Arrays>) {
-    // Scale amplifier data (units = microVolts).
-    if let Some(Arrays::ArrayTwo(amplifier_data)) = data.get_mut("amplifier_data") {
-        amplifier_data.map_inplace(|x| *x = (0.195 * (*x as f32 - 32768.0)) as i32);
+fn scale_timestamps(header: &RhsHeader, data: &mut HashMap<String, Arrays>) {
+    // Scale time steps (units = seconds). Timestamps are kept in f64 so that
+    // individual samples stay distinguishable in long recordings.
+    if let Some(Arrays::ArrayOne(t)) = data.get("t") {
+        let t = t.mapv(|x| timestamp_sample(header, x) as f64 / header.sample_rate as f64);
+        data.insert("t".to_string(), Arrays::ArrayOneFloat(t));
     }
+}
 
-    // Scale stim data.
-    if let DataType::Float(stim_step_size) = header["stim_step_size"] {
-        if let Some(Arrays::ArrayTwo(stim_data)) = data.get_mut("stim_data") {
-            stim_data.map_inplace(|x| *x = (*x as f32 * stim_step_size) as i32);
-        }
+/// The sample count stored as timestamp `t` in a file with `header`, which
+/// is unsigned in RHD files before version 1.2.
+pub(crate) fn timestamp_sample(header: &RhsHeader, t: i32) -> i64 {
+    if header.timestamps_signed() {
+        t as i64
+    } else {
+        t as u32 as i64
     }
+}
 
-    // Scale DC amplifier data (units = Volts).
-    if let DataType::Bool(dc_amplifier_data_saved) = header["dc_amplifier_data_saved"] {
-        if dc_amplifier_data_saved {
-            if let Some(Arrays::ArrayTwo(dc_amplifier_data)) = data.get_mut("dc_amplifier_data") {
-                dc_amplifier_data.map_inplace(|x| *x = (-0.01923 * (*x as f32 - 512.0)) as i32);
-            }
+fn add_rhd_timestamps(header: &RhsHeader, data: &mut HashMap<String, Arrays>) {
+    // RHD auxiliary inputs are sampled every 4th amplifier sample, supply
+    // voltage and temperature sensors once per data block.
+    if !header.is_rhd() {
+        return;
+    }
+    let step = header.num_samples_per_data_block() as isize;
+    let (t_aux_input, t_supply_voltage) = match data.get("t") {
+        Some(Arrays::ArrayOne(t)) => (Arrays::ArrayOne(t.slice(s![..;4]).to_owned()), Arrays::ArrayOne(t.slice(s![..;step]).to_owned())),
+        Some(Arrays::ArrayOneFloat(t)) => (Arrays::ArrayOneFloat(t.slice(s![..;4]).to_owned()), Arrays::ArrayOneFloat(t.slice(s![..;step]).to_owned())),
+        _ => return,
+    };
+    data.insert("t_aux_input".to_string(), t_aux_input);
+    data.insert("t_supply_voltage".to_string(), t_supply_voltage);
+}
+
+/// Signal name, gain, offset and units converting a raw sample to physical
+/// units as `gain * (raw - offset)`.
+pub type ScaleFactor = (&'static str, f32, f32, &'static str);
+
+/// Scale factors of the analog signals recorded in files with this header.
+/// Timestamps are scaled by `1 / header.sample_rate` instead.
+pub fn scale_factors(header: &RhsHeader) -> Vec<ScaleFactor> {
+    // Amplifier data (units = microVolts).
+    let mut factors: Vec<ScaleFactor> = vec![("amplifier_data", 0.195, 32768.0, "uV")];
+
+    if header.is_rhd() {
+        // Auxiliary input and supply voltage data (units = Volts).
+        factors.push(("aux_input_data", 37.4e-6, 0.0, "V"));
+        factors.push(("supply_voltage_data", 74.8e-6, 0.0, "V"));
+
+        // Temperature sensor data (units = degrees Celsius).
+        factors.push(("temp_sensor_data", 0.01, 0.0, "degC"));
+
+        // Board ADC data (units = Volts). The conversion depends on which
+        // evaluation board recorded the file.
+        match header.eval_board_mode {
+            1 => factors.push(("board_adc_data", 152.59e-6, 32768.0, "V")),
+            13 => factors.push(("board_adc_data", 312.5e-6, 32768.0, "V")),
+            _ => factors.push(("board_adc_data", 50.354e-6, 0.0, "V")),
         }
+        return factors;
     }
 
-    // Scale board ADC data (units = Volts).
-    if let Some(Arrays::ArrayTwo(board_adc_data)) = data.get_mut("board_adc_data") {
-        board_adc_data.map_inplace(|x| *x = (312.5e-6 * (*x as f32 - 32768.0)) as i32);
+    // Stim data (units = microAmps). The step size is stored in Amps.
+    if let Some(stim_parameters) = &header.stim_parameters {
+        factors.push(("stim_data", stim_parameters.stim_step_size / 1.0e-6, 0.0, "uA"));
     }
 
-    // Scale board DAC data (units = Volts).
-    if let Some(Arrays::ArrayTwo(board_dac_data)) = data.get_mut("board_dac_data") {
-        board_dac_data.map_inplace(|x| *x = (312.5e-6 * (*x as f32 - 32768.0)) as i32);
+    // DC amplifier data (units = Volts).
+    if header.dc_amplifier_data_saved {
+        factors.push(("dc_amplifier_data", -0.01923, 512.0, "V"));
     }
+
+    // Board ADC and DAC data (units = Volts).
+    factors.push(("board_adc_data", 312.5e-6, 32768.0, "V"));
+    factors.push(("board_dac_data", 312.5e-6, 32768.0, "V"));
+
+    factors
 }
 
+pub(crate) fn scale_factors_to_result(header: &RhsHeader) -> DataType {
+    let mut result: HashMap<String, DataType> = HashMap::new();
 
-fn extract_digital_data(header: &mut HashMap<String, DataType>, data: &mut HashMap<String, Arrays>) {
-    if let DataType::Int(num_board_dig_in_channels) = header["num_board_dig_in_channels"] {
-        if let DataType::VecChannel(board_dig_in_channels) = &header["board_dig_in_channels"] {
-            if let Some(Arrays::ArrayTwo(board_dig_in_raw)) = data.remove("board_dig_in_raw") {
-                let mut board_dig_in_data = Array2::<i32>::zeros(board_dig_in_raw.dim());
-                for i in 0..num_board_dig_in_channels as usize {
-                    if let Some(DataType::Int(native_order)) = board_dig_in_channels[i].get("native_order") {
-                        let mask = 1 << *native_order as usize;
-                        let mapped = board_dig_in_raw.mapv(|x| if x & mask != 0 { 1 } else { 0 });
-                        board_dig_in_data.row_mut(i).assign(&mapped.index_axis(Axis(0), 0));
-                    }
-                }
-                data.insert("board_dig_in_data".to_string(), Arrays::ArrayTwo(board_dig_in_data));
-            }
-        }
+    let mut insert = |data_key: &str, gain: f32, offset: f32, units: &str| {
+        let mut factor = HashMap::new();
+        factor.insert("gain".to_string(), DataType::Float(gain));
+        factor.insert("offset".to_string(), DataType::Float(offset));
+        factor.insert("units".to_string(), DataType::String(units.to_string()));
+        result.insert(data_key.to_string(), DataType::HashMap(factor));
+    };
+
+    // Timestamps (units = seconds).
+    insert("t", 1.0 / header.sample_rate, 0.0, "s");
+    for (data_key, gain, offset, units) in scale_factors(header) {
+        insert(data_key, gain, offset, units);
     }
 
-    if let DataType::Int(num_board_dig_out_channels) = header["num_board_dig_out_channels"] {
-        if let DataType::VecChannel(board_dig_out_channels) = &header["board_dig_out_channels"] {
-            if let Some(Arrays::ArrayTwo(board_dig_out_raw)) = data.remove("board_dig_out_raw") {
-                let mut board_dig_out_data = Array2::<i32>::zeros(board_dig_out_raw.dim());
-                for i in 0..num_board_dig_out_channels as usize {
-                    if let Some(DataType::Int(native_order)) = board_dig_out_channels[i].get("native_order") {
-                        let mask = 1 << *native_order as usize;
-                        let mapped = board_dig_out_raw.mapv(|x| if x & mask != 0 { 1 } else { 0 });
-                        board_dig_out_data.row_mut(i).assign(&mapped.index_axis(Axis(0), 0));
-                    }
-                }
-                data.insert("board_dig_out_data".to_string(), Arrays::ArrayTwo(board_dig_out_data));
-            }
-        }
+    DataType::HashMap(result)
+}
+
+fn scale_analog_data(header: &RhsHeader, data: &mut HashMap<String, Arrays>) {
+    for (data_key, gain, offset, _) in scale_factors(header) {
+        scale_signal(data, data_key, |x| gain * (x - offset));
     }
+}
 
+fn scale_signal(data: &mut HashMap<String, Arrays>, data_key: &str, scale: impl Fn(f32) -> f32) {
+    // Replace the integer samples with their floating point value in
+    // physical units.
+    let scaled = match data.get(data_key) {
+        Some(Arrays::ArrayTwoU16(signal)) => signal.mapv(|x| scale(x as f32)),
+        Some(Arrays::ArrayTwo(signal)) => signal.mapv(|x| scale(x as f32)),
+        _ => return,
+    };
+    data.insert(data_key.to_string(), Arrays::ArrayTwoFloat(scaled));
 }
 
 fn extract_stim_data(data: &mut HashMap<String, Arrays>) {
-    if let Some(Arrays::ArrayTwo(stim_data_raw)) = data.get_mut("stim_data_raw") {
+    if let Some(Arrays::ArrayTwoU16(stim_data_raw)) = data.get("stim_data_raw") {
         // Interpret 2^15 bit (compliance limit) as true or false.
         let compliance_limit_data = stim_data_raw.mapv(|x| (x & 32768) >= 1);
 
@@ -1294,10 +1794,10 @@ fn extract_stim_data(data: &mut HashMap<String, Arrays>) {
         let amp_settle_data = stim_data_raw.mapv(|x| (x & 8192) >= 1);
 
         // Interpret 2^8 bit (stim polarity) as +1 for 0_bit or -1 for 1_bit.
-        let stim_polarity = stim_data_raw.mapv(|x| 1 - 2 * ((x & 256) >> 8));
+        let stim_polarity = stim_data_raw.mapv(|x| 1 - 2 * ((x as i32 & 256) >> 8));
 
         // Get least-significant 8 bits corresponding to the current amplitude.
-        let curr_amp = stim_data_raw.mapv(|x| x & 255);
+        let curr_amp = stim_data_raw.mapv(|x| x as i32 & 255);
 
         // Multiply current amplitude by the correct sign.
         let stim_data = &curr_amp * &stim_polarity;
@@ -1310,129 +1810,613 @@ fn extract_stim_data(data: &mut HashMap<String, Arrays>) {
     }
 }
 
-fn apply_notch_filter(header: &mut HashMap<String, DataType>, data: &mut HashMap<String, Arrays>) {
-    // If data was not recorded with notch filter turned on, return without
-    // applying notch filter. Similarly, if data was recorded from Intan RHX
-    // software version 3.0 or later, any active notch filter was already
-    // applied to the saved data, so it should not be re-applied.
-    if let DataType::Int(notch_filter_frequency) = &header["notch_filter_frequency"] {
-        if notch_filter_frequency == &0 {
-            return;
-        }
-        if let DataType::HashMap(version) = &header["version"] {
-            if let (DataType::Int(major), DataType::Int(_)) = (&version["major"], &version["minor"]) {
-                if major >= &3 {
-                    return;
-                }
-            }
-        }
+fn apply_notch_filter(header: &RhsHeader, result_out: &mut HashMap<String, DataType>, notch: &NotchOptions, verbose: bool) -> Result<()> {
+    let notch_frequencies = notch.frequencies(header)?;
+    if notch_frequencies.is_empty() {
+        return Ok(());
     }
 
-    // Apply notch filter individually to each channel in order
-    println!("Applying notch filter...");
+    // Apply notch filter individually to each channel in order, removing
+    // each harmonic in turn
+    if verbose {
+        println!("Applying {}notch filter at {:?} Hz...", if notch.zero_phase { "zero-phase " } else { "" }, notch_frequencies);
+    }
+    let filter = if notch.zero_phase { notch_filter_zero_phase } else { notch_filter };
     let print_step = 10;
     let mut percent_done = print_step;
-    if let Some(Arrays::ArrayTwo(amplifier_data)) = data.get_mut("amplifier_data") {
+    if let Some(DataType::Array(Arrays::ArrayTwoFloat(amplifier_data))) = result_out.get_mut("amplifier_data") {
         let num_amplifier_channels = amplifier_data.shape()[0];
         for i in 0..num_amplifier_channels {
-            let channel_data: Vec<f64> = amplifier_data.slice_mut(s![i, ..]).iter().map(|&x| x as f64).collect();
-            if let (DataType::Float(sample_rate), DataType::Float(notch_filter_frequency)) = (&header["sample_rate"], &header["notch_filter_frequency"]) {
-                let result = notch_filter(&channel_data, *sample_rate, *notch_filter_frequency, 10);
-                amplifier_data.slice_mut(s![i, ..]).assign(&Array1::from(result.iter().map(|&x| x as i32).collect::<Vec<i32>>()));
+            let mut channel_data: Vec<f64> = amplifier_data.slice_mut(s![i, ..]).iter().map(|&x| x as f64).collect();
+            for &notch_frequency in &notch_frequencies {
+                channel_data = filter(&channel_data, header.sample_rate, notch_frequency, notch.bandwidth);
             }
+            amplifier_data.slice_mut(s![i, ..]).assign(&Array1::from(channel_data.iter().map(|&x| x as f32).collect::<Vec<f32>>()));
 
-            percent_done = print_progress(i, num_amplifier_channels, print_step, percent_done);
+            if verbose {
+                percent_done = print_progress(i, num_amplifier_channels, print_step, percent_done);
+            }
         }
     }
+    Ok(())
 }
 
-fn notch_filter(signal_in: &Vec<f64>, f_sample: f32, f_notch: f32, bandwidth: i32) -> Vec<f64> {
-    let t_step = 1.0 / f_sample;
-    let f_c = f_notch * t_step;
-    let signal_length = signal_in.len();
-    let iir_parameters = calculate_iir_parameters(bandwidth, t_step, f_c);
-
-    let mut signal_out = vec![0.0; signal_length];
-
-    signal_out[0] = signal_in[0];
-    signal_out[1] = signal_in[1];
-
-    for i in 2..signal_length {
-        signal_out[i] = calculate_iir(i, signal_in, &signal_out, &iir_parameters);
+pub(crate) fn print_progress(current: usize, total: usize, step: usize, percent_done: usize) -> usize {
+    let progress = (current as f64 / total as f64) * 100.0;
+    if progress >= percent_done as f64 {
+        println!("{}% done...", percent_done);
+        return percent_done + step;
     }
-
-    signal_out
+    percent_done
 }
 
-fn calculate_iir_parameters(bandwidth: i32, t_step: f32, f_c: f32) -> HashMap<String, f64> {
-    let f_c = f_c as f64;
-    let bandwidth = bandwidth as f64;
-    let t_step = t_step as f64; 
-    let mut parameters = HashMap::new();
-    let d = (-2.0 * PI * (bandwidth / 2.0) * t_step).exp();
-    let b = (1.0 + d * d) * (2.0 * PI * f_c).cos();
-    let a0 = 1.0;
-    let a1 = -b;
-    let a2 = d * d;
-    let a = (1.0 + d * d) / 2.0;
-    let b0 = 1.0;
-    let b1 = -2.0 * (2.0 * PI * f_c).cos();
-    let b2 = 1.0;
 
-    parameters.insert("d".to_string(), d);
-    parameters.insert("b".to_string(), b);
-    parameters.insert("a0".to_string(), a0);
-    parameters.insert("a1".to_string(), a1);
-    parameters.insert("a2".to_string(), a2);
-    parameters.insert("a".to_string(), a);
-    parameters.insert("b0".to_string(), b0);
-    parameters.insert("b1".to_string(), b1);
-    parameters.insert("b2".to_string(), b2);
-
-    parameters
-}
+/// Errors raised while reading Intan files.
+///
+/// Errors that point at a place in a file carry the byte offset and a short
+/// description of what was being read there.
+#[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
+pub enum RhsError {
+    /// The file could not be opened, read or seeked.
+    IoError { context: String, offset: Option<u64>, source: std::io::Error },
+    /// The file ended in the middle of the header or of a data block.
+    TruncatedFileError { context: String, offset: Option<u64> },
+    /// A file does not hold the number of bytes its header implies.
+    FileSizeError { context: String, size: u64, expected: u64 },
+    /// The file does not start with the RHS or RHD magic number.
+    UnrecognizedFileError { magic_number: u32 },
+    /// A channel in the header has a signal type this reader does not know.
+    UnknownChannelTypeError { offset: u64, signal_type: i16 },
+    /// A QString in the header claims to be longer than the rest of the file.
+    QStringError { offset: u64, length: u32 },
+    /// A header field holds a value this reader does not recognize.
+    HeaderFieldError { field: String, value: i32 },
+    /// A requested channel is not in the header.
+    ChannelNotFoundError { channel: String },
+    /// No amplifier channels in the header are on the requested port.
+    PortNotFoundError { port: String },
+    /// A file cannot be joined to the recording in the first file of a
+    /// series, because their headers differ in `field`.
+    IncompatibleHeaderError { path: String, field: String },
+    /// The data arrays do not match the header they were allocated from.
+    DataArrayError(String),
+    /// The requested data cannot be loaded from this file, e.g. an empty
+    /// sample range.
+    InvalidArgumentError(String),
+}
+
+impl RhsError {
+    /// Record what was being read, and where, when an I/O error does not
+    /// know yet. Errors that already carry a location are left alone.
+    pub(crate) fn at(self, context: impl Into<String>, offset: Option<u64>) -> RhsError {
+        match self {
+            RhsError::IoError { context: old, offset: None, source } if old.is_empty() => RhsError::IoError { context: context.into(), offset, source },
+            RhsError::TruncatedFileError { context: old, offset: None } if old.is_empty() => RhsError::TruncatedFileError { context: context.into(), offset },
+            other => other,
+        }
+    }
 
-fn calculate_iir(i: usize, signal_in: &Vec<f64>, signal_out: &Vec<f64>, iir_parameters: &HashMap<String, f64>) -> f64 {
-    let sample = (
-        iir_parameters["a"] * iir_parameters["b2"] * signal_in[i - 2]
-        + iir_parameters["a"] * iir_parameters["b1"] * signal_in[i - 1]
-        + iir_parameters["a"] * iir_parameters["b0"] * signal_in[i]
-        - iir_parameters["a2"] * signal_out[i - 2]
-        - iir_parameters["a1"] * signal_out[i - 1]
-    ) / iir_parameters["a0"];
+    pub(crate) fn header_field(field: &str, value: impl Into<i32>) -> RhsError {
+        RhsError::HeaderFieldError { field: field.to_string(), value: value.into() }
+    }
 
-    sample
+    pub(crate) fn missing_array(data_key: &str) -> RhsError {
+        RhsError::DataArrayError(format!("'{}' is not in 'data'", data_key))
+    }
 }
 
-fn print_progress(current: usize, total: usize, step: usize, percent_done: usize) -> usize {
-    let progress = (current as f64 / total as f64) * 100.0;
-    if progress >= percent_done as f64 {
-        println!("{}% done...", percent_done);
-        return percent_done + step;
+impl From<std::io::Error> for RhsError {
+    fn from(source: std::io::Error) -> Self {
+        if source.kind() == io::ErrorKind::UnexpectedEof {
+            RhsError::TruncatedFileError { context: String::new(), offset: None }
+        } else {
+            RhsError::IoError { context: String::new(), offset: None, source }
+        }
     }
-    percent_done
 }
 
+impl From<ndarray::ShapeError> for RhsError {
+    fn from(error: ndarray::ShapeError) -> Self {
+        RhsError::DataArrayError(error.to_string())
+    }
+}
 
-#[derive(Debug)]
-pub enum RhsError {
-    UnrecognizedFileError,
-    UnknownChannelTypeError,
-    FileSizeError,
-    QStringError,
-    ChannelNotFoundError,
+// "while reading notes at byte 1234", or as much of it as is known.
+fn location(context: &str, offset: &Option<u64>) -> String {
+    let mut location = String::new();
+    if !context.is_empty() {
+        location.push_str(&format!(" while {}", context));
+    }
+    if let Some(offset) = offset {
+        location.push_str(&format!(" at byte {}", offset));
+    }
+    location
 }
 
 impl std::fmt::Display for RhsError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            RhsError::UnrecognizedFileError => write!(f, "Invalid magic number, not an RHS header file"),
-            RhsError::UnknownChannelTypeError => write!(f, "Channel field in RHS header has an unrecognized signal_type value"),
-            RhsError::FileSizeError => write!(f, "File reading failed due to invalid file size"),
-            RhsError::QStringError => write!(f, "Reading a QString failed because it is too long"),
-            RhsError::ChannelNotFoundError => write!(f, "Specified channel not found when plotting"),
+            RhsError::IoError { context, offset, source } => write!(f, "I/O error{}: {}", location(context, offset), source),
+            RhsError::TruncatedFileError { context, offset } => write!(f, "File ended unexpectedly{}", location(context, offset)),
+            RhsError::FileSizeError { context, size, expected } => write!(f, "{} holds {} bytes, expected {}", context, size, expected),
+            RhsError::UnrecognizedFileError { magic_number } => write!(f, "Unrecognized file type: magic number {:#010x} is neither RHS nor RHD", magic_number),
+            RhsError::UnknownChannelTypeError { offset, signal_type } => write!(f, "Channel at byte {} of the header has an unrecognized signal type {}", offset, signal_type),
+            RhsError::QStringError { offset, length } => write!(f, "QString at byte {} claims {} bytes, past the end of the file", offset, length),
+            RhsError::HeaderFieldError { field, value } => write!(f, "Header field '{}' has an unrecognized value {}", field, value),
+            RhsError::ChannelNotFoundError { channel } => write!(f, "Channel '{}' not found in header", channel),
+            RhsError::PortNotFoundError { port } => write!(f, "No amplifier channels found on port '{}'", port),
+            RhsError::IncompatibleHeaderError { path, field } => write!(f, "Cannot join '{}' to the series: its header differs from the first file in {}", path, field),
+            RhsError::DataArrayError(message) => write!(f, "Unexpected data array layout: {}", message),
+            RhsError::InvalidArgumentError(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for RhsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RhsError::IoError { source, .. } => Some(source),
+            _ => None,
         }
     }
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import_dat::SaveFormat;
+    use crate::test_files::{test_dir, write_rhd, write_rhs, write_session, RhdChannels};
+
+    const RHD_CHANNELS: RhdChannels = RhdChannels { amplifier: 2, aux_input: 3, supply_voltage: 1, temp_sensor: 2 };
+
+    // A distinct value for every stored sample of each RHD signal.
+    fn rhd_sample(data_key: &str, channel: usize, sample: usize) -> u16 {
+        let base = match data_key {
+            "amplifier_data" => 10000,
+            "aux_input_data" => 20000,
+            "supply_voltage_data" => 30000,
+            _ => 40000,
+        };
+        (base + 1000 * channel + sample) as u16
+    }
+
+    fn array<'a>(result: &'a HashMap<String, DataType>, data_key: &str) -> &'a Arrays {
+        match result.get(data_key) {
+            Some(DataType::Array(array)) => array,
+            other => panic!("unexpected {} {:?}", data_key, other),
+        }
+    }
 
-impl std::error::Error for RhsError {}
+    fn u16_array<'a>(result: &'a HashMap<String, DataType>, data_key: &str) -> &'a Array2<u16> {
+        match array(result, data_key) {
+            Arrays::ArrayTwoU16(array) => array,
+            other => panic!("unexpected {} {:?}", data_key, other),
+        }
+    }
+
+    #[test]
+    fn lenient_load_survives_corrupted_timestamps() {
+        // Six blocks, with block 4 holding timestamps that overflow when
+        // subtracted from their neighbours.
+        let mut timestamps: Vec<i32> = (0..6 * 128).collect();
+        for (i, timestamp) in timestamps[4 * 128..5 * 128].iter_mut().enumerate() {
+            *timestamp = if i % 2 == 0 { i32::MIN } else { i32::MAX };
+        }
+        let path = test_dir("corrupted").join("corrupted.rhs");
+        write_rhs(&path, 2, &timestamps, |channel, _| channel as i16);
+
+        let options = LoadOptions { lenient: true, raw: true, ..Default::default() };
+        let (result, data_present) = load_file(path.to_str().unwrap(), &options).unwrap();
+        assert!(data_present);
+        match result.get("corrupted_blocks") {
+            Some(DataType::VecLong(blocks)) => assert_eq!(blocks, &vec![4]),
+            other => panic!("unexpected corrupted_blocks {:?}", other),
+        }
+        match result.get("t") {
+            Some(DataType::Array(Arrays::ArrayOne(t))) => assert_eq!(t.len(), 6 * 128),
+            other => panic!("unexpected t {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rhd_signals_at_their_own_rates() {
+        let path = test_dir("rhd_layout").join("layout.rhd");
+        let timestamps: Vec<u32> = (0..3 * 128).collect();
+        write_rhd(&path, (3, 0), RHD_CHANNELS, &timestamps, rhd_sample);
+
+        let options = LoadOptions { raw: true, ..Default::default() };
+        let (result, _) = load_file(path.to_str().unwrap(), &options).unwrap();
+        for (data_key, num_channels, num_samples) in [
+            ("amplifier_data", 2, 3 * 128),
+            ("aux_input_data", 3, 3 * 32),
+            ("supply_voltage_data", 1, 3),
+            ("temp_sensor_data", 2, 3),
+        ] {
+            let signal = u16_array(&result, data_key);
+            let expected = Array2::from_shape_fn((num_channels, num_samples), |(channel, sample)| rhd_sample(data_key, channel, sample));
+            assert_eq!(signal, &expected, "{}", data_key);
+        }
+        match (array(&result, "t_aux_input"), array(&result, "t_supply_voltage")) {
+            (Arrays::ArrayOne(t_aux_input), Arrays::ArrayOne(t_supply_voltage)) => {
+                assert_eq!(t_aux_input.to_vec(), (0..3 * 128).step_by(4).collect::<Vec<i32>>());
+                assert_eq!(t_supply_voltage.to_vec(), [0, 128, 256]);
+            },
+            other => panic!("unexpected auxiliary timestamps {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rhd_version_1_blocks_and_timestamps() {
+        // Version 1.x files hold 60 samples per block. Timestamps are
+        // unsigned before version 1.2, so these cross 2^31 without wrapping.
+        let first = (1u32 << 31) - 60;
+        let timestamps: Vec<u32> = (first..first + 2 * 60).collect();
+        let path = test_dir("rhd_v1").join("v1_1.rhd");
+        write_rhd(&path, (1, 1), RHD_CHANNELS, &timestamps, rhd_sample);
+
+        let options = LoadOptions { lenient: true, ..Default::default() };
+        let (result, _) = load_file(path.to_str().unwrap(), &options).unwrap();
+        match array(&result, "t") {
+            Arrays::ArrayOneFloat(t) => {
+                assert_eq!(t.len(), 2 * 60);
+                assert_eq!(t[0], first as f64 / 20000.0);
+                assert_eq!(t[119], (first + 119) as f64 / 20000.0);
+            },
+            other => panic!("unexpected t {:?}", other),
+        }
+        match result.get("corrupted_blocks") {
+            Some(DataType::VecLong(blocks)) => assert!(blocks.is_empty()),
+            other => panic!("unexpected corrupted_blocks {:?}", other),
+        }
+        match array(&result, "aux_input_data") {
+            Arrays::ArrayTwoFloat(aux_input_data) => assert_eq!(aux_input_data.dim(), (3, 2 * 15)),
+            other => panic!("unexpected aux_input_data {:?}", other),
+        }
+
+        // From version 1.2 on the same bits are signed.
+        let timestamps: Vec<u32> = (-60..60).map(|t: i32| t as u32).collect();
+        let path = test_dir("rhd_v1").join("v1_2.rhd");
+        write_rhd(&path, (1, 2), RHD_CHANNELS, &timestamps, rhd_sample);
+        let (result, _) = load_file(path.to_str().unwrap(), &LoadOptions::default()).unwrap();
+        match array(&result, "t") {
+            Arrays::ArrayOneFloat(t) => {
+                assert_eq!(t[0], -60.0 / 20000.0);
+                assert_eq!(t[119], 59.0 / 20000.0);
+            },
+            other => panic!("unexpected t {:?}", other),
+        }
+    }
+
+    fn f32_array<'a>(result: &'a HashMap<String, DataType>, data_key: &str) -> &'a Array2<f32> {
+        match array(result, data_key) {
+            Arrays::ArrayTwoFloat(array) => array,
+            other => panic!("unexpected {} {:?}", data_key, other),
+        }
+    }
+
+    fn times(result: &HashMap<String, DataType>) -> &Array1<f64> {
+        match array(result, "t") {
+            Arrays::ArrayOneFloat(t) => t,
+            other => panic!("unexpected t {:?}", other),
+        }
+    }
+
+    #[test]
+    fn sample_range_matches_full_load() {
+        let path = test_dir("sample_range").join("range.rhs");
+        let timestamps: Vec<i32> = (0..4 * 128).collect();
+        write_rhs(&path, 3, &timestamps, |channel, sample| (channel * 1000 + sample) as i16);
+        let path = path.to_str().unwrap();
+
+        let (full, _) = load_file(path, &LoadOptions::default()).unwrap();
+        let options = LoadOptions { start: Some(Position::Sample(130)), stop: Some(Position::Sample(300)), ..Default::default() };
+        let (range, _) = load_file(path, &options).unwrap();
+        assert_eq!(times(&range), &times(&full).slice(s![130..300]));
+        assert_eq!(f32_array(&range, "amplifier_data"), &f32_array(&full, "amplifier_data").slice(s![.., 130..300]));
+
+        // The same range in seconds.
+        let options = LoadOptions { start: Some(Position::Seconds(130.0 / 30000.0)), stop: Some(Position::Seconds(0.01)), ..Default::default() };
+        let (range, _) = load_file(path, &options).unwrap();
+        assert_eq!(times(&range), &times(&full).slice(s![130..300]));
+    }
+
+    #[test]
+    fn rhd_sample_range_covers_whole_blocks() {
+        let path = test_dir("rhd_sample_range").join("range.rhd");
+        let timestamps: Vec<u32> = (0..4 * 128).collect();
+        write_rhd(&path, (3, 0), RHD_CHANNELS, &timestamps, rhd_sample);
+
+        let options = LoadOptions { raw: true, start: Some(Position::Sample(130)), stop: Some(Position::Sample(300)), ..Default::default() };
+        let (result, _) = load_file(path.to_str().unwrap(), &options).unwrap();
+        match array(&result, "t") {
+            Arrays::ArrayOne(t) => assert_eq!(t.to_vec(), (128..384).collect::<Vec<i32>>()),
+            other => panic!("unexpected t {:?}", other),
+        }
+        let expected = Array2::from_shape_fn((2, 256), |(channel, sample)| rhd_sample("amplifier_data", channel, sample + 128));
+        assert_eq!(u16_array(&result, "amplifier_data"), &expected);
+        let expected = Array2::from_shape_fn((3, 64), |(channel, sample)| rhd_sample("aux_input_data", channel, sample + 32));
+        assert_eq!(u16_array(&result, "aux_input_data"), &expected);
+        let expected = Array2::from_shape_fn((1, 2), |(channel, sample)| rhd_sample("supply_voltage_data", channel, sample + 1));
+        assert_eq!(u16_array(&result, "supply_voltage_data"), &expected);
+    }
+
+    fn notch_header(version: (i16, i16), notch_filter_mode: NotchFilterMode, sample_rate: f32) -> RhsHeader {
+        let mut header = RhsHeader { version: Version { major: version.0, minor: version.1 }, sample_rate, ..Default::default() };
+        header.frequency_parameters.notch_filter_mode = notch_filter_mode;
+        header
+    }
+
+    #[test]
+    fn notch_frequencies_follow_header() {
+        let auto = NotchOptions { apply: ApplyNotch::Auto, ..Default::default() };
+        let force = NotchOptions { apply: ApplyNotch::Force, ..Default::default() };
+        let recorded = notch_header((2, 0), NotchFilterMode::Hz60, 30000.0);
+        assert_eq!(auto.frequencies(&recorded).unwrap(), [60.0]);
+        assert_eq!(NotchOptions::default().frequencies(&recorded).unwrap(), Vec::<f32>::new());
+
+        // RHX 3.0 and later saved the data already filtered.
+        let filtered = notch_header((3, 0), NotchFilterMode::Hz50, 30000.0);
+        assert_eq!(auto.frequencies(&filtered).unwrap(), Vec::<f32>::new());
+        assert_eq!(force.frequencies(&filtered).unwrap(), [50.0]);
+
+        // A filter that was off is only applied when forced, at a given
+        // frequency.
+        let off = notch_header((2, 0), NotchFilterMode::Disabled, 30000.0);
+        assert_eq!(auto.frequencies(&off).unwrap(), Vec::<f32>::new());
+        assert!(force.frequencies(&off).is_err());
+        let given = NotchOptions { frequency: Some(50.0), ..force.clone() };
+        assert_eq!(given.frequencies(&off).unwrap(), [50.0]);
+        assert_eq!(given.frequencies(&recorded).unwrap(), [50.0]);
+    }
+
+    #[test]
+    fn notch_harmonics_stop_below_nyquist() {
+        let options = NotchOptions { apply: ApplyNotch::Force, harmonics: 5, ..Default::default() };
+        let header = notch_header((2, 0), NotchFilterMode::Hz60, 30000.0);
+        assert_eq!(options.frequencies(&header).unwrap(), [60.0, 120.0, 180.0, 240.0, 300.0]);
+        // At 500 Hz the Nyquist frequency is 250 Hz, which the fifth harmonic
+        // of 50 Hz would reach.
+        let options = NotchOptions { frequency: Some(50.0), ..options };
+        let header = notch_header((2, 0), NotchFilterMode::Hz60, 500.0);
+        assert_eq!(options.frequencies(&header).unwrap(), [50.0, 100.0, 150.0, 200.0]);
+
+        for invalid in [NotchOptions { harmonics: 0, ..options.clone() }, NotchOptions { bandwidth: 0.0, ..options.clone() }, NotchOptions { frequency: Some(-60.0), ..options }] {
+            assert!(invalid.frequencies(&header).is_err());
+        }
+    }
+
+    #[test]
+    fn lenient_truncated_file() {
+        // Three blocks, the last cut short by 10 bytes.
+        let path = test_dir("lenient_truncated").join("truncated.rhs");
+        write_rhs(&path, 2, &(0..3 * 128).collect::<Vec<i32>>(), |_, _| 0);
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &bytes[..bytes.len() - 10]).unwrap();
+        let path = path.to_str().unwrap();
+
+        assert!(matches!(read_header_info(path, false), Err(RhsError::FileSizeError { .. })));
+        let info = read_header_info(path, true).unwrap();
+        assert_eq!((info.num_blocks, info.num_samples), (2, 2 * 128));
+
+        let options = LoadOptions { lenient: true, ..Default::default() };
+        let (result, _) = load_file(path, &options).unwrap();
+        let bytes_per_block = 128 * 4 + 2 * 2 * 128 * 2;
+        assert!(matches!(result.get("dropped_bytes"), Some(DataType::Long(dropped)) if *dropped == bytes_per_block - 10));
+        assert_eq!(times(&result).len(), 2 * 128);
+    }
+
+    #[test]
+    fn truncated_file_errors() {
+        let path = test_dir("truncated").join("truncated.rhs");
+        write_rhs(&path, 2, &(0..3 * 128).collect::<Vec<i32>>(), |_, _| 0);
+        let bytes = std::fs::read(&path).unwrap();
+        let path = path.to_str().unwrap();
+        let mut fid = open_file(path).unwrap();
+        let header = read_header(&mut fid).unwrap();
+        let header_size = fid.stream_position().unwrap();
+        let bytes_per_block = get_bytes_per_data_block(&header) as u64;
+
+        // The last data block cut short by 10 bytes.
+        std::fs::write(path, &bytes[..bytes.len() - 10]).unwrap();
+        match load_file(path, &LoadOptions::default()) {
+            Err(error @ RhsError::FileSizeError { size, expected, .. }) => {
+                assert_eq!((size, expected), (3 * bytes_per_block - 10, 3 * bytes_per_block));
+                assert_eq!(error.to_string(), format!("Data following the header at byte {} holds {} bytes, expected {}", header_size, size, expected));
+            }
+            other => panic!("unexpected result {:?}", other.map(|_| ())),
+        }
+
+        // The header cut short in the sample rate.
+        std::fs::write(path, &bytes[..10]).unwrap();
+        match load_file(path, &LoadOptions::default()) {
+            Err(error @ RhsError::TruncatedFileError { .. }) => assert_eq!(error.to_string(), "File ended unexpectedly while reading the sample rate at byte 8"),
+            other => panic!("unexpected result {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn end_of_file_check_reports_bytes_read() {
+        // Reading stopped at the end of the header, short of the data blocks.
+        let path = test_dir("end_of_file").join("end_of_file.rhs");
+        write_rhs(&path, 1, &(0..128).collect::<Vec<i32>>(), |_, _| 0);
+        let filesize = std::fs::metadata(&path).unwrap().len();
+        let mut fid = open_file(path.to_str().unwrap()).unwrap();
+        read_header(&mut fid).unwrap();
+        let position = fid.stream_position().unwrap();
+
+        match check_end_of_file(filesize, &mut fid) {
+            Err(error @ RhsError::FileSizeError { size, expected, .. }) => {
+                assert_eq!((size, expected), (position, filesize));
+                assert_eq!(error.to_string(), format!("File read through the last data block holds {} bytes, expected {}", position, filesize));
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+        fid.seek(SeekFrom::End(0)).unwrap();
+        assert!(check_end_of_file(filesize, &mut fid).is_ok());
+    }
+
+    fn channel_names(channels: &[Channel]) -> Vec<&str> {
+        channels.iter().map(|channel| channel.native_channel_name.as_str()).collect()
+    }
+
+    #[test]
+    fn channels_selected_by_name_and_port() {
+        // Four amplifier channels, the last two moved to port B, and three
+        // digital inputs.
+        let dir = test_dir("select_channels");
+        write_session(&dir, SaveFormat::OneFilePerSignalType, 4, 3, 128, |_, _| 0, |_, _| false);
+        let mut header = read_header(&mut open_file(dir.join("info.rhs").to_str().unwrap()).unwrap()).unwrap();
+        for channel in &mut header.amplifier_channels[2..] {
+            channel.port_prefix = "B".to_string();
+            channel.port_name = "Port B".to_string();
+        }
+
+        let select = |channels: &[&str], port: Option<&str>| {
+            let options = LoadOptions {
+                channels: (!channels.is_empty()).then(|| channels.iter().map(|name| name.to_string()).collect()),
+                port: port.map(str::to_string),
+                ..Default::default()
+            };
+            let mut selected_header = header.clone();
+            select_channels(&mut selected_header, &options).map(|channel_rows| (selected_header, channel_rows))
+        };
+
+        let (unselected, channel_rows) = select(&[], None).unwrap();
+        assert!(channel_rows.is_none());
+        assert_eq!(channel_names(&unselected.amplifier_channels), ["A-000", "A-001", "A-002", "A-003"]);
+
+        // Channels keep their order in the file, whatever order they are
+        // asked for in.
+        let (selected, channel_rows) = select(&["DIGITAL-IN-02", "A-003", "A-001"], None).unwrap();
+        assert_eq!(channel_names(&selected.amplifier_channels), ["A-001", "A-003"]);
+        assert_eq!(channel_names(&selected.board_dig_in_channels), ["DIGITAL-IN-02"]);
+        let channel_rows = channel_rows.unwrap();
+        assert_eq!(channel_rows["amplifier_data"], [1, 3]);
+        assert_eq!(channel_rows["stim_data_raw"], [1, 3]);
+        assert!(channel_rows["board_adc_data"].is_empty());
+
+        for port in ["B", "Port B"] {
+            let (selected, _) = select(&[], Some(port)).unwrap();
+            assert_eq!(channel_names(&selected.amplifier_channels), ["A-002", "A-003"]);
+            assert!(selected.board_dig_in_channels.is_empty());
+        }
+        let (selected, _) = select(&["A-000", "A-003"], Some("B")).unwrap();
+        assert_eq!(channel_names(&selected.amplifier_channels), ["A-000", "A-002", "A-003"]);
+
+        match select(&["A-001", "A-009"], None) {
+            Err(error @ RhsError::ChannelNotFoundError { .. }) => assert_eq!(error.to_string(), "Channel 'A-009' not found in header"),
+            other => panic!("unexpected result {:?}", other.map(|_| ())),
+        }
+        match select(&[], Some("C")) {
+            Err(error @ RhsError::PortNotFoundError { .. }) => assert_eq!(error.to_string(), "No amplifier channels found on port 'C'"),
+            other => panic!("unexpected result {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn selected_channels_match_full_load() {
+        let path = test_dir("select_load").join("select.rhs");
+        write_rhs(&path, 4, &(0..2 * 128).collect::<Vec<i32>>(), |channel, sample| (channel * 1000 + sample) as i16);
+        let path = path.to_str().unwrap();
+        let (full, _) = load_file(path, &LoadOptions::default()).unwrap();
+
+        let options = LoadOptions { channels: Some(vec!["A-003".to_string(), "A-001".to_string()]), ..Default::default() };
+        let (selected, _) = load_file(path, &options).unwrap();
+        let full_amplifier = f32_array(&full, "amplifier_data");
+        assert_eq!(f32_array(&selected, "amplifier_data"), &ndarray::stack(Axis(0), &[full_amplifier.row(1), full_amplifier.row(3)]).unwrap());
+        match selected.get("amplifier_channels") {
+            Some(DataType::VecChannel(channels)) => {
+                let names: Vec<&DataType> = channels.iter().map(|channel| &channel["native_channel_name"]).collect();
+                assert!(matches!(names[..], [DataType::String(first), DataType::String(second)] if first == "A-001" && second == "A-003"));
+            },
+            other => panic!("unexpected amplifier_channels {:?}", other),
+        }
+
+        let unknown = LoadOptions { channels: Some(vec!["A-004".to_string()]), ..Default::default() };
+        assert!(matches!(load_file(path, &unknown), Err(RhsError::ChannelNotFoundError { channel }) if channel == "A-004"));
+        let port = LoadOptions { port: Some("B".to_string()), ..Default::default() };
+        assert!(matches!(load_file(path, &port), Err(RhsError::PortNotFoundError { port }) if port == "B"));
+    }
+
+    #[test]
+    fn scaled_signals_follow_scale_factors() {
+        let dir = test_dir("scaled");
+        let rhs = dir.join("scaled.rhs");
+        write_rhs(&rhs, 2, &(0..2 * 128).collect::<Vec<i32>>(), |channel, sample| (channel as i16 * 700) - sample as i16);
+        let rhd = dir.join("scaled.rhd");
+        write_rhd(&rhd, (3, 0), RHD_CHANNELS, &(0..2 * 128).collect::<Vec<u32>>(), rhd_sample);
+
+        for path in [rhs.to_str().unwrap(), rhd.to_str().unwrap()] {
+            let (scaled, _) = load_file(path, &LoadOptions::default()).unwrap();
+            let (raw, _) = load_file(path, &LoadOptions { raw: true, ..Default::default() }).unwrap();
+            let Some(DataType::HashMap(scale_factors)) = raw.get("scale_factors") else {
+                panic!("no scale_factors in {}", path);
+            };
+
+            let mut num_scaled = 0;
+            for (data_key, factor) in scale_factors {
+                let DataType::HashMap(factor) = factor else {
+                    panic!("unexpected scale factor {:?}", factor);
+                };
+                let (Some(DataType::Float(gain)), Some(DataType::Float(offset))) = (factor.get("gain"), factor.get("offset")) else {
+                    panic!("unexpected scale factor {:?}", factor);
+                };
+                let raw_signal: Array2<f32> = match raw.get(data_key) {
+                    Some(DataType::Array(Arrays::ArrayTwoU16(signal))) => signal.mapv(|x| x as f32),
+                    Some(DataType::Array(Arrays::ArrayTwo(signal))) => signal.mapv(|x| x as f32),
+                    Some(DataType::Array(Arrays::ArrayOne(t))) => t.mapv(|x| x as f32).insert_axis(Axis(0)),
+                    _ => continue,
+                };
+                let scaled_signal: Array2<f64> = match scaled.get(data_key) {
+                    Some(DataType::Array(Arrays::ArrayTwoFloat(signal))) => signal.mapv(|x| x as f64),
+                    Some(DataType::Array(Arrays::ArrayOneFloat(t))) => t.clone().insert_axis(Axis(0)),
+                    other => panic!("unexpected scaled {} {:?}", data_key, other),
+                };
+                assert_eq!(raw_signal.dim(), scaled_signal.dim(), "{}", data_key);
+                for (raw_value, scaled_value) in raw_signal.iter().zip(&scaled_signal) {
+                    let expected = *gain as f64 * (*raw_value as f64 - *offset as f64);
+                    assert!((scaled_value - expected).abs() <= 1e-6 * expected.abs().max(1.0), "{}: {} != {}", data_key, scaled_value, expected);
+                }
+                num_scaled += 1;
+            }
+            // Timestamps, amplifier data and, in the RHD file, the auxiliary
+            // inputs, supply voltage and temperature sensors.
+            let expected_scaled = if path.ends_with(".rhd") { 5 } else { 3 };
+            assert_eq!(num_scaled, expected_scaled, "{}", path);
+        }
+
+        // 700 steps of 0.195 uV above zero.
+        let (scaled, _) = load_file(rhs.to_str().unwrap(), &LoadOptions::default()).unwrap();
+        assert!((f32_array(&scaled, "amplifier_data")[[1, 0]] - 136.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn raw_load_keeps_stored_samples() {
+        let path = test_dir("raw").join("raw.rhs");
+        let amplifier = |channel: usize, sample: usize| (channel as i16 * 700) - sample as i16;
+        write_rhs(&path, 2, &(5..5 + 2 * 128).collect::<Vec<i32>>(), amplifier);
+        let (result, _) = load_file(path.to_str().unwrap(), &LoadOptions { raw: true, ..Default::default() }).unwrap();
+
+        let expected = Array2::from_shape_fn((2, 2 * 128), |(channel, sample)| (amplifier(channel, sample) as i32 + 32768) as u16);
+        assert_eq!(u16_array(&result, "amplifier_data"), &expected);
+        match array(&result, "t") {
+            Arrays::ArrayOne(t) => assert_eq!(t.to_vec(), (5..5 + 2 * 128).collect::<Vec<i32>>()),
+            other => panic!("unexpected t {:?}", other),
+        }
+        assert!(!result.values().any(|value| matches!(value, DataType::Array(Arrays::ArrayOneFloat(_) | Arrays::ArrayTwoFloat(_)))));
+
+        let Some(DataType::HashMap(scale_factors)) = result.get("scale_factors") else {
+            panic!("no scale_factors");
+        };
+        let Some(DataType::HashMap(amplifier_factor)) = scale_factors.get("amplifier_data") else {
+            panic!("no amplifier_data scale factor");
+        };
+        assert!(matches!(amplifier_factor.get("gain"), Some(DataType::Float(gain)) if *gain == 0.195));
+        assert!(matches!(amplifier_factor.get("offset"), Some(DataType::Float(offset)) if *offset == 32768.0));
+        assert!(matches!(amplifier_factor.get("units"), Some(DataType::String(units)) if units == "uV"));
+
+        // Scaled loads carry no scale factors.
+        let (scaled, _) = load_file(path.to_str().unwrap(), &LoadOptions::default()).unwrap();
+        assert!(!scaled.contains_key("scale_factors"));
+    }
+}
@@ -0,0 +1,280 @@
+// Standard library imports
+use std::fs::File;
+use std::io::Seek;
+use std::ops::Range;
+
+// External crates
+use byteorder::{ByteOrder, LittleEndian};
+use memmap2::Mmap;
+use ndarray::{Array2, ArrayView1, ArrayView2, ArrayView3, ShapeBuilder};
+
+// Local modules
+use crate::header::RhsHeader;
+use crate::import_hash::{get_bytes_per_data_block, open_file, read_header, RhsError};
+
+/// Where one signal type lives inside each data block of a traditional .rhs
+/// or .rhd file.
+///
+/// Sample `s` of channel `c` in block `b` starts at byte
+/// `offset + b * block_stride + c * channel_stride + s * sample_stride` of the
+/// file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignalLayout {
+    /// Key of the signal, matching the keys returned by `load_file` in raw mode.
+    pub data_key: String,
+    /// Byte offset of the signal in the first data block, from the start of the file.
+    pub offset: usize,
+    pub num_channels: usize,
+    /// Samples per channel in each block. Lower than the amplifier count for
+    /// RHD auxiliary inputs, supply voltage and temperature sensors.
+    pub samples_per_block: usize,
+    /// 4 for timestamps (i32), 2 for everything else (u16).
+    pub bytes_per_sample: usize,
+    pub block_stride: usize,
+    pub channel_stride: usize,
+    pub sample_stride: usize,
+}
+
+/// Work out the layout of every signal type in a data block, for data blocks
+/// starting `data_offset` bytes into the file.
+pub fn block_layout(header: &RhsHeader, data_offset: usize) -> Vec<SignalLayout> {
+    let samples_per_block = header.num_samples_per_data_block();
+    let block_stride = get_bytes_per_data_block(header);
+
+    // Signals in the order they are written to each block, with the number of
+    // channels and samples per channel in a block.
+    let digital_words = |channels: &Vec<_>| if channels.is_empty() { 0 } else { 1 };
+    let mut signals: Vec<(&str, usize, usize, usize)> = vec![("t", 1, samples_per_block, 4)];
+    if header.is_rhd() {
+        signals.extend([
+            ("amplifier_data", header.amplifier_channels.len(), samples_per_block, 2),
+            ("aux_input_data", header.aux_input_channels.len(), samples_per_block / 4, 2),
+            ("supply_voltage_data", header.supply_voltage_channels.len(), 1, 2),
+            ("temp_sensor_data", header.num_temp_sensor_channels, 1, 2),
+            ("board_adc_data", header.board_adc_channels.len(), samples_per_block, 2),
+        ]);
+    } else {
+        let num_amplifier_channels = header.amplifier_channels.len();
+        signals.push(("amplifier_data", num_amplifier_channels, samples_per_block, 2));
+        if header.dc_amplifier_data_saved {
+            signals.push(("dc_amplifier_data", num_amplifier_channels, samples_per_block, 2));
+        }
+        signals.extend([
+            ("stim_data", num_amplifier_channels, samples_per_block, 2),
+            ("board_adc_data", header.board_adc_channels.len(), samples_per_block, 2),
+            ("board_dac_data", header.board_dac_channels.len(), samples_per_block, 2),
+        ]);
+    }
+    signals.extend([
+        ("board_dig_in_raw", digital_words(&header.board_dig_in_channels), samples_per_block, 2),
+        ("board_dig_out_raw", digital_words(&header.board_dig_out_channels), samples_per_block, 2),
+    ]);
+
+    // Within a block each channel's samples are stored contiguously, one
+    // channel after the other.
+    let mut layout = Vec::new();
+    let mut offset = data_offset;
+    for (data_key, num_channels, samples_per_block, bytes_per_sample) in signals {
+        if num_channels > 0 {
+            layout.push(SignalLayout {
+                data_key: data_key.to_string(),
+                offset,
+                num_channels,
+                samples_per_block,
+                bytes_per_sample,
+                block_stride,
+                channel_stride: samples_per_block * bytes_per_sample,
+                sample_stride: bytes_per_sample,
+            });
+        }
+        offset += num_channels * samples_per_block * bytes_per_sample;
+    }
+
+    layout
+}
+
+/// A traditional .rhs or .rhd file mapped into memory.
+///
+/// Signals are exposed as strided views over the mapped bytes, so only the
+/// pages that are actually touched are read from disk. Views are indexed as
+/// `[block, channel, sample in block]` and hold the unscaled samples; use the
+/// `scale_factors` returned by `load_file` in raw mode to convert them.
+pub struct MappedRecording {
+    mmap: Mmap,
+    header: RhsHeader,
+    num_blocks: usize,
+    samples_per_block: usize,
+    layout: Vec<SignalLayout>,
+}
+
+impl MappedRecording {
+    pub fn open(file_path: &str) -> std::result::Result<MappedRecording, RhsError> {
+        let mut fid: File = open_file(file_path)?;
+        let header: RhsHeader = read_header(&mut fid)?;
+        let data_offset = fid.stream_position()? as usize;
+
+        // Safety: the mapping is read-only. As with any memory-mapped file,
+        // the file must not be truncated while the mapping is alive.
+        let mmap = unsafe { Mmap::map(&fid)? };
+
+        let bytes_per_block = get_bytes_per_data_block(&header);
+        let bytes_remaining = mmap.len() - data_offset;
+        if !bytes_remaining.is_multiple_of(bytes_per_block) {
+            return Err(RhsError::FileSizeError {
+                context: format!("Data following the header at byte {}", data_offset),
+                size: bytes_remaining as u64,
+                expected: bytes_remaining.div_ceil(bytes_per_block) as u64 * bytes_per_block as u64,
+            });
+        }
+
+        Ok(MappedRecording {
+            num_blocks: bytes_remaining / bytes_per_block,
+            samples_per_block: header.num_samples_per_data_block(),
+            layout: block_layout(&header, data_offset),
+            mmap,
+            header,
+        })
+    }
+
+    /// The parsed header. Use `RhsHeader::to_dict` for the form `load_file` returns.
+    pub fn header(&self) -> &RhsHeader {
+        &self.header
+    }
+
+    pub fn num_blocks(&self) -> usize {
+        self.num_blocks
+    }
+
+    /// Number of amplifier samples per channel.
+    pub fn num_samples(&self) -> usize {
+        self.num_blocks * self.samples_per_block
+    }
+
+    pub fn layout(&self) -> &[SignalLayout] {
+        &self.layout
+    }
+
+    pub fn signal_layout(&self, data_key: &str) -> std::result::Result<&SignalLayout, RhsError> {
+        self.layout.iter().find(|signal| signal.data_key == data_key)
+            .ok_or_else(|| RhsError::InvalidArgumentError(format!("'{}' is not present in this file", data_key)))
+    }
+
+    /// Timestamps of the data blocks in `blocks`, indexed as
+    /// `[block, sample in block]`. The header only keeps data 16-bit aligned,
+    /// so timestamps are decoded from the mapped bytes rather than viewed in
+    /// place; only the requested blocks are touched. They keep the 32 bits
+    /// stored in the file, which are unsigned in RHD files before version 1.2
+    /// (see `RhsHeader::timestamps_signed`).
+    pub fn timestamps(&self, blocks: Range<usize>) -> std::result::Result<Array2<i32>, RhsError> {
+        let signal = self.signal_layout("t")?;
+        if blocks.end > self.num_blocks {
+            return Err(RhsError::InvalidArgumentError(format!("Blocks {}..{} are out of range for {} blocks", blocks.start, blocks.end, self.num_blocks)));
+        }
+
+        let mut timestamps = Array2::zeros((blocks.len(), signal.samples_per_block));
+        for (mut row, block) in timestamps.rows_mut().into_iter().zip(blocks) {
+            let start = signal.offset + block * signal.block_stride;
+            let bytes = &self.mmap[start..start + signal.channel_stride];
+            row.assign(&ArrayView1::from(&bytes.chunks_exact(4).map(LittleEndian::read_i32).collect::<Vec<i32>>()));
+        }
+        Ok(timestamps)
+    }
+
+    /// A 16-bit signal, indexed as `[block, channel, sample in block]`.
+    pub fn signal(&self, data_key: &str) -> std::result::Result<ArrayView3<'_, u16>, RhsError> {
+        let signal = self.signal_layout(data_key)?;
+        if signal.bytes_per_sample != 2 {
+            return Err(RhsError::InvalidArgumentError(format!("'{}' is not a 16-bit signal", data_key)));
+        }
+        self.view(signal)
+    }
+
+    /// One channel of a 16-bit signal, indexed as `[block, sample in block]`.
+    pub fn channel(&self, data_key: &str, channel: usize) -> std::result::Result<ArrayView2<'_, u16>, RhsError> {
+        let view = self.signal(data_key)?;
+        if channel >= view.len_of(ndarray::Axis(1)) {
+            return Err(RhsError::InvalidArgumentError(format!("Channel {} is out of range for '{}'", channel, data_key)));
+        }
+        Ok(view.index_axis_move(ndarray::Axis(1), channel))
+    }
+
+    fn view<T>(&self, signal: &SignalLayout) -> std::result::Result<ArrayView3<'_, T>, RhsError> {
+        let element_size = std::mem::size_of::<T>();
+        let ptr = self.mmap.as_ptr().wrapping_add(signal.offset);
+        if !(ptr as usize).is_multiple_of(std::mem::align_of::<T>()) || !signal.block_stride.is_multiple_of(element_size) {
+            return Err(RhsError::DataArrayError(format!("'{}' is not aligned for a zero-copy view", signal.data_key)));
+        }
+
+        let shape = (self.num_blocks, signal.num_channels, signal.samples_per_block);
+        let strides = (signal.block_stride / element_size, signal.channel_stride / element_size, signal.sample_stride / element_size);
+        // Safety: the layout keeps every element of the view inside the
+        // mapping, which lives as long as the returned view borrows `self`.
+        Ok(unsafe { ArrayView3::from_shape_ptr(shape.strides(strides), ptr as *const T) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    use crate::import_hash::{load_file, Arrays, DataType, LoadOptions};
+    use crate::test_files::{test_dir, write_rhd, write_rhs, RhdChannels};
+
+    // Every mapped signal, and the timestamps, against `load_file` in raw mode.
+    fn check_against_load_file(path: &str, data_keys: &[&str]) {
+        let recording = MappedRecording::open(path).unwrap();
+        let options = LoadOptions { raw: true, ..Default::default() };
+        let (result, _) = load_file(path, &options).unwrap();
+        let array = |data_key: &str| match result.get(data_key) {
+            Some(DataType::Array(array)) => array,
+            other => panic!("unexpected {} {:?}", data_key, other),
+        };
+
+        let num_blocks = recording.num_blocks();
+        let timestamps = recording.timestamps(0..num_blocks).unwrap();
+        match array("t") {
+            Arrays::ArrayOne(t) => assert_eq!(timestamps.iter().copied().collect::<Vec<i32>>(), t.to_vec()),
+            other => panic!("unexpected t {:?}", other),
+        }
+
+        for &data_key in data_keys {
+            let view = recording.signal(data_key).unwrap();
+            let (_, num_channels, samples_per_block) = view.dim();
+            let expected = match array(data_key) {
+                Arrays::ArrayTwoU16(expected) => expected,
+                other => panic!("unexpected {} {:?}", data_key, other),
+            };
+            assert_eq!(expected.dim(), (num_channels, num_blocks * samples_per_block), "{}", data_key);
+            for ((block, channel, sample), value) in view.indexed_iter() {
+                assert_eq!(*value, expected[[channel, block * samples_per_block + sample]], "{}", data_key);
+            }
+            let channel = recording.channel(data_key, num_channels - 1).unwrap();
+            assert_eq!(channel.iter().copied().collect::<Vec<u16>>(), expected.row(num_channels - 1).to_vec(), "{}", data_key);
+        }
+    }
+
+    #[test]
+    fn rhs_views_match_load_file() {
+        let path = test_dir("mmap_rhs").join("mapped.rhs");
+        write_rhs(&path, 3, &(0..4 * 128).collect::<Vec<i32>>(), |channel, sample| (channel * 1000 + sample) as i16 - 300);
+        let path = path.to_str().unwrap();
+        check_against_load_file(path, &["amplifier_data"]);
+
+        let recording = MappedRecording::open(path).unwrap();
+        assert_eq!((recording.num_blocks(), recording.num_samples()), (4, 4 * 128));
+        assert!(matches!(recording.signal("t"), Err(RhsError::InvalidArgumentError(_))));
+        assert!(matches!(recording.signal("board_adc_data"), Err(RhsError::InvalidArgumentError(_))));
+        assert!(matches!(recording.channel("amplifier_data", 3), Err(RhsError::InvalidArgumentError(_))));
+        assert!(matches!(recording.timestamps(0..5), Err(RhsError::InvalidArgumentError(_))));
+    }
+
+    #[test]
+    fn rhd_views_match_load_file() {
+        let path = test_dir("mmap_rhd").join("mapped.rhd");
+        let channels = RhdChannels { amplifier: 2, aux_input: 3, supply_voltage: 1, temp_sensor: 2 };
+        let samples: HashMap<&str, usize> = HashMap::from([("amplifier_data", 10000), ("aux_input_data", 20000), ("supply_voltage_data", 30000), ("temp_sensor_data", 40000)]);
+        write_rhd(&path, (3, 0), channels, &(0..3 * 128).collect::<Vec<u32>>(), |data_key, channel, sample| (samples[data_key] + 1000 * channel + sample) as u16);
+        check_against_load_file(path.to_str().unwrap(), &["amplifier_data", "aux_input_data", "supply_voltage_data", "temp_sensor_data"]);
+    }
+}
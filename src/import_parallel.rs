@@ -0,0 +1,241 @@
+// Standard library imports
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek};
+use std::ops::Range;
+
+// External crates
+use byteorder::{ByteOrder, LittleEndian};
+use ndarray::{ArrayViewMut1, ArrayViewMut2, Axis};
+use rayon::prelude::*;
+use rayon::ThreadPool;
+
+// Local modules
+use crate::header::RhsHeader;
+use crate::import_hash::{clip_block, get_bytes_per_data_block, print_progress, Arrays, ChannelRows, RhsError};
+use crate::import_mmap::{block_layout, SignalLayout};
+
+// Number of data blocks each thread decodes per chunk. Blocks are read from
+// disk a chunk at a time, so the raw bytes never take more memory than one
+// chunk.
+const BLOCKS_PER_THREAD: usize = 64;
+
+// The part of one output array that a single data block fills.
+enum BlockDest<'a> {
+    Timestamps(ArrayViewMut1<'a, i32>),
+    // Analog signals, and the 16-bit digital words shared by all digital
+    // channels, kept in a single row (or none if no channel was selected).
+    Analog(ArrayViewMut2<'a, u16>),
+}
+
+struct SignalJob<'a> {
+    signal: &'a SignalLayout,
+    rows: Option<&'a [usize]>,
+    source: Range<usize>,
+    dest: BlockDest<'a>,
+}
+
+/// Start a pool of `num_threads` threads (one per core if 0) to decode data
+/// blocks on.
+pub(crate) fn thread_pool(num_threads: usize) -> std::result::Result<ThreadPool, RhsError> {
+    rayon::ThreadPoolBuilder::new().num_threads(num_threads).build()
+        .map_err(|e| RhsError::InvalidArgumentError(format!("Could not start {} decoding threads: {}", num_threads, e)))
+}
+
+/// Read `num_blocks` data blocks starting at the current position of `fid`
+/// and decode them on the threads of `pool` into `data`.
+///
+/// This fills the same arrays, at the same positions, as calling
+/// `read_one_data_block` for each block: the first block belongs at `index`
+/// and the rest follow it. Since every block has the same size and a known
+/// place in the output, blocks are decoded concurrently into disjoint slices
+/// of the output arrays.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn read_data_blocks_parallel(data: &mut HashMap<String, Arrays>, header: &RhsHeader, channel_rows: Option<&ChannelRows>, index: i64, num_blocks: usize, pool: &ThreadPool, progress: bool, fid: &mut File) -> std::result::Result<(), RhsError> {
+    let samples_per_block = header.num_samples_per_data_block();
+    let bytes_per_block = get_bytes_per_data_block(header);
+    let layout = block_layout(header, 0);
+
+    let blocks_per_chunk = pool.current_num_threads() * BLOCKS_PER_THREAD;
+    let mut buffer: Vec<u8> = Vec::new();
+    let print_step = 10;
+    let mut percent_done = print_step;
+    let mut block = 0;
+    while block < num_blocks {
+        let chunk_blocks = blocks_per_chunk.min(num_blocks - block);
+        buffer.resize(chunk_blocks * bytes_per_block, 0);
+        let offset = fid.stream_position()?;
+        fid.read_exact(&mut buffer)
+            .map_err(|e| RhsError::from(e).at(format!("reading {} data blocks", chunk_blocks), Some(offset)))?;
+
+        let chunk_index = index + (block * samples_per_block) as i64;
+        let jobs = split_into_blocks(data, &layout, channel_rows, chunk_index, chunk_blocks, samples_per_block)?;
+        pool.install(|| {
+            jobs.into_par_iter()
+                .zip(buffer.par_chunks(bytes_per_block))
+                .for_each(|(block_jobs, bytes)| decode_block(block_jobs, bytes));
+        });
+
+        block += chunk_blocks;
+        if progress {
+            percent_done = print_progress(block, num_blocks, print_step, percent_done);
+        }
+    }
+
+    Ok(())
+}
+
+// Split every output array into the slices filled by each of `num_blocks`
+// blocks, the first of which belongs at `index`.
+fn split_into_blocks<'a>(data: &'a mut HashMap<String, Arrays>, layout: &'a [SignalLayout], channel_rows: Option<&'a ChannelRows>, index: i64, num_blocks: usize, samples_per_block: usize) -> std::result::Result<Vec<Vec<SignalJob<'a>>>, RhsError> {
+    let mut jobs: Vec<Vec<SignalJob<'a>>> = (0..num_blocks).map(|_| Vec::new()).collect();
+
+    for (data_key, array) in data.iter_mut() {
+        // Stimulation words are decoded into 'stim_data' later; the file's
+        // stimulation signal fills 'stim_data_raw'.
+        let signal_key = match data_key.as_str() {
+            "stim_data" => continue,
+            "stim_data_raw" => "stim_data",
+            other => other,
+        };
+        let Some(signal) = layout.iter().find(|signal| signal.data_key == signal_key) else {
+            continue;
+        };
+        let rows = channel_rows.and_then(|channel_rows| channel_rows.get(data_key)).map(Vec::as_slice);
+
+        // Signals sampled below the amplifier rate (RHD auxiliary inputs,
+        // supply voltage and temperature) advance by fewer samples per block.
+        let signal_index = index * signal.samples_per_block as i64 / samples_per_block as i64;
+        let block_index = |block: usize| signal_index + (block * signal.samples_per_block) as i64;
+
+        match array {
+            Arrays::ArrayOne(t) => {
+                let len = t.len();
+                let (_, start) = clip_block(block_index(0), signal.samples_per_block, len);
+                let (_, mut rest) = t.view_mut().split_at(Axis(0), start);
+                for (block, block_jobs) in jobs.iter_mut().enumerate() {
+                    let (source, _) = clip_block(block_index(block), signal.samples_per_block, len);
+                    let (dest, tail) = rest.split_at(Axis(0), source.len());
+                    rest = tail;
+                    block_jobs.push(SignalJob { signal, rows, source, dest: BlockDest::Timestamps(dest) });
+                }
+            },
+            Arrays::ArrayTwoU16(array) => {
+                let len = array.ncols();
+                let (_, start) = clip_block(block_index(0), signal.samples_per_block, len);
+                let (_, mut rest) = array.view_mut().split_at(Axis(1), start);
+                for (block, block_jobs) in jobs.iter_mut().enumerate() {
+                    let (source, _) = clip_block(block_index(block), signal.samples_per_block, len);
+                    let (dest, tail) = rest.split_at(Axis(1), source.len());
+                    rest = tail;
+                    block_jobs.push(SignalJob { signal, rows, source, dest: BlockDest::Analog(dest) });
+                }
+            },
+            _ => return Err(RhsError::DataArrayError(format!("Unexpected array type for '{}'", data_key))),
+        }
+    }
+
+    Ok(jobs)
+}
+
+fn decode_block(block_jobs: Vec<SignalJob>, bytes: &[u8]) {
+    for job in block_jobs {
+        let signal = job.signal;
+        let sample_bytes = |channel: usize, sample: usize| {
+            let start = signal.offset + channel * signal.channel_stride + sample * signal.sample_stride;
+            &bytes[start..start + signal.bytes_per_sample]
+        };
+
+        match job.dest {
+            BlockDest::Timestamps(mut dest) => {
+                for (value, sample) in dest.iter_mut().zip(job.source.clone()) {
+                    *value = LittleEndian::read_i32(sample_bytes(0, sample));
+                }
+            },
+            BlockDest::Analog(mut dest) => {
+                for (dest_row, mut row) in dest.rows_mut().into_iter().enumerate() {
+                    let channel = job.rows.map_or(dest_row, |rows| rows[dest_row]);
+                    for (value, sample) in row.iter_mut().zip(job.source.clone()) {
+                        *value = LittleEndian::read_u16(sample_bytes(channel, sample));
+                    }
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    use crate::import_hash::{initialize_memory, open_file, read_data_blocks, read_header, select_channels, LoadOptions};
+    use crate::test_files::{test_dir, write_rhd, write_rhs, RhdChannels};
+
+    // Enough blocks for several chunks on two threads, ending partway
+    // through a chunk.
+    const NUM_BLOCKS: usize = 5 * BLOCKS_PER_THREAD + 7;
+
+    // Read `sample_range` of the file at `path` with the channels selected
+    // by `options`, one block at a time or on a pool of two threads.
+    fn read(path: &PathBuf, options: &LoadOptions, sample_range: Range<u64>, parallel: bool) -> HashMap<String, Arrays> {
+        let mut fid = open_file(path).unwrap();
+        let header = read_header(&mut fid).unwrap();
+        let mut selected_header = header.clone();
+        let channel_rows = select_channels(&mut selected_header, options).unwrap();
+        let (mut data, _) = initialize_memory(&selected_header, sample_range.end - sample_range.start);
+        let pool = thread_pool(2).unwrap();
+        let pool = if parallel { Some(&pool) } else { None };
+        read_data_blocks(&mut data, &header, channel_rows.as_ref(), &sample_range, pool, false, &mut fid).unwrap();
+        data
+    }
+
+    fn assert_same_data(path: &PathBuf, options: &LoadOptions, sample_range: Range<u64>) {
+        let sequential = read(path, options, sample_range.clone(), false);
+        let parallel = read(path, options, sample_range, true);
+        assert_eq!(sequential.len(), parallel.len());
+        for (data_key, expected) in &sequential {
+            match (expected, &parallel[data_key]) {
+                (Arrays::ArrayOne(expected), Arrays::ArrayOne(actual)) => assert_eq!(expected, actual, "{}", data_key),
+                (Arrays::ArrayTwo(expected), Arrays::ArrayTwo(actual)) => assert_eq!(expected, actual, "{}", data_key),
+                (Arrays::ArrayTwoU16(expected), Arrays::ArrayTwoU16(actual)) => assert_eq!(expected, actual, "{}", data_key),
+                (expected, actual) => panic!("{}: {:?} read as {:?}", data_key, expected, actual),
+            }
+        }
+    }
+
+    fn write_test_rhs(name: &str) -> PathBuf {
+        let path = test_dir(name).join("blocks.rhs");
+        let timestamps: Vec<i32> = (0..(NUM_BLOCKS * 128) as i32).collect();
+        write_rhs(&path, 4, &timestamps, |channel, sample| (channel * 1000 + sample % 997) as i16);
+        path
+    }
+
+    #[test]
+    fn parallel_matches_sequential() {
+        let path = write_test_rhs("parallel_all");
+        assert_same_data(&path, &LoadOptions::default(), 0..(NUM_BLOCKS * 128) as u64);
+    }
+
+    #[test]
+    fn parallel_matches_sequential_for_selected_channels_and_range() {
+        // Start and stop partway through blocks, so that the first and last
+        // blocks are clipped.
+        let path = write_test_rhs("parallel_selection");
+        let options = LoadOptions { channels: Some(vec!["A-003".to_string(), "A-001".to_string()]), ..Default::default() };
+        assert_same_data(&path, &options, 130..(NUM_BLOCKS * 128 - 50) as u64);
+        assert_same_data(&path, &LoadOptions::default(), 70..100);
+    }
+
+    #[test]
+    fn parallel_matches_sequential_for_rhd() {
+        // Auxiliary inputs at 1/4 of the amplifier rate, supply voltage and
+        // temperature once per block.
+        let path = test_dir("parallel_rhd").join("blocks.rhd");
+        let timestamps: Vec<u32> = (0..(NUM_BLOCKS * 128) as u32).collect();
+        let channels = RhdChannels { amplifier: 3, aux_input: 3, supply_voltage: 1, temp_sensor: 2 };
+        write_rhd(&path, (3, 0), channels, &timestamps, |_, channel, sample| (channel * 1000 + sample % 997) as u16);
+        assert_same_data(&path, &LoadOptions::default(), 0..(NUM_BLOCKS * 128) as u64);
+        assert_same_data(&path, &LoadOptions::default(), 3 * 128..(NUM_BLOCKS - 2) as u64 * 128);
+    }
+}
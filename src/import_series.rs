@@ -0,0 +1,309 @@
+// Standard library imports
+use std::collections::HashMap;
+use std::fs::File;
+
+// External crates
+use ndarray::{concatenate, Array, ArrayView, Axis, RemoveAxis};
+
+// Local modules
+use crate::header::RhsHeader;
+use crate::import_hash::{calculate_data_size, filter_amplifier_data, load_file, open_file, read_header, timestamp_sample, Arrays, ArtifactOptions, DataType, LoadOptions, NotchOptions, Position, RhsError};
+
+/// Find the files of a series from a glob pattern such as
+/// `rat1_240101_*.rhs`, in name order. RHX names each file after the time it
+/// was started, so name order is recording order.
+pub fn find_file_series(pattern: &str) -> std::result::Result<Vec<String>, RhsError> {
+    let paths = glob::glob(pattern).map_err(|e| RhsError::InvalidArgumentError(format!("Invalid file pattern '{}': {}", pattern, e)))?;
+
+    let mut file_paths = Vec::new();
+    for path in paths {
+        let path = path.map_err(|e| RhsError::from(std::io::Error::from(e)))?;
+        file_paths.push(path.to_string_lossy().into_owned());
+    }
+    if file_paths.is_empty() {
+        return Err(RhsError::InvalidArgumentError(format!("No files match '{}'", pattern)));
+    }
+
+    Ok(file_paths)
+}
+
+/// Load a recording split across consecutive traditional .rhs or .rhd files,
+/// such as those written by RHX's "new file every N minutes" option.
+///
+/// Every file must have been recorded with the same channels and settings as
+/// the first (see `RhsHeader::incompatible_field`). Their data is joined along
+/// time and returned under the same keys as `load_file`, with the header of
+/// the first file loaded. `start` and `stop` count from the first sample of
+/// the first file, and `corrupted_blocks` counts blocks from the start of the
+/// first file.
+///
+/// Two more keys describe the series. `series_files` lists each file that was
+/// loaded, with the index of its first sample in the joined data and its
+/// number of samples. `timestamp_gaps` lists each file whose first timestamp
+/// does not follow on from the last timestamp of the file before it, with the
+/// number of samples missing in between (negative if the timestamps overlap or
+/// start over). Both keys are present even when no file holds any data.
+pub fn load_file_series(file_paths: &[String], options: &LoadOptions) -> std::result::Result<(HashMap<String, DataType>, bool), RhsError> {
+    if file_paths.is_empty() {
+        return Err(RhsError::InvalidArgumentError("No files given to load".to_string()));
+    }
+
+    // Check every header against the first before reading any data.
+    let mut headers: Vec<(RhsHeader, u64)> = Vec::new();
+    for file_path in file_paths {
+        let mut fid: File = open_file(file_path)?;
+        let header: RhsHeader = read_header(&mut fid)?;
+        let data_size = calculate_data_size(&header, file_path, options.lenient, &mut fid)?;
+        if let Some((first_header, _)) = headers.first() {
+            if let Some(field) = first_header.incompatible_field(&header) {
+                return Err(RhsError::IncompatibleHeaderError { path: file_path.clone(), field: field.to_string() });
+            }
+        }
+        headers.push((header, data_size.num_samples));
+    }
+
+    let first_header = &headers[0].0;
+    options.check_filters(first_header)?;
+    let samples_per_block = first_header.num_samples_per_data_block() as u64;
+    let total_samples: u64 = headers.iter().map(|(_, num_samples)| num_samples).sum();
+    let data_present = total_samples > 0;
+    let sample_range = if data_present {
+        options.sample_range(total_samples, first_header.sample_rate)?
+    } else {
+        0..0
+    };
+
+    let mut result_out: HashMap<String, DataType> = HashMap::new();
+    let mut parts: HashMap<String, Vec<Arrays>> = HashMap::new();
+    let mut series_files: Vec<HashMap<String, DataType>> = Vec::new();
+    let mut timestamp_gaps: Vec<HashMap<String, DataType>> = Vec::new();
+    let mut dropped_bytes = 0;
+    let mut corrupted_blocks: Vec<i64> = Vec::new();
+    let mut last_timestamp: Option<i64> = None;
+    let mut file_start = 0;
+    let mut first_sample = 0;
+    for (i, (file_path, (_, num_samples))) in file_paths.iter().zip(&headers).enumerate() {
+        // The part of the requested range that falls in this file.
+        let file_offset = file_start;
+        file_start += num_samples;
+        let file_range = sample_range.start.max(file_offset)..sample_range.end.min(file_start);
+        // Files without data are still loaded for their headers when no
+        // file in the series holds any data.
+        if file_range.is_empty() && data_present {
+            continue;
+        }
+
+        if options.verbose {
+            println!("\nLoading file {} of {}: {}", i + 1, file_paths.len(), file_path);
+        }
+        // Artifacts and filters are left until the files are joined, so
+        // that the filters run over the series as one continuous signal.
+        let file_options = LoadOptions {
+            start: Some(Position::Sample(file_range.start - file_offset)),
+            stop: Some(Position::Sample(file_range.end - file_offset)),
+            notch: NotchOptions::default(),
+            bands: Vec::new(),
+            artifacts: ArtifactOptions::default(),
+            ..options.clone()
+        };
+        let (file_result, _) = load_file(file_path, &file_options)?;
+
+        let mut file_samples = 0;
+        for (key, value) in file_result {
+            match (key.as_str(), value) {
+                ("t", DataType::Array(t)) => {
+                    if let Some((first, last)) = timestamp_bounds(&t, first_header) {
+                        if let Some(last_timestamp) = last_timestamp {
+                            let missing_samples = first - last_timestamp - 1;
+                            if missing_samples != 0 {
+                                if options.verbose {
+                                    println!("Warning: {} samples missing before {}", missing_samples, file_path);
+                                }
+                                timestamp_gaps.push(timestamp_gap(file_path, first_sample, missing_samples));
+                            }
+                        }
+                        last_timestamp = Some(last);
+                    }
+                    file_samples = num_timestamps(&t);
+                    parts.entry(key).or_default().push(t);
+                },
+                (_, DataType::Array(array)) => parts.entry(key).or_default().push(array),
+                ("dropped_bytes", DataType::Long(bytes)) => dropped_bytes += bytes,
+                ("corrupted_blocks", DataType::VecLong(blocks)) => {
+                    let first_block = (file_offset / samples_per_block) as i64;
+                    corrupted_blocks.extend(blocks.iter().map(|block| block + first_block));
+                },
+                (_, value) => {
+                    result_out.entry(key).or_insert(value);
+                },
+            }
+        }
+
+        series_files.push(series_file(file_path, first_sample, file_samples));
+        first_sample += file_samples as u64;
+    }
+
+    if options.verbose && timestamp_gaps.is_empty() {
+        println!("No missing timestamps between files.");
+    }
+
+    for (data_key, data_parts) in parts {
+        let joined = concatenate_arrays(&data_key, &data_parts)?;
+        result_out.insert(data_key, DataType::Array(joined));
+    }
+    if !options.raw {
+        filter_amplifier_data(first_header, options, &mut result_out)?;
+    }
+    if options.lenient {
+        result_out.insert("dropped_bytes".to_string(), DataType::Long(dropped_bytes));
+        result_out.insert("corrupted_blocks".to_string(), DataType::VecLong(corrupted_blocks));
+    }
+    result_out.insert("series_files".to_string(), DataType::VecChannel(series_files));
+    result_out.insert("timestamp_gaps".to_string(), DataType::VecChannel(timestamp_gaps));
+
+    Ok((result_out, data_present))
+}
+
+// First and last timestamp of a file, as the sample counts stored in the file.
+fn timestamp_bounds(t: &Arrays, header: &RhsHeader) -> Option<(i64, i64)> {
+    let sample_rate = header.sample_rate;
+    match t {
+        Arrays::ArrayOne(t) => Some((timestamp_sample(header, *t.first()?), timestamp_sample(header, *t.last()?))),
+        // Scaled timestamps are in seconds.
+        Arrays::ArrayOneFloat(t) => {
+            let to_sample = |x: f64| (x * sample_rate as f64).round() as i64;
+            Some((to_sample(*t.first()?), to_sample(*t.last()?)))
+        },
+        _ => None,
+    }
+}
+
+fn num_timestamps(t: &Arrays) -> usize {
+    match t {
+        Arrays::ArrayOne(t) => t.len(),
+        Arrays::ArrayOneFloat(t) => t.len(),
+        _ => 0,
+    }
+}
+
+fn series_file(file_path: &str, first_sample: u64, num_samples: usize) -> HashMap<String, DataType> {
+    let mut file = HashMap::new();
+    file.insert("path".to_string(), DataType::String(file_path.to_string()));
+    file.insert("first_sample".to_string(), DataType::Long(first_sample as i64));
+    file.insert("num_samples".to_string(), DataType::Long(num_samples as i64));
+    file
+}
+
+fn timestamp_gap(file_path: &str, sample: u64, missing_samples: i64) -> HashMap<String, DataType> {
+    let mut gap = HashMap::new();
+    gap.insert("path".to_string(), DataType::String(file_path.to_string()));
+    gap.insert("sample".to_string(), DataType::Long(sample as i64));
+    gap.insert("missing_samples".to_string(), DataType::Long(missing_samples));
+    gap
+}
+
+// Join the parts of one signal from consecutive files along time, the last
+// axis of every array.
+fn concatenate_arrays(data_key: &str, parts: &[Arrays]) -> std::result::Result<Arrays, RhsError> {
+    Ok(match &parts[0] {
+        Arrays::ArrayOne(_) => Arrays::ArrayOne(join(data_key, parts, Axis(0), |part| match part { Arrays::ArrayOne(array) => Some(array.view()), _ => None })?),
+        Arrays::ArrayOneFloat(_) => Arrays::ArrayOneFloat(join(data_key, parts, Axis(0), |part| match part { Arrays::ArrayOneFloat(array) => Some(array.view()), _ => None })?),
+        Arrays::ArrayTwo(_) => Arrays::ArrayTwo(join(data_key, parts, Axis(1), |part| match part { Arrays::ArrayTwo(array) => Some(array.view()), _ => None })?),
+        Arrays::ArrayTwoBool(_) => Arrays::ArrayTwoBool(join(data_key, parts, Axis(1), |part| match part { Arrays::ArrayTwoBool(array) => Some(array.view()), _ => None })?),
+        Arrays::ArrayTwoU16(_) => Arrays::ArrayTwoU16(join(data_key, parts, Axis(1), |part| match part { Arrays::ArrayTwoU16(array) => Some(array.view()), _ => None })?),
+        Arrays::ArrayTwoFloat(_) => Arrays::ArrayTwoFloat(join(data_key, parts, Axis(1), |part| match part { Arrays::ArrayTwoFloat(array) => Some(array.view()), _ => None })?),
+    })
+}
+
+fn join<'a, A: Clone + 'a, D: RemoveAxis + 'a>(data_key: &str, parts: &'a [Arrays], axis: Axis, view: impl Fn(&'a Arrays) -> Option<ArrayView<'a, A, D>>) -> std::result::Result<Array<A, D>, RhsError> {
+    let views = parts.iter()
+        .map(|part| view(part).ok_or_else(|| RhsError::DataArrayError(format!("'{}' has a different type in each file", data_key))))
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    Ok(concatenate(axis, &views)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_files::{test_dir, write_rhs};
+
+    #[test]
+    fn timestamp_gaps_beyond_i32_are_kept() {
+        let dir = test_dir("series_gap");
+        let first: Vec<i32> = (0..128).map(|i| i32::MIN + i).collect();
+        let second: Vec<i32> = (0..128).map(|i| i32::MAX - 127 + i).collect();
+        let file_paths: Vec<String> = [first, second].iter().enumerate().map(|(i, timestamps)| {
+            let path = dir.join(format!("rec_{:02}.rhs", i));
+            write_rhs(&path, 1, timestamps, |_, _| 0);
+            path.to_string_lossy().into_owned()
+        }).collect();
+
+        let options = LoadOptions { raw: true, ..Default::default() };
+        let (result, _) = load_file_series(&file_paths, &options).unwrap();
+        let Some(DataType::VecChannel(gaps)) = result.get("timestamp_gaps") else {
+            panic!("no timestamp_gaps");
+        };
+        assert_eq!(gaps.len(), 1);
+        assert!(matches!(gaps[0].get("sample"), Some(DataType::Long(128))));
+        let expected = (i32::MAX as i64 - 127) - (i32::MIN as i64 + 127) - 1;
+        assert!(matches!(gaps[0].get("missing_samples"), Some(DataType::Long(missing)) if *missing == expected));
+    }
+
+    #[test]
+    fn series_is_filtered_as_one_signal() {
+        use crate::filter::FilterBand;
+        use crate::import_hash::{ApplyNotch, BandOptions};
+
+        // One recording saved whole and split into two files.
+        let dir = test_dir("series_filter");
+        let amplifier = |channel: usize, sample: usize| {
+            let t = sample as f64 / 30000.0;
+            (500.0 * (2.0 * std::f64::consts::PI * 60.0 * t).sin() + 200.0 * (2.0 * std::f64::consts::PI * (1000.0 + channel as f64) * t).sin()) as i16
+        };
+        let timestamps: Vec<i32> = (0..8 * 128).collect();
+        let whole = dir.join("whole.rhs");
+        write_rhs(&whole, 2, &timestamps, amplifier);
+        let file_paths: Vec<String> = (0..2).map(|i| {
+            let path = dir.join(format!("rec_{:02}.rhs", i));
+            write_rhs(&path, 2, &timestamps[i * 4 * 128..(i + 1) * 4 * 128], |channel, sample| amplifier(channel, sample + i * 4 * 128));
+            path.to_string_lossy().into_owned()
+        }).collect();
+
+        let options = LoadOptions {
+            notch: NotchOptions { apply: ApplyNotch::Force, frequency: Some(60.0), zero_phase: true, ..Default::default() },
+            bands: vec![BandOptions { name: "spike_band".to_string(), band: FilterBand::HighPass(300.0), order: 2, zero_phase: true }],
+            ..Default::default()
+        };
+        let (expected, _) = load_file(whole.to_str().unwrap(), &options).unwrap();
+        let (series, _) = load_file_series(&file_paths, &options).unwrap();
+        for key in ["amplifier_data", "spike_band"] {
+            let (Some(DataType::Array(Arrays::ArrayTwoFloat(expected))), Some(DataType::Array(Arrays::ArrayTwoFloat(series)))) = (expected.get(key), series.get(key)) else {
+                panic!("{} missing", key);
+            };
+            assert_eq!(expected.dim(), series.dim());
+            for (a, b) in expected.iter().zip(series) {
+                assert!((a - b).abs() < 1e-3, "{}: {} != {}", key, a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn series_without_data_lists_its_files() {
+        let dir = test_dir("series_empty");
+        let file_paths: Vec<String> = (0..2).map(|i| {
+            let path = dir.join(format!("rec_{:02}.rhs", i));
+            write_rhs(&path, 1, &[], |_, _| 0);
+            path.to_string_lossy().into_owned()
+        }).collect();
+
+        let (result, data_present) = load_file_series(&file_paths, &LoadOptions::default()).unwrap();
+        assert!(!data_present);
+        assert!(result.contains_key("frequency_parameters"));
+        let Some(DataType::VecChannel(files)) = result.get("series_files") else {
+            panic!("no series_files");
+        };
+        assert_eq!(files.len(), 2);
+        assert!(files.iter().all(|file| matches!(file.get("num_samples"), Some(DataType::Long(0)))));
+        assert!(matches!(result.get("timestamp_gaps"), Some(DataType::VecChannel(gaps)) if gaps.is_empty()));
+    }
+}
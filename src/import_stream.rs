@@ -0,0 +1,200 @@
+// Standard library imports
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Seek, SeekFrom};
+use std::ops::Range;
+
+// External crates
+use rayon::ThreadPool;
+
+// Local modules
+use crate::header::RhsHeader;
+use crate::import_hash::{
+    calculate_data_size, data_to_result, file_sample_range, initialize_memory, open_file, parse_data, read_data_blocks, read_header,
+    select_channels, ApplyNotch, ChannelRows, DataType, LoadOptions, RhsError,
+};
+use crate::import_parallel::thread_pool;
+use crate::stim::ArtifactMethod;
+
+/// A run of consecutive data blocks read by `BlockIterator`.
+#[derive(Debug, Clone)]
+pub struct DataChunk {
+    /// Index of the chunk's first sample, counted from the first sample in
+    /// the file.
+    pub first_sample: u64,
+    /// Number of amplifier samples per channel in the chunk.
+    pub num_samples: usize,
+    /// Timestamps and signals, under the same keys and in the same units as
+    /// the data returned by `load_file`.
+    pub data: HashMap<String, DataType>,
+}
+
+/// Reads a traditional .rhs or .rhd file a fixed number of data blocks at a
+/// time, so that only one chunk of the recording is held in memory.
+///
+/// Each chunk is decoded and scaled exactly as `load_file` would, honoring the
+/// `raw`, `start`, `stop`, `channels`, `port`, `num_threads` and `lenient`
+/// options. Notch and band filters and stimulation artifact removal are
+/// rejected, since they would restart at every chunk. In lenient
+/// mode, a partial block at the end of the file is dropped but timestamps are
+/// not checked for corrupted blocks.
+pub struct BlockIterator {
+    fid: File,
+    // Every channel in the file, which lays out the data blocks.
+    file_header: RhsHeader,
+    selected_header: RhsHeader,
+    channel_rows: Option<ChannelRows>,
+    pool: Option<ThreadPool>,
+    raw: bool,
+    // Position of the first data block in the file.
+    data_offset: u64,
+    sample_range: Range<u64>,
+    blocks_per_chunk: u64,
+    next_sample: u64,
+}
+
+impl BlockIterator {
+    pub fn open(file_path: &str, blocks_per_chunk: usize, options: &LoadOptions) -> std::result::Result<BlockIterator, RhsError> {
+        if blocks_per_chunk == 0 {
+            return Err(RhsError::InvalidArgumentError("Chunks must hold at least one data block".to_string()));
+        }
+        if options.notch.apply != ApplyNotch::Skip || !options.bands.is_empty() || options.artifacts.method != ArtifactMethod::Keep {
+            return Err(RhsError::InvalidArgumentError("Chunks cannot be filtered or have stimulation artifacts removed; load the whole recording instead".to_string()));
+        }
+
+        let mut fid: File = open_file(file_path)?;
+        let header: RhsHeader = read_header(&mut fid)?;
+        let data_offset = fid.stream_position()?;
+        let data_size = calculate_data_size(&header, file_path, options.lenient, &mut fid)?;
+
+        // Data blocks are laid out according to the full header, while only
+        // the selected channels are allocated and returned.
+        let mut selected_header = header.clone();
+        let channel_rows = select_channels(&mut selected_header, options)?;
+
+        let sample_range = if data_size.data_present {
+            file_sample_range(&header, options, data_size.num_samples)?
+        } else {
+            0..0
+        };
+        let pool = options.num_threads.map(thread_pool).transpose()?;
+
+        Ok(BlockIterator {
+            fid,
+            file_header: header,
+            selected_header,
+            channel_rows,
+            pool,
+            raw: options.raw,
+            data_offset,
+            next_sample: sample_range.start,
+            sample_range,
+            blocks_per_chunk: blocks_per_chunk as u64,
+        })
+    }
+
+    /// The header, narrowed to the selected channels.
+    pub fn header(&self) -> &RhsHeader {
+        &self.selected_header
+    }
+
+    /// The samples the iterator reads, counted from the first sample in the
+    /// file.
+    pub fn sample_range(&self) -> Range<u64> {
+        self.sample_range.clone()
+    }
+
+    fn read_chunk(&mut self, chunk_range: Range<u64>) -> std::result::Result<DataChunk, RhsError> {
+        let num_samples = chunk_range.end - chunk_range.start;
+        let (mut data, _) = initialize_memory(&self.selected_header, num_samples);
+        self.fid.seek(SeekFrom::Start(self.data_offset))?;
+        read_data_blocks(&mut data, &self.file_header, self.channel_rows.as_ref(), &chunk_range, self.pool.as_ref(), false, &mut self.fid)?;
+
+        let mut result_out: HashMap<String, DataType> = HashMap::new();
+        parse_data(&self.selected_header, &mut data, self.raw);
+        data_to_result(&self.selected_header, &mut data, &mut result_out);
+
+        Ok(DataChunk { first_sample: chunk_range.start, num_samples: num_samples as usize, data: result_out })
+    }
+}
+
+impl Iterator for BlockIterator {
+    type Item = std::result::Result<DataChunk, RhsError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next_sample >= self.sample_range.end {
+            return None;
+        }
+
+        // Chunks start and end on block boundaries, except where the sample
+        // range starts or stops partway through a block.
+        let samples_per_block = self.file_header.num_samples_per_data_block() as u64;
+        let first_block = self.next_sample / samples_per_block;
+        let chunk_end = ((first_block + self.blocks_per_chunk) * samples_per_block).min(self.sample_range.end);
+        let chunk = self.read_chunk(self.next_sample..chunk_end);
+
+        // Stop after an error rather than reading past the block that failed.
+        self.next_sample = if chunk.is_ok() { chunk_end } else { self.sample_range.end };
+        Some(chunk)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::{concatenate, s, Array1, Array2, Axis};
+
+    use crate::import_hash::{load_file, Arrays, NotchOptions, Position};
+    use crate::test_files::{test_dir, write_rhs};
+
+    fn times(data: &HashMap<String, DataType>) -> Array1<f64> {
+        match data.get("t") {
+            Some(DataType::Array(Arrays::ArrayOneFloat(t))) => t.clone(),
+            other => panic!("unexpected t {:?}", other),
+        }
+    }
+
+    fn amplifier_data(data: &HashMap<String, DataType>) -> Array2<f32> {
+        match data.get("amplifier_data") {
+            Some(DataType::Array(Arrays::ArrayTwoFloat(amplifier_data))) => amplifier_data.clone(),
+            other => panic!("unexpected amplifier_data {:?}", other),
+        }
+    }
+
+    #[test]
+    fn chunks_join_into_full_load() {
+        // Ten blocks read three at a time, so the last chunk is short.
+        let path = test_dir("chunks").join("chunks.rhs");
+        let timestamps: Vec<i32> = (0..10 * 128).collect();
+        write_rhs(&path, 2, &timestamps, |channel, sample| (channel * 1000 + sample) as i16);
+        let path = path.to_str().unwrap();
+        let (full, _) = load_file(path, &LoadOptions::default()).unwrap();
+
+        for (start, stop) in [(0, 10 * 128), (100, 1200)] {
+            let options = LoadOptions { start: Some(Position::Sample(start)), stop: Some(Position::Sample(stop)), ..Default::default() };
+            let chunks: Vec<DataChunk> = BlockIterator::open(path, 3, &options).unwrap().collect::<std::result::Result<_, _>>().unwrap();
+            let first_samples: Vec<u64> = chunks.iter().map(|chunk| chunk.first_sample).collect();
+            let expected_first_samples: Vec<u64> = std::iter::once(start).chain((3..10).step_by(3).map(|block| block * 128).filter(|&sample| sample > start && sample < stop)).collect();
+            assert_eq!(first_samples, expected_first_samples);
+
+            let t: Vec<Array1<f64>> = chunks.iter().map(|chunk| times(&chunk.data)).collect();
+            let t = concatenate(Axis(0), &t.iter().map(Array1::view).collect::<Vec<_>>()).unwrap();
+            assert_eq!(t, times(&full).slice(s![start as usize..stop as usize]));
+            let amplifier: Vec<Array2<f32>> = chunks.iter().map(|chunk| amplifier_data(&chunk.data)).collect();
+            let amplifier = concatenate(Axis(1), &amplifier.iter().map(Array2::view).collect::<Vec<_>>()).unwrap();
+            assert_eq!(amplifier, amplifier_data(&full).slice(s![.., start as usize..stop as usize]));
+        }
+    }
+
+    #[test]
+    fn chunks_reject_filters() {
+        let path = test_dir("chunk_filters").join("chunks.rhs");
+        write_rhs(&path, 1, &(0..128).collect::<Vec<i32>>(), |_, _| 0);
+        let path = path.to_str().unwrap();
+        let notch = LoadOptions { notch: NotchOptions { apply: ApplyNotch::Force, ..Default::default() }, ..Default::default() };
+        assert!(matches!(BlockIterator::open(path, 1, &notch), Err(RhsError::InvalidArgumentError(_))));
+        let mut artifacts = LoadOptions::default();
+        artifacts.artifacts.method = ArtifactMethod::Zero;
+        assert!(matches!(BlockIterator::open(path, 1, &artifacts), Err(RhsError::InvalidArgumentError(_))));
+    }
+}
@@ -1,61 +1,48 @@
-use pyo3::prelude::*;
-use pyo3::types::{PyDict, PyList};
-use pyo3::exceptions::PyRuntimeError;
-use numpy::IntoPyArray;
+//! Readers for Intan Technologies RHS and RHD recordings.
+//!
+//! The reader is plain Rust: `load_file` reads a traditional .rhs or .rhd
+//! file into the same keys the Python module returns, `read_header` and
+//! `read_header_info` parse just the header, and `MappedRecording` gives
+//! strided views over the data blocks of a memory-mapped file.
+//! `BlockIterator` reads a file a chunk of data blocks at a time, for
+//! recordings too large to load at once. Sessions saved as one file per signal
+//! type or per channel are read by the functions in `import_dat`, and
+//! recordings split across consecutive files by `load_file_series`. The
+//! notch and Butterworth filters `load_file` can apply are also available on
+//! their own in `filter`, `detect_spikes` finds threshold crossings in
+//! loaded amplifier data, `extract_stim_pulses` turns loaded stimulation
+//! data into a table of pulses, and `find_digital_edges` and
+//! `decode_strobed_words` turn digital inputs and outputs into events.
+//!
+//! The Python bindings are built only with the `python` feature, so Rust
+//! consumers do not link against libpython.
 
-mod import_hash;
-use import_hash::{DataType, Arrays};
+pub mod digital;
+pub mod filter;
+pub mod header;
+pub mod import_hash;
+pub mod import_dat;
+pub mod import_mmap;
+mod import_parallel;
+pub mod import_series;
+pub mod import_stream;
+pub mod spikes;
+pub mod stim;
+#[cfg(feature = "python")]
+mod python;
+#[cfg(test)]
+mod test_files;
 
-fn data_type_to_py_object(py: Python, data: &DataType) -> PyResult<PyObject> {
-    match data {
-        DataType::String(val) => Ok(val.clone().into_py(py)),
-        DataType::Int(val) => Ok((*val).into_py(py)),
-        DataType::Float(val) => Ok((*val).into_py(py)),
-        DataType::Bool(val) => Ok((*val).into_py(py)),
-        DataType::HashMap(val) => {
-            let dict = PyDict::new_bound(py);
-            for (key, value) in val {
-                dict.set_item(key, data_type_to_py_object(py, value)?)?;
-            }
-            Ok(dict.into())
-        },
-        DataType::VecInt(val) => {
-            let list = PyList::new_bound(py, val);
-            Ok(list.into())
-        },
-        DataType::VecChannel(val) => {
-            let list = PyList::new_bound(py, &[]);
-            for hashmap in val {
-                let dict = PyDict::new_bound(py);
-                for (key, value) in hashmap {
-                    dict.set_item(key, data_type_to_py_object(py, value)?)?;
-                }
-                list.append(dict)?;
-            }
-            Ok(list.into())
-        },
-        DataType::Array(arrays) => {
-            match arrays {
-                Arrays::ArrayOne(array) => Ok(array.clone().into_pyarray_bound(py).into()),
-                Arrays::ArrayTwo(array) => Ok(array.clone().into_pyarray_bound(py).into()),
-                Arrays::ArrayTwoBool(array) => Ok(array.clone().into_pyarray_bound(py).into()),
-            }
-        },
-        DataType::None => Ok(py.None()),
-    }
-}
-
-#[pyfunction]
-fn load_file_wrapper(py: Python, file_path: String) -> PyResult<(PyObject, bool)> {
-    let result = import_hash::load_file(&file_path);
-    match result {
-        Ok((mut hash_map, flag)) => {
-            let py_dict = PyDict::new_bound(py);
-            for (key, value) in &mut hash_map {
-                py_dict.set_item(key, data_type_to_py_object(py, value)?)?;
-            }
-            Ok((py_dict.into(), flag))
-        },
-        Err(e) => Err(PyRuntimeError::new_err(format!("{}", e))),
-    }
-}
\ No newline at end of file
+pub use digital::{decode_strobed_words, digital_channel, digital_channels, find_bit_edges, find_digital_edges, DigitalEdges, StrobedWordOptions, StrobedWords};
+pub use filter::{filter_channels, filtfilt, notch_filter, notch_filter_zero_phase, Biquad, ButterworthFilter, FilterBand};
+pub use header::RhsHeader;
+pub use import_hash::{
+    load_file, read_header, read_header_info, scale_factors, ApplyNotch, Arrays, ArtifactOptions, BandOptions, DataType, HeaderInfo, LoadOptions, NotchOptions,
+    Position, RhsError, ScaleFactor,
+};
+pub use import_dat::{detect_save_format, load_file_per_channel, load_file_per_signal_type, read_session_header_info, SaveFormat};
+pub use import_mmap::MappedRecording;
+pub use import_series::{find_file_series, load_file_series};
+pub use import_stream::{BlockIterator, DataChunk};
+pub use spikes::{detect_spikes, ChannelSpikes, SpikeOptions, Threshold};
+pub use stim::{artifact_mask, extract_stim_pulses, find_stim_pulses, remove_artifacts, ArtifactMethod, PolarityOrder, StimPhase, StimPulse, StimPulseOptions};
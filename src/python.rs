@@ -0,0 +1,1266 @@
+use std::collections::HashMap;
+
+use pyo3::prelude::*;
+use pyo3::create_exception;
+use pyo3::types::{PyDict, PyList, PySlice, PyTuple};
+use pyo3::exceptions::{PyException, PyKeyError, PyRuntimeError, PyValueError};
+use numpy::{IntoPyArray, PyArray1, PyArray2, PyArrayMethods, PyReadonlyArray2};
+use ndarray::{Array1, Array2, ArrayView1};
+
+use crate::{import_dat, import_hash, import_mmap, import_series, import_stream};
+use crate::digital::{decode_strobed_words, digital_channel, find_bit_edges, StrobedWordOptions};
+use crate::filter::{filter_channels, ButterworthFilter, FilterBand};
+use crate::header::SpikeTrigger;
+use crate::import_hash::{DataType, Arrays, ApplyNotch, ArtifactOptions, BandOptions, LoadOptions, NotchOptions, Position, RhsError};
+use crate::import_parallel::thread_pool;
+use crate::spikes::{channel_thresholds, detect_threshold_crossings, SpikeOptions, Threshold};
+use crate::stim::{find_stim_pulses, ArtifactMethod, StimPulse, StimPulseOptions};
+use crate::import_dat::SaveFormat;
+
+create_exception!(intan_import_py, IntanError, PyException, "Base class for errors raised while reading Intan files.");
+create_exception!(intan_import_py, IntanFormatError, IntanError, "The file is not a valid RHS or RHD file, or its header is inconsistent.");
+create_exception!(intan_import_py, TruncatedFileError, IntanFormatError, "The file ends before the header or data blocks it describes.");
+create_exception!(intan_import_py, ChannelNotFoundError, IntanError, "A requested channel or port is not in the recording.");
+
+// The Python exception raised for each kind of `RhsError`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PyErrorKind {
+    OsError,
+    TruncatedFile,
+    IntanFormat,
+    ChannelNotFound,
+    Value,
+    Runtime,
+}
+
+impl From<&RhsError> for PyErrorKind {
+    fn from(error: &RhsError) -> PyErrorKind {
+        match error {
+            RhsError::IoError { .. } => PyErrorKind::OsError,
+            // A QString running past the end of the file means the header
+            // was cut short.
+            RhsError::TruncatedFileError { .. } | RhsError::QStringError { .. } => PyErrorKind::TruncatedFile,
+            RhsError::FileSizeError { size, expected, .. } if size < expected => PyErrorKind::TruncatedFile,
+            RhsError::FileSizeError { .. }
+            | RhsError::UnrecognizedFileError { .. }
+            | RhsError::UnknownChannelTypeError { .. }
+            | RhsError::HeaderFieldError { .. }
+            | RhsError::IncompatibleHeaderError { .. } => PyErrorKind::IntanFormat,
+            RhsError::ChannelNotFoundError { .. } | RhsError::PortNotFoundError { .. } => PyErrorKind::ChannelNotFound,
+            RhsError::InvalidArgumentError(_) => PyErrorKind::Value,
+            RhsError::DataArrayError(_) => PyErrorKind::Runtime,
+        }
+    }
+}
+
+impl From<RhsError> for PyErr {
+    fn from(error: RhsError) -> PyErr {
+        let message = error.to_string();
+        match (PyErrorKind::from(&error), error) {
+            // Keep the OSError subclass Python would raise itself, such as
+            // FileNotFoundError.
+            (_, RhsError::IoError { source, .. }) => std::io::Error::new(source.kind(), message).into(),
+            (PyErrorKind::TruncatedFile, _) => TruncatedFileError::new_err(message),
+            (PyErrorKind::IntanFormat, _) => IntanFormatError::new_err(message),
+            (PyErrorKind::ChannelNotFound, _) => ChannelNotFoundError::new_err(message),
+            (PyErrorKind::Value, _) => PyValueError::new_err(message),
+            (PyErrorKind::OsError | PyErrorKind::Runtime, _) => PyRuntimeError::new_err(message),
+        }
+    }
+}
+
+fn data_type_to_py_object(py: Python, data: &DataType) -> PyResult<PyObject> {
+    match data {
+        DataType::String(val) => Ok(val.clone().into_py(py)),
+        DataType::Int(val) => Ok((*val).into_py(py)),
+        DataType::Long(val) => Ok((*val).into_py(py)),
+        DataType::Float(val) => Ok((*val).into_py(py)),
+        DataType::Bool(val) => Ok((*val).into_py(py)),
+        DataType::HashMap(val) => {
+            let dict = PyDict::new_bound(py);
+            for (key, value) in val {
+                dict.set_item(key, data_type_to_py_object(py, value)?)?;
+            }
+            Ok(dict.into())
+        },
+        DataType::VecInt(val) => {
+            let list = PyList::new_bound(py, val);
+            Ok(list.into())
+        },
+        DataType::VecLong(val) => {
+            let list = PyList::new_bound(py, val);
+            Ok(list.into())
+        },
+        DataType::VecChannel(val) => {
+            let list = PyList::empty_bound(py);
+            for hashmap in val {
+                let dict = PyDict::new_bound(py);
+                for (key, value) in hashmap {
+                    dict.set_item(key, data_type_to_py_object(py, value)?)?;
+                }
+                list.append(dict)?;
+            }
+            Ok(list.into())
+        },
+        DataType::Array(arrays) => Ok(arrays_into_py(py, arrays.clone())),
+        DataType::None => Ok(py.None()),
+    }
+}
+
+fn result_to_py_object(py: Python, result: Result<(HashMap<String, DataType>, bool), RhsError>) -> PyResult<(PyObject, bool)> {
+    match result {
+        Ok((mut hash_map, flag)) => {
+            let py_dict = PyDict::new_bound(py);
+            for (key, value) in &mut hash_map {
+                py_dict.set_item(key, data_type_to_py_object(py, value)?)?;
+            }
+            for (data_key, states) in unpack_digital_result(&hash_map)? {
+                py_dict.set_item(data_key, states.into_pyarray_bound(py))?;
+            }
+            Ok((py_dict.into(), flag))
+        },
+        Err(e) => Err(e.into()),
+    }
+}
+
+// The dictionary returned by the legacy loaders holds one row per digital
+// channel, as Intan's own readers do, unpacked here from the digital words.
+fn unpack_digital_result(result: &HashMap<String, DataType>) -> Result<Vec<(String, Array2<bool>)>, RhsError> {
+    let mut unpacked = Vec::new();
+    for source in ["board_dig_in", "board_dig_out"] {
+        let (Some(DataType::Array(Arrays::ArrayTwoU16(words))), Some(DataType::VecChannel(channels))) =
+            (result.get(&format!("{}_raw", source)), result.get(&format!("{}_channels", source))) else {
+            continue;
+        };
+        if words.nrows() == 0 {
+            continue;
+        }
+        let mut states = Array2::from_elem((channels.len(), words.ncols()), false);
+        for (mut row, channel) in states.outer_iter_mut().zip(channels) {
+            let native_order = match channel.get("native_order") {
+                Some(DataType::Int(native_order)) => *native_order,
+                _ => 0,
+            };
+            row.assign(&digital_channel(words.row(0), native_order)?);
+        }
+        unpacked.push((format!("{}_data", source), states));
+    }
+    Ok(unpacked)
+}
+
+// Run a loader with the GIL released, so other Python threads keep running
+// while files are read and decoded.
+fn without_gil<T: Send>(py: Python, load: impl FnOnce() -> Result<T, RhsError> + Send) -> Result<T, RhsError> {
+    py.allow_threads(load)
+}
+
+fn position(sample: Option<u64>, seconds: Option<f64>, name: &str) -> PyResult<Option<Position>> {
+    match (sample, seconds) {
+        (Some(_), Some(_)) => Err(PyValueError::new_err(format!("Give either {}_sample or {}_time, not both", name, name))),
+        (Some(sample), None) => Ok(Some(Position::Sample(sample))),
+        (None, Some(seconds)) => Ok(Some(Position::Seconds(seconds))),
+        (None, None) => Ok(None),
+    }
+}
+
+fn notch_options(notch: &str, frequency: Option<f32>, bandwidth: f32, harmonics: usize, zero_phase: bool) -> PyResult<NotchOptions> {
+    let apply = match notch {
+        "skip" => ApplyNotch::Skip,
+        "auto" => ApplyNotch::Auto,
+        "force" => ApplyNotch::Force,
+        _ => return Err(PyValueError::new_err(format!("notch must be 'skip', 'auto' or 'force', not '{}'", notch))),
+    };
+    Ok(NotchOptions { apply, frequency, bandwidth, harmonics, zero_phase })
+}
+
+fn artifact_options(artifacts: Option<&str>, pre_ms: f32, post_ms: f32, all_channels: bool) -> PyResult<ArtifactOptions> {
+    let method = match artifacts {
+        None => ArtifactMethod::Keep,
+        Some("zero") => ArtifactMethod::Zero,
+        Some("nan") => ArtifactMethod::Nan,
+        Some("interpolate") => ArtifactMethod::Interpolate,
+        Some("template") => ArtifactMethod::Template,
+        Some(other) => return Err(PyValueError::new_err(format!("artifacts must be None, 'zero', 'nan', 'interpolate' or 'template', not '{}'", other))),
+    };
+    Ok(ArtifactOptions { method, pre_ms, post_ms, all_channels })
+}
+
+// A band given from Python as (low, high) cutoffs, either of which may be None.
+type Cutoffs = (Option<f32>, Option<f32>);
+
+fn filter_band(low: Option<f32>, high: Option<f32>) -> PyResult<FilterBand> {
+    match (low, high) {
+        (Some(low), Some(high)) => Ok(FilterBand::BandPass(low, high)),
+        (Some(low), None) => Ok(FilterBand::HighPass(low)),
+        (None, Some(high)) => Ok(FilterBand::LowPass(high)),
+        (None, None) => Err(PyValueError::new_err("Give a low cutoff, a high cutoff or both")),
+    }
+}
+
+fn band_options(bands: Option<HashMap<String, Cutoffs>>, order: usize, zero_phase: bool) -> PyResult<Vec<BandOptions>> {
+    bands.unwrap_or_default().into_iter()
+        .map(|(name, (low, high))| Ok(BandOptions { name, band: filter_band(low, high)?, order, zero_phase }))
+        .collect()
+}
+
+// The arguments `load`, `load_series`, `iter_blocks` and the
+// `load_file*_wrapper` functions take after the path, parsed by pyo3 in one
+// place so that the entry points cannot drift apart. Not exported: it is only
+// constructed through `load_options`.
+#[pyclass(module = "intan_import_py", name = "LoadOptions")]
+struct PyLoadOptions {
+    options: LoadOptions,
+}
+
+#[pymethods]
+impl PyLoadOptions {
+    #[new]
+    #[pyo3(signature = (raw=false, start_sample=None, stop_sample=None, start_time=None, stop_time=None, channels=None, port=None, num_threads=None, lenient=false, notch="skip", notch_frequency=None, notch_bandwidth=10.0, notch_harmonics=1, zero_phase=false, bands=None, filter_order=4, artifacts=None, artifact_pre_ms=0.0, artifact_post_ms=1.0, artifact_all_channels=false, verbose=true))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(raw: bool, start_sample: Option<u64>, stop_sample: Option<u64>, start_time: Option<f64>, stop_time: Option<f64>, channels: Option<Vec<String>>, port: Option<String>, num_threads: Option<usize>, lenient: bool, notch: &str, notch_frequency: Option<f32>, notch_bandwidth: f32, notch_harmonics: usize, zero_phase: bool, bands: Option<HashMap<String, Cutoffs>>, filter_order: usize, artifacts: Option<&str>, artifact_pre_ms: f32, artifact_post_ms: f32, artifact_all_channels: bool, verbose: bool) -> PyResult<PyLoadOptions> {
+        let options = LoadOptions {
+            raw,
+            start: position(start_sample, start_time, "start")?,
+            stop: position(stop_sample, stop_time, "stop")?,
+            channels,
+            port,
+            num_threads,
+            lenient,
+            notch: notch_options(notch, notch_frequency, notch_bandwidth, notch_harmonics, zero_phase)?,
+            bands: band_options(bands, filter_order, zero_phase)?,
+            artifacts: artifact_options(artifacts, artifact_pre_ms, artifact_post_ms, artifact_all_channels)?,
+            verbose,
+        };
+        Ok(PyLoadOptions { options })
+    }
+}
+
+fn load_options(py: Python, args: &Bound<PyTuple>, kwargs: Option<&Bound<PyDict>>) -> PyResult<LoadOptions> {
+    let options = py.get_type_bound::<PyLoadOptions>().call(args, kwargs)?;
+    let options = options.downcast::<PyLoadOptions>()?.borrow();
+    Ok(options.options.clone())
+}
+
+#[pyfunction]
+#[pyo3(signature = (file_path, *args, **kwargs))]
+fn load_file_wrapper(py: Python, file_path: String, args: &Bound<PyTuple>, kwargs: Option<&Bound<PyDict>>) -> PyResult<(PyObject, bool)> {
+    let options = load_options(py, args, kwargs)?;
+    result_to_py_object(py, without_gil(py, || import_hash::load_file(&file_path, &options)))
+}
+
+#[pyfunction]
+#[pyo3(signature = (path, *args, **kwargs))]
+fn load_file_per_signal_type_wrapper(py: Python, path: String, args: &Bound<PyTuple>, kwargs: Option<&Bound<PyDict>>) -> PyResult<(PyObject, bool)> {
+    let options = load_options(py, args, kwargs)?;
+    result_to_py_object(py, without_gil(py, || import_dat::load_file_per_signal_type(&path, &options)))
+}
+
+#[pyfunction]
+#[pyo3(signature = (path, *args, **kwargs))]
+fn load_file_per_channel_wrapper(py: Python, path: String, args: &Bound<PyTuple>, kwargs: Option<&Bound<PyDict>>) -> PyResult<(PyObject, bool)> {
+    let options = load_options(py, args, kwargs)?;
+    result_to_py_object(py, without_gil(py, || import_dat::load_file_per_channel(&path, &options)))
+}
+
+fn arrays_into_py(py: Python, arrays: Arrays) -> PyObject {
+    match arrays {
+        Arrays::ArrayOne(array) => array.into_pyarray_bound(py).into(),
+        Arrays::ArrayTwo(array) => array.into_pyarray_bound(py).into(),
+        Arrays::ArrayTwoBool(array) => array.into_pyarray_bound(py).into(),
+        Arrays::ArrayTwoU16(array) => array.into_pyarray_bound(py).into(),
+        Arrays::ArrayOneFloat(array) => array.into_pyarray_bound(py).into(),
+        Arrays::ArrayTwoFloat(array) => array.into_pyarray_bound(py).into(),
+    }
+}
+
+// Native name, custom name and native order (bit number) of a digital
+// channel.
+type DigitalChannel = (String, String, i32);
+
+/// A spike threshold: a value in microVolts, or "header", "rms" or "mad".
+#[derive(FromPyObject)]
+enum ThresholdArg {
+    Value(f32),
+    Method(String),
+}
+
+/// A recording loaded from an Intan RHS or RHD file or session directory.
+///
+/// Header information is available through `header`, `channels`, `notes`,
+/// `sample_rate` and `duration`; signal arrays through `data` or the
+/// individual properties such as `amplifier_data`. Indexing the recording
+/// (`recording["amplifier_data"]`) looks up the same keys as the dictionary
+/// returned by `load_file_wrapper`.
+#[pyclass(module = "intan_import_py", name = "IntanRecording")]
+struct IntanRecording {
+    header: Py<PyDict>,
+    data: Py<PyDict>,
+    /// Amplifier sample rate in Hz.
+    #[pyo3(get)]
+    sample_rate: f64,
+    /// Number of amplifier samples in the recording.
+    #[pyo3(get)]
+    num_samples: usize,
+    /// Recording duration in seconds.
+    #[pyo3(get)]
+    duration: f64,
+    /// False if the file only held a header and no data blocks.
+    #[pyo3(get)]
+    data_present: bool,
+    /// Bytes after the last complete data block that were not loaded
+    /// (`lenient=True` only).
+    #[pyo3(get)]
+    dropped_bytes: u64,
+    /// Data blocks, counted from the start of the file, whose timestamps
+    /// break the sequence (`lenient=True` only).
+    #[pyo3(get)]
+    corrupted_blocks: Vec<u64>,
+}
+
+impl IntanRecording {
+    fn from_result(py: Python, result: HashMap<String, DataType>, data_present: bool) -> PyResult<Self> {
+        let sample_rate = match &result.get("frequency_parameters") {
+            Some(DataType::HashMap(frequency_parameters)) => match frequency_parameters.get("amplifier_sample_rate") {
+                Some(DataType::Float(rate)) => *rate as f64,
+                _ => 0.0,
+            },
+            _ => 0.0,
+        };
+        let num_samples = match result.get("t") {
+            Some(DataType::Array(Arrays::ArrayOne(t))) => t.len(),
+            Some(DataType::Array(Arrays::ArrayOneFloat(t))) => t.len(),
+            _ => 0,
+        };
+        let duration = if sample_rate > 0.0 { num_samples as f64 / sample_rate } else { 0.0 };
+        let dropped_bytes = match result.get("dropped_bytes") {
+            Some(DataType::Long(dropped_bytes)) => *dropped_bytes as u64,
+            _ => 0,
+        };
+        let corrupted_blocks = match result.get("corrupted_blocks") {
+            Some(DataType::VecLong(blocks)) => blocks.iter().map(|&block| block as u64).collect(),
+            _ => Vec::new(),
+        };
+
+        // Arrays are moved into numpy once here, everything else stays in
+        // the header dictionary.
+        let header = PyDict::new_bound(py);
+        let data = PyDict::new_bound(py);
+        for (key, value) in result {
+            match value {
+                DataType::Array(arrays) => data.set_item(key, arrays_into_py(py, arrays))?,
+                other => header.set_item(key, data_type_to_py_object(py, &other)?)?,
+            }
+        }
+
+        Ok(IntanRecording {
+            header: header.unbind(),
+            data: data.unbind(),
+            sample_rate,
+            num_samples,
+            duration,
+            data_present,
+            dropped_bytes,
+            corrupted_blocks,
+        })
+    }
+
+    fn data_item(&self, py: Python, key: &str) -> PyResult<Option<PyObject>> {
+        Ok(self.data.bind(py).get_item(key)?.map(|item| item.unbind()))
+    }
+
+    // Timestamps in seconds, converted from sample numbers in raw recordings.
+    fn times(&self, py: Python) -> PyResult<Vec<f64>> {
+        let Some(t) = self.data_item(py, "t")? else {
+            return Err(PyKeyError::new_err("t"));
+        };
+        let t = t.bind(py);
+        if let Ok(times) = t.downcast::<PyArray1<f64>>() {
+            Ok(times.to_vec()?)
+        } else {
+            Ok(t.downcast::<PyArray1<i32>>()?.to_vec()?.into_iter().map(|sample| sample as f64 / self.sample_rate).collect())
+        }
+    }
+
+    // The digital word of "board_dig_in" or "board_dig_out" and the channels
+    // it holds, or None if the recording has none of those channels.
+    fn digital_word(&self, py: Python, source: &str) -> PyResult<Option<(Array1<u16>, Vec<DigitalChannel>)>> {
+        if source != "board_dig_in" && source != "board_dig_out" {
+            return Err(PyValueError::new_err(format!("source must be 'board_dig_in' or 'board_dig_out', not '{}'", source)));
+        }
+        let Some(words) = self.data_item(py, &format!("{}_raw", source))? else {
+            return Ok(None);
+        };
+        let words = words.bind(py).downcast::<PyArray2<u16>>()?.readonly();
+        let words = words.as_array();
+        if words.nrows() == 0 {
+            return Ok(None);
+        }
+        let channels = match self.header.bind(py).get_item(format!("{}_channels", source))? {
+            Some(channels) => channels.extract::<Vec<Bound<PyDict>>>()?.iter()
+                .map(|channel| {
+                    let name = |key: &str| channel.get_item(key)?.map_or(Ok(String::new()), |name| name.extract::<String>());
+                    let native_order = channel.get_item("native_order")?.map_or(Ok(0), |order| order.extract::<i32>())?;
+                    Ok((name("native_channel_name")?, name("custom_channel_name")?, native_order))
+                })
+                .collect::<PyResult<Vec<_>>>()?,
+            None => Vec::new(),
+        };
+        Ok(Some((words.row(0).to_owned(), channels)))
+    }
+
+    // The state of every "board_dig_in" or "board_dig_out" channel, one row
+    // per channel, unpacked from the digital word.
+    fn unpack_digital_data(&self, py: Python, source: &str) -> PyResult<Option<PyObject>> {
+        let Some((words, channels)) = self.digital_word(py, source)? else {
+            return Ok(None);
+        };
+        let mut states = Array2::from_elem((channels.len(), words.len()), false);
+        for (mut row, (_, _, native_order)) in states.outer_iter_mut().zip(&channels) {
+            row.assign(&digital_channel(words.view(), *native_order)?);
+        }
+        Ok(Some(states.into_pyarray_bound(py).into()))
+    }
+
+    // The word and channels of "board_dig_in" or "board_dig_out", raising
+    // KeyError if the recording has none of those channels.
+    fn require_digital_word(&self, py: Python, source: &str) -> PyResult<(Array1<u16>, Vec<DigitalChannel>)> {
+        self.digital_word(py, source)?.ok_or_else(|| PyKeyError::new_err(format!("{}_raw", source)))
+    }
+
+    fn amplifier_channel_names(&self, py: Python) -> PyResult<Vec<String>> {
+        match self.header.bind(py).get_item("amplifier_channels")? {
+            Some(channels) => channels.extract::<Vec<Bound<PyDict>>>()?.iter()
+                .map(|channel| channel.get_item("native_channel_name")?.map_or(Ok(String::new()), |name| name.extract::<String>()))
+                .collect(),
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+#[pymethods]
+impl IntanRecording {
+    /// Header information: frequency and stimulation parameters, notes,
+    /// reference channel, spike triggers and channel lists.
+    #[getter]
+    fn header(&self, py: Python) -> Py<PyDict> {
+        self.header.clone_ref(py)
+    }
+
+    /// All signal arrays, keyed as in the dictionary returned by `load_file_wrapper`.
+    #[getter]
+    fn data(&self, py: Python) -> Py<PyDict> {
+        self.data.clone_ref(py)
+    }
+
+    /// Channel lists keyed by signal group (`amplifier_channels`, `board_adc_channels`, ...).
+    #[getter]
+    fn channels<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let channels = PyDict::new_bound(py);
+        for (key, value) in self.header.bind(py).iter() {
+            if key.extract::<String>()?.ends_with("_channels") {
+                channels.set_item(key, value)?;
+            }
+        }
+        Ok(channels)
+    }
+
+    /// The three notes saved with the recording.
+    #[getter]
+    fn notes(&self, py: Python) -> PyResult<Option<PyObject>> {
+        Ok(self.header.bind(py).get_item("notes")?.map(|item| item.unbind()))
+    }
+
+    /// Timestamps in seconds (sample numbers when loaded with `raw=True`).
+    #[getter]
+    fn t(&self, py: Python) -> PyResult<Option<PyObject>> {
+        self.data_item(py, "t")
+    }
+
+    /// Amplifier data in microVolts.
+    #[getter]
+    fn amplifier_data(&self, py: Python) -> PyResult<Option<PyObject>> {
+        self.data_item(py, "amplifier_data")
+    }
+
+    /// DC amplifier data in Volts (RHS only).
+    #[getter]
+    fn dc_amplifier_data(&self, py: Python) -> PyResult<Option<PyObject>> {
+        self.data_item(py, "dc_amplifier_data")
+    }
+
+    /// Stimulation current in microAmps (RHS only).
+    #[getter]
+    fn stim_data(&self, py: Python) -> PyResult<Option<PyObject>> {
+        self.data_item(py, "stim_data")
+    }
+
+    /// Board analog input data in Volts.
+    #[getter]
+    fn board_adc_data(&self, py: Python) -> PyResult<Option<PyObject>> {
+        self.data_item(py, "board_adc_data")
+    }
+
+    /// Board analog output data in Volts (RHS only).
+    #[getter]
+    fn board_dac_data(&self, py: Python) -> PyResult<Option<PyObject>> {
+        self.data_item(py, "board_dac_data")
+    }
+
+    /// Board digital input states as booleans, one row per channel, unpacked
+    /// from `board_dig_in_raw` each time this is read. `digital_channel`
+    /// unpacks a single channel.
+    #[getter]
+    fn board_dig_in_data(&self, py: Python) -> PyResult<Option<PyObject>> {
+        self.unpack_digital_data(py, "board_dig_in")
+    }
+
+    /// Board digital output states as booleans, one row per channel, unpacked
+    /// from `board_dig_out_raw` each time this is read.
+    #[getter]
+    fn board_dig_out_data(&self, py: Python) -> PyResult<Option<PyObject>> {
+        self.unpack_digital_data(py, "board_dig_out")
+    }
+
+    /// Board digital input words as stored in the file, a single row of one
+    /// uint16 per sample. Each channel is the bit given by its `native_order`.
+    #[getter]
+    fn board_dig_in_raw(&self, py: Python) -> PyResult<Option<PyObject>> {
+        self.data_item(py, "board_dig_in_raw")
+    }
+
+    /// Board digital output words as stored in the file, a single row of one
+    /// uint16 per sample.
+    #[getter]
+    fn board_dig_out_raw(&self, py: Python) -> PyResult<Option<PyObject>> {
+        self.data_item(py, "board_dig_out_raw")
+    }
+
+    /// Auxiliary input data in Volts (RHD only).
+    #[getter]
+    fn aux_input_data(&self, py: Python) -> PyResult<Option<PyObject>> {
+        self.data_item(py, "aux_input_data")
+    }
+
+    /// Supply voltage data in Volts (RHD only).
+    #[getter]
+    fn supply_voltage_data(&self, py: Python) -> PyResult<Option<PyObject>> {
+        self.data_item(py, "supply_voltage_data")
+    }
+
+    /// Temperature sensor data in degrees Celsius (RHD only).
+    #[getter]
+    fn temp_sensor_data(&self, py: Python) -> PyResult<Option<PyObject>> {
+        self.data_item(py, "temp_sensor_data")
+    }
+
+    fn __getitem__(&self, py: Python, key: &str) -> PyResult<PyObject> {
+        if let Some(item) = self.data_item(py, key)? {
+            return Ok(item);
+        }
+        if let Some(source) = key.strip_suffix("_data").filter(|source| *source == "board_dig_in" || *source == "board_dig_out") {
+            if let Some(item) = self.unpack_digital_data(py, source)? {
+                return Ok(item);
+            }
+        }
+        match self.header.bind(py).get_item(key)? {
+            Some(item) => Ok(item.unbind()),
+            None => Err(PyKeyError::new_err(key.to_string())),
+        }
+    }
+
+    /// Detect spikes by threshold crossing on each amplifier channel of
+    /// `data[source]`, such as a spike band derived with `bands` when loading.
+    /// Detection on wideband `amplifier_data` also picks up slow field
+    /// potential swings.
+    ///
+    /// `threshold` is a value in microVolts for every channel, `"header"` (the
+    /// default) for each channel's spike scope threshold as set in RHX, or
+    /// `"rms"` or `"mad"` for `multiple` times each channel's RMS or
+    /// MAD-estimated noise level. Negative thresholds detect negative-going
+    /// spikes. Channels whose spike scope triggers on a digital input are
+    /// skipped with the `"header"` threshold.
+    ///
+    /// Returns a dict keyed by native channel name. Each entry holds the
+    /// `threshold` used, the `samples` and `times` at which spikes crossed it,
+    /// their peak `amplitudes` within `post_trigger_ms`, and `snippets`, one row
+    /// per spike running from `pre_trigger_ms` before the crossing to
+    /// `post_trigger_ms` after it (NaN past the ends of the data). A channel
+    /// cannot trigger again until `dead_time_ms` after a crossing.
+    #[pyo3(signature = (source="amplifier_data", threshold=None, multiple=-5.0, pre_trigger_ms=1.0, post_trigger_ms=2.0, dead_time_ms=1.0))]
+    #[allow(clippy::too_many_arguments)]
+    fn detect_spikes<'py>(&self, py: Python<'py>, source: &str, threshold: Option<ThresholdArg>, multiple: f32, pre_trigger_ms: f32, post_trigger_ms: f32, dead_time_ms: f32) -> PyResult<Bound<'py, PyDict>> {
+        let threshold = match threshold {
+            None => Threshold::Header,
+            Some(ThresholdArg::Value(value)) => Threshold::Fixed(value),
+            Some(ThresholdArg::Method(method)) => match method.as_str() {
+                "header" => Threshold::Header,
+                "rms" => Threshold::Rms(multiple),
+                "mad" => Threshold::Mad(multiple),
+                _ => return Err(PyValueError::new_err(format!("threshold must be a number, 'header', 'rms' or 'mad', not '{}'", method))),
+            },
+        };
+        let options = SpikeOptions { threshold, pre_trigger_ms, post_trigger_ms, dead_time_ms };
+
+        let Some(data) = self.data_item(py, source)? else {
+            return Err(PyKeyError::new_err(source.to_string()));
+        };
+        let data = data.bind(py).downcast::<PyArray2<f32>>()
+            .map_err(|_| PyValueError::new_err(format!("'{}' is not scaled amplifier data; load it without raw=True", source)))?
+            .readonly();
+        let data = data.as_array();
+
+        // Channel names and spike trigger settings from the header.
+        let header = self.header.bind(py);
+        let channel_names = self.amplifier_channel_names(py)?;
+        if channel_names.len() != data.nrows() {
+            return Err(PyValueError::new_err(format!("'{}' has {} rows but the recording has {} amplifier channels", source, data.nrows(), channel_names.len())));
+        }
+        let spike_triggers = match header.get_item("spike_triggers")? {
+            Some(triggers) => triggers.extract::<Vec<HashMap<String, i32>>>()?.iter()
+                .map(|trigger| SpikeTrigger {
+                    voltage_trigger_mode: trigger.get("voltage_trigger_mode").copied().unwrap_or_default(),
+                    voltage_threshold: trigger.get("voltage_threshold").copied().unwrap_or_default(),
+                    digital_trigger_channel: trigger.get("digital_trigger_channel").copied().unwrap_or_default(),
+                    digital_edge_polarity: trigger.get("digital_edge_polarity").copied().unwrap_or_default(),
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+
+        let sample_rate = self.sample_rate as f32;
+        let spikes = without_gil(py, || {
+            let thresholds = channel_thresholds(data, &spike_triggers, threshold)?;
+            detect_threshold_crossings(data, &thresholds, sample_rate, &options)
+        })?;
+
+        let t = self.times(py)?;
+        let result = PyDict::new_bound(py);
+        for (name, channel_spikes) in channel_names.into_iter().zip(spikes) {
+            let times: Vec<f64> = channel_spikes.samples.iter().map(|&sample| t.get(sample as usize).copied().unwrap_or(f64::NAN)).collect();
+            let samples: Vec<i64> = channel_spikes.samples.iter().map(|&sample| sample as i64).collect();
+            let entry = PyDict::new_bound(py);
+            entry.set_item("threshold", channel_spikes.threshold)?;
+            entry.set_item("samples", samples.into_pyarray_bound(py))?;
+            entry.set_item("times", times.into_pyarray_bound(py))?;
+            entry.set_item("amplitudes", channel_spikes.amplitudes.into_pyarray_bound(py))?;
+            entry.set_item("snippets", channel_spikes.snippets.into_pyarray_bound(py))?;
+            result.set_item(name, entry)?;
+        }
+        Ok(result)
+    }
+
+    /// Extract the stimulation pulses in `stim_data` as a table, one row per
+    /// pulse ordered by channel and onset.
+    ///
+    /// Each run of samples at the same non-zero current is a phase. Phases
+    /// belong to the same pulse until their charge balances or they are more
+    /// than `max_interphase_gap_ms` apart, and pulses belong to the same train
+    /// while each starts within `max_train_gap_ms` of the end of the last.
+    ///
+    /// Returns a dict of equal-length columns: `channel` (native channel
+    /// name), `train` and `pulse_in_train` (counting from 0 on each channel),
+    /// `onset_sample`, `offset_sample` (the sample after the pulse),
+    /// `onset_time` and `offset_time` in seconds, `polarity`
+    /// (`"cathodic_first"` or `"anodic_first"`), `pulse_width` (of the first
+    /// phase) and `interphase_gap` in seconds, `num_phases`, and
+    /// `phase_amplitudes`, one row per pulse of signed phase currents in
+    /// microAmps, padded with NaN. Raw recordings are converted with the
+    /// header's `stim_step_size`.
+    #[pyo3(signature = (max_interphase_gap_ms=1.0, max_train_gap_ms=100.0))]
+    fn stim_pulses<'py>(&self, py: Python<'py>, max_interphase_gap_ms: f32, max_train_gap_ms: f32) -> PyResult<Bound<'py, PyDict>> {
+        let options = StimPulseOptions { max_interphase_gap_ms, max_train_gap_ms };
+        let Some(stim_data) = self.data_item(py, "stim_data")? else {
+            return Err(PyKeyError::new_err("stim_data"));
+        };
+
+        // Raw recordings hold stimulation steps and sample numbers.
+        let stim_data = stim_data.bind(py);
+        let current: Array2<f32> = if let Ok(current) = stim_data.downcast::<PyArray2<f32>>() {
+            current.to_owned_array()
+        } else {
+            let stim_step_size = self.header.bind(py).get_item("stim_parameters")?
+                .and_then(|parameters| parameters.get_item("stim_step_size").ok())
+                .map_or(Ok(0.0), |step| step.extract::<f32>())?;
+            stim_data.downcast::<PyArray2<i32>>()?.to_owned_array().mapv(|steps| steps as f32 * stim_step_size / 1.0e-6)
+        };
+        let channel_names = self.amplifier_channel_names(py)?;
+
+        let times = self.times(py)?;
+        let sample_rate = self.sample_rate as f32;
+        let pulses = without_gil(py, || find_stim_pulses(current.view(), ArrayView1::from(&times), &channel_names, sample_rate, &options))?;
+
+        let max_phases = pulses.iter().map(|pulse| pulse.phases.len()).max().unwrap_or(0);
+        let mut phase_amplitudes = Array2::from_elem((pulses.len(), max_phases), f32::NAN);
+        for (mut row, pulse) in phase_amplitudes.outer_iter_mut().zip(&pulses) {
+            for (amplitude, phase) in row.iter_mut().zip(&pulse.phases) {
+                *amplitude = phase.amplitude;
+            }
+        }
+        let column = |value: fn(&StimPulse) -> i64| pulses.iter().map(value).collect::<Vec<i64>>().into_pyarray_bound(py);
+        let time_column = |value: fn(&StimPulse) -> f64| pulses.iter().map(value).collect::<Vec<f64>>().into_pyarray_bound(py);
+
+        let table = PyDict::new_bound(py);
+        table.set_item("channel", pulses.iter().map(|pulse| pulse.channel.as_str()).collect::<Vec<&str>>())?;
+        table.set_item("train", column(|pulse| pulse.train as i64))?;
+        table.set_item("pulse_in_train", column(|pulse| pulse.pulse_in_train as i64))?;
+        table.set_item("onset_sample", column(|pulse| pulse.onset_sample as i64))?;
+        table.set_item("offset_sample", column(|pulse| pulse.offset_sample as i64))?;
+        table.set_item("onset_time", time_column(|pulse| pulse.onset_time))?;
+        table.set_item("offset_time", time_column(|pulse| pulse.offset_time))?;
+        table.set_item("polarity", pulses.iter().map(|pulse| pulse.polarity.name()).collect::<Vec<&str>>())?;
+        table.set_item("pulse_width", time_column(|pulse| pulse.pulse_width))?;
+        table.set_item("interphase_gap", time_column(|pulse| pulse.interphase_gap))?;
+        table.set_item("num_phases", column(|pulse| pulse.phases.len() as i64))?;
+        table.set_item("phase_amplitudes", phase_amplitudes.into_pyarray_bound(py))?;
+        Ok(table)
+    }
+
+    /// The state of one digital input or output channel, by native or custom
+    /// name, as a boolean array with one value per sample.
+    fn digital_channel<'py>(&self, py: Python<'py>, name: &str) -> PyResult<Bound<'py, PyArray1<bool>>> {
+        for source in ["board_dig_in", "board_dig_out"] {
+            let Some((words, channels)) = self.digital_word(py, source)? else {
+                continue;
+            };
+            if let Some((_, _, native_order)) = channels.iter().find(|(native_name, custom_name, _)| native_name == name || custom_name == name) {
+                return Ok(digital_channel(words.view(), *native_order)?.into_pyarray_bound(py));
+            }
+        }
+        Err(PyKeyError::new_err(format!("No digital channel named '{}'", name)))
+    }
+
+    /// Find the rising and falling edges of each digital input (`source=
+    /// "board_dig_in"`) or output (`"board_dig_out"`) channel.
+    ///
+    /// Returns a dict keyed by native channel name. Each entry holds the
+    /// `rising_samples` and `falling_samples` at which the channel went high
+    /// and low, counted from the first sample loaded, and the matching
+    /// `rising_times` and `falling_times` in seconds. The first sample is
+    /// never an edge.
+    #[pyo3(signature = (source="board_dig_in"))]
+    fn digital_edges<'py>(&self, py: Python<'py>, source: &str) -> PyResult<Bound<'py, PyDict>> {
+        let (words, channels) = self.require_digital_word(py, source)?;
+        let times = Array1::from(self.times(py)?);
+        let result = PyDict::new_bound(py);
+        for (name, _, native_order) in channels {
+            let edges = find_bit_edges(words.view(), times.view(), &name, native_order)?;
+            let to_i64 = |samples: Vec<u64>| samples.into_iter().map(|sample| sample as i64).collect::<Vec<i64>>();
+            let entry = PyDict::new_bound(py);
+            entry.set_item("rising_samples", to_i64(edges.rising_samples).into_pyarray_bound(py))?;
+            entry.set_item("rising_times", edges.rising_times.into_pyarray_bound(py))?;
+            entry.set_item("falling_samples", to_i64(edges.falling_samples).into_pyarray_bound(py))?;
+            entry.set_item("falling_times", edges.falling_times.into_pyarray_bound(py))?;
+            result.set_item(edges.channel, entry)?;
+        }
+        Ok(result)
+    }
+
+    /// Decode codes written to a group of digital channels, such as trial
+    /// numbers sent on DIGITAL-IN-01 to 08.
+    ///
+    /// `bits` names the channels holding the code, by native or custom name,
+    /// least significant bit first. Codes are read on each rising edge of
+    /// the `strobe` channel or, without one, whenever the bits change to a
+    /// non-zero value, `settle_ms` after the edge or change so that bits
+    /// switching slightly late are not missed.
+    ///
+    /// Returns a dict with the `samples` and `times` (in seconds) of each
+    /// edge or change and the `codes` read there.
+    #[pyo3(signature = (bits, strobe=None, source="board_dig_in", settle_ms=0.0))]
+    fn strobed_words<'py>(&self, py: Python<'py>, bits: Vec<String>, strobe: Option<String>, source: &str, settle_ms: f32) -> PyResult<Bound<'py, PyDict>> {
+        let (words, channels) = self.require_digital_word(py, source)?;
+        let native_order = |name: &String| {
+            channels.iter()
+                .find(|(native_name, custom_name, _)| native_name == name || custom_name == name)
+                .map(|(_, _, native_order)| *native_order)
+                .ok_or_else(|| PyValueError::new_err(format!("No {} channel named '{}'", source, name)))
+        };
+        let options = StrobedWordOptions {
+            bits: bits.iter().map(native_order).collect::<PyResult<Vec<i32>>>()?,
+            strobe: strobe.as_ref().map(native_order).transpose()?,
+            settle_ms,
+        };
+        let times = Array1::from(self.times(py)?);
+        let decoded = decode_strobed_words(words.view(), times.view(), self.sample_rate as f32, &options)?;
+
+        let result = PyDict::new_bound(py);
+        result.set_item("samples", decoded.samples.into_iter().map(|sample| sample as i64).collect::<Vec<i64>>().into_pyarray_bound(py))?;
+        result.set_item("times", decoded.times.into_pyarray_bound(py))?;
+        result.set_item("codes", decoded.codes.into_iter().map(|code| code as i64).collect::<Vec<i64>>().into_pyarray_bound(py))?;
+        Ok(result)
+    }
+
+    fn __contains__(&self, py: Python, key: &str) -> PyResult<bool> {
+        let digital_data = match key {
+            "board_dig_in_data" => self.digital_word(py, "board_dig_in")?.is_some(),
+            "board_dig_out_data" => self.digital_word(py, "board_dig_out")?.is_some(),
+            _ => false,
+        };
+        Ok(digital_data || self.data.bind(py).contains(key)? || self.header.bind(py).contains(key)?)
+    }
+
+    fn __repr__(&self, py: Python) -> String {
+        format!(
+            "IntanRecording(num_samples={}, sample_rate={}, duration={:.3}s, signals=[{}])",
+            self.num_samples,
+            self.sample_rate,
+            self.duration,
+            self.data.bind(py).keys().iter().map(|key| key.to_string()).collect::<Vec<_>>().join(", "),
+        )
+    }
+}
+
+/// Load an Intan recording and return it as an `IntanRecording`.
+///
+/// `path` may be a traditional .rhs or .rhd file, or the directory (or its
+/// info.rhs header) of a One File Per Signal Type or One File Per Channel
+/// session; the save format is detected automatically.
+///
+/// With `raw=True`, analog signals are returned as unscaled uint16 arrays and
+/// timestamps as sample numbers. The gain, offset and units converting each
+/// signal are listed in `header["scale_factors"]`.
+///
+/// To load only part of the recording, give `start_sample`/`stop_sample` as
+/// sample indices or `start_time`/`stop_time` in seconds, both counted from
+/// the first sample in the file. `stop` is exclusive and is clipped to the end
+/// of the recording. Only the data blocks covering the range are read. RHD
+/// files sample auxiliary inputs, supply voltage and temperature sensors at
+/// lower rates within each data block, so their ranges are widened to whole
+/// data blocks (60 or 128 samples) for every signal; `t` gives the samples
+/// actually loaded.
+///
+/// To load only some channels, give `channels` as a list of native
+/// (`"A-000"`) or custom channel names, and/or `port` (`"B"`) to load every
+/// amplifier channel on that port. Only the selected channels are allocated,
+/// and the channel lists in the header match the selection.
+///
+/// With `num_threads`, the data blocks of traditional files are decoded on
+/// that many threads (0 for one per core). The GIL is released while loading.
+///
+/// Files that cannot be read raise a subclass of `IntanError`:
+/// `IntanFormatError` for files that are not valid RHS/RHD recordings,
+/// `TruncatedFileError` (a kind of `IntanFormatError`) for files that end
+/// early, and `ChannelNotFoundError` for unknown channels or ports. Missing
+/// files raise the usual `OSError` subclasses.
+///
+/// With `lenient=True`, a traditional file that ends partway through a data
+/// block (a crashed or still-running recording) is loaded up to its last
+/// complete block instead of raising `TruncatedFileError`. The number of
+/// bytes left over is given by `dropped_bytes`, and blocks whose timestamps
+/// break the sequence are loaded as they are and listed in `corrupted_blocks`.
+///
+/// Amplifier data is not notch filtered unless `notch` is `"auto"` or
+/// `"force"`. `"auto"` applies the mains notch filter that was on during
+/// recording, only for files saved by software older than RHX 3.0 (newer
+/// versions saved the data already filtered), as Intan's own readers do.
+/// `"force"` always filters. `notch_frequency` overrides the 50 or 60 Hz
+/// setting in the header, `notch_bandwidth` is the width of each notch in Hz,
+/// and `notch_harmonics=3` also removes the second and third harmonics
+/// (100/120 and 150/180 Hz). Raw data is never filtered.
+///
+/// `bands` derives frequency bands from the amplifier data with Butterworth
+/// filters of order `filter_order`, for example
+/// `bands={"spike_band": (300, 6000), "lfp": (1, 300)}`. Each band is a
+/// (low, high) pair of cutoffs in Hz, with None for a high-pass or low-pass
+/// filter, and is returned in `data` under its name, filtered in parallel
+/// across channels. `amplifier_data` itself is not band filtered.
+///
+/// With `zero_phase=True`, every filter stage is run forward and then
+/// backward over the padded signal, like SciPy's `filtfilt`, so spike
+/// waveforms are not shifted or distorted by the filter's phase response.
+///
+/// `artifacts` removes stimulation artifacts from the amplifier data of .rhs
+/// recordings before it is filtered: `"zero"` or `"nan"` blanks them,
+/// `"interpolate"` bridges them with a straight line and `"template"`
+/// subtracts each channel's mean artifact. The samples treated are those
+/// flagged in `amp_settle_data` or `charge_recovery_data` or with non-zero
+/// `stim_data`, from `artifact_pre_ms` before to `artifact_post_ms` after
+/// each flagged stretch, on every channel at once with
+/// `artifact_all_channels=True`. NaN blanking also applies to `bands`.
+///
+/// A summary of the header and the data, progress and warnings such as
+/// timestamp gaps are printed while loading, unless `verbose=False`.
+#[pyfunction]
+#[pyo3(signature = (path, *args, **kwargs))]
+fn load(py: Python, path: String, args: &Bound<PyTuple>, kwargs: Option<&Bound<PyDict>>) -> PyResult<IntanRecording> {
+    let options = load_options(py, args, kwargs)?;
+    let result = without_gil(py, || match import_dat::detect_save_format(&path) {
+        SaveFormat::Traditional => import_hash::load_file(&path, &options),
+        SaveFormat::OneFilePerSignalType => import_dat::load_file_per_signal_type(&path, &options),
+        SaveFormat::OneFilePerChannel => import_dat::load_file_per_channel(&path, &options),
+    });
+    let (result_out, data_present) = result?;
+    IntanRecording::from_result(py, result_out, data_present)
+}
+
+/// The files of a series: a list of paths in recording order, or a glob
+/// pattern matching them.
+#[derive(FromPyObject)]
+enum FileSeries {
+    Pattern(String),
+    Paths(Vec<String>),
+}
+
+/// Load a recording split across consecutive .rhs or .rhd files, such as
+/// those written by RHX's "new file every N minutes" option, as one
+/// `IntanRecording`.
+///
+/// `files` is a list of paths in recording order or a glob pattern such as
+/// `"rat1_240101_*.rhs"`, whose matches are taken in name order. Every file
+/// must have been recorded with the same channels and settings, otherwise
+/// `IntanFormatError` is raised. Sample and time ranges count from the start
+/// of the first file; the other arguments are as for `load`. Artifacts are
+/// removed and filters run over the joined files, not file by file.
+///
+/// `header["series_files"]` gives the path, first sample and number of
+/// samples of each file loaded. `header["timestamp_gaps"]` lists each file
+/// whose timestamps do not follow on from the file before it, with the sample
+/// it starts at and the number of samples missing in between.
+#[pyfunction]
+#[pyo3(signature = (files, *args, **kwargs))]
+fn load_series(py: Python, files: FileSeries, args: &Bound<PyTuple>, kwargs: Option<&Bound<PyDict>>) -> PyResult<IntanRecording> {
+    let options = load_options(py, args, kwargs)?;
+    let file_paths = match files {
+        FileSeries::Pattern(pattern) => import_series::find_file_series(&pattern)?,
+        FileSeries::Paths(paths) => paths,
+    };
+    let (result_out, data_present) = without_gil(py, || import_series::load_file_series(&file_paths, &options))?;
+    IntanRecording::from_result(py, result_out, data_present)
+}
+
+/// Header information and data size of a recording, read without loading
+/// any sample data.
+#[pyclass(module = "intan_import_py", name = "HeaderInfo")]
+struct PyHeaderInfo {
+    header: Py<PyDict>,
+    /// False if the file only holds a header and no data blocks.
+    #[pyo3(get)]
+    data_present: bool,
+    /// Number of data blocks following the header.
+    #[pyo3(get)]
+    num_blocks: u64,
+    /// Number of amplifier samples per channel.
+    #[pyo3(get)]
+    num_samples: u64,
+    /// Record duration in milliseconds.
+    #[pyo3(get)]
+    duration_ms: f64,
+}
+
+#[pymethods]
+impl PyHeaderInfo {
+    /// Header information, keyed as in the dictionary returned by `load_file_wrapper`.
+    #[getter]
+    fn header(&self, py: Python) -> Py<PyDict> {
+        self.header.clone_ref(py)
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "HeaderInfo(num_blocks={}, num_samples={}, duration_ms={:.3})",
+            self.num_blocks, self.num_samples, self.duration_ms,
+        )
+    }
+}
+
+/// Read only the header of an Intan recording.
+///
+/// Returns a `HeaderInfo` with the header, the number of data blocks and
+/// samples, and the record duration in milliseconds. The size of the data is
+/// worked out from the file sizes, so no sample data is read. With
+/// `lenient=True`, a traditional file that ends partway through a data block
+/// is counted up to its last complete block, as `load` reads it, instead of
+/// raising `TruncatedFileError`.
+#[pyfunction]
+#[pyo3(signature = (path, lenient=false))]
+fn read_header_info(py: Python, path: String, lenient: bool) -> PyResult<PyHeaderInfo> {
+    let result = match import_dat::detect_save_format(&path) {
+        SaveFormat::Traditional => import_hash::read_header_info(&path, lenient),
+        _ => import_dat::read_session_header_info(&path),
+    };
+    let info = result?;
+
+    let header = PyDict::new_bound(py);
+    for (key, value) in &info.header.to_dict() {
+        header.set_item(key, data_type_to_py_object(py, value)?)?;
+    }
+
+    Ok(PyHeaderInfo {
+        header: header.unbind(),
+        data_present: info.data_present,
+        num_blocks: info.num_blocks,
+        num_samples: info.num_samples,
+        duration_ms: info.duration_ms,
+    })
+}
+
+/// A traditional .rhs or .rhd file mapped into memory.
+///
+/// `signal(name)` returns a read-only numpy view of the unscaled samples,
+/// indexed as `[block, channel, sample in block]`, straight over the file.
+/// Slicing it only reads the pages it touches, so even very large recordings
+/// can be explored without loading them. Use `signals` for the available
+/// names and `header["scale_factors"]` from `load(..., raw=True)` to convert
+/// samples to physical units.
+#[pyclass(module = "intan_import_py", name = "MappedRecording")]
+struct PyMappedRecording {
+    // Supplies the header, layout and size checks. Its own mapping is never
+    // read from Python, so it only costs address space.
+    recording: import_mmap::MappedRecording,
+    // Views are created over numpy's own mapping of the file rather than
+    // over `recording`, because numpy keeps it alive for as long as any view
+    // exists and can view the timestamps, which the header only keeps 16-bit
+    // aligned, in place as int32.
+    memmap: PyObject,
+}
+
+#[pymethods]
+impl PyMappedRecording {
+    /// Number of data blocks in the file.
+    #[getter]
+    fn num_blocks(&self) -> usize {
+        self.recording.num_blocks()
+    }
+
+    /// Number of amplifier samples per channel.
+    #[getter]
+    fn num_samples(&self) -> usize {
+        self.recording.num_samples()
+    }
+
+    /// Header information, keyed as in the dictionary returned by `load_file_wrapper`.
+    #[getter]
+    fn header<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let header = PyDict::new_bound(py);
+        for (key, value) in &self.recording.header().to_dict() {
+            header.set_item(key, data_type_to_py_object(py, value)?)?;
+        }
+        Ok(header)
+    }
+
+    /// Names of the signals present in the file.
+    #[getter]
+    fn signals(&self) -> Vec<String> {
+        self.recording.layout().iter().map(|signal| signal.data_key.clone()).collect()
+    }
+
+    /// A zero-copy view of one signal, indexed as `[block, channel, sample in block]`.
+    /// Timestamps (`"t"`) are int32, all other signals uint16.
+    fn signal(&self, py: Python, name: &str) -> PyResult<PyObject> {
+        let signal = self.recording.signal_layout(name).map_err(|e| PyKeyError::new_err(format!("{}", e)))?;
+        let numpy = py.import_bound("numpy")?;
+        let dtype = if signal.bytes_per_sample == 4 { "<i4" } else { "<u2" };
+
+        let kwargs = PyDict::new_bound(py);
+        kwargs.set_item("shape", PyTuple::new_bound(py, [self.recording.num_blocks(), signal.num_channels, signal.samples_per_block]))?;
+        kwargs.set_item("dtype", dtype)?;
+        kwargs.set_item("buffer", self.memmap.bind(py))?;
+        kwargs.set_item("offset", signal.offset)?;
+        kwargs.set_item("strides", PyTuple::new_bound(py, [signal.block_stride, signal.channel_stride, signal.sample_stride]))?;
+        Ok(numpy.getattr("ndarray")?.call((), Some(&kwargs))?.unbind())
+    }
+
+    /// A zero-copy view of one channel of a signal, indexed as `[block, sample in block]`.
+    fn channel(&self, py: Python, name: &str, index: usize) -> PyResult<PyObject> {
+        let signal = self.signal(py, name)?;
+        let all = PySlice::full_bound(py);
+        Ok(signal.bind(py).get_item((all.clone(), index, all))?.unbind())
+    }
+
+    fn __repr__(&self) -> String {
+        format!("MappedRecording(num_blocks={}, num_samples={}, signals=[{}])", self.recording.num_blocks(), self.recording.num_samples(), self.signals().join(", "))
+    }
+}
+
+/// Map a traditional .rhs or .rhd file into memory without reading its data.
+/// See `MappedRecording`.
+#[pyfunction]
+fn open_mapped(py: Python, path: String) -> PyResult<PyMappedRecording> {
+    let recording = import_mmap::MappedRecording::open(&path)?;
+
+    let kwargs = PyDict::new_bound(py);
+    kwargs.set_item("dtype", "uint8")?;
+    kwargs.set_item("mode", "r")?;
+    let memmap = py.import_bound("numpy")?.getattr("memmap")?.call((path,), Some(&kwargs))?.unbind();
+
+    Ok(PyMappedRecording { recording, memmap })
+}
+
+/// Iterates over a traditional .rhs or .rhd file a chunk of data blocks at a
+/// time. See `iter_blocks`.
+#[pyclass(module = "intan_import_py", name = "BlockIterator")]
+struct PyBlockIterator {
+    blocks: import_stream::BlockIterator,
+}
+
+#[pymethods]
+impl PyBlockIterator {
+    /// Header information for the selected channels, keyed as in the
+    /// dictionary returned by `load_file_wrapper`.
+    #[getter]
+    fn header<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let header = PyDict::new_bound(py);
+        for (key, value) in &self.blocks.header().to_dict() {
+            header.set_item(key, data_type_to_py_object(py, value)?)?;
+        }
+        Ok(header)
+    }
+
+    /// Gain, offset and units converting the samples of each signal to
+    /// physical units, for chunks read with `raw=True`.
+    #[getter]
+    fn scale_factors(&self, py: Python) -> PyResult<PyObject> {
+        data_type_to_py_object(py, &import_hash::scale_factors_to_result(self.blocks.header()))
+    }
+
+    /// First and last (exclusive) sample the iterator reads.
+    #[getter]
+    fn sample_range(&self) -> (u64, u64) {
+        let sample_range = self.blocks.sample_range();
+        (sample_range.start, sample_range.end)
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python) -> PyResult<Option<PyObject>> {
+        let blocks = &mut self.blocks;
+        let Some(chunk) = py.allow_threads(|| blocks.next()) else {
+            return Ok(None);
+        };
+        let chunk = chunk?;
+
+        let data = PyDict::new_bound(py);
+        data.set_item("first_sample", chunk.first_sample)?;
+        for (key, value) in chunk.data {
+            match value {
+                DataType::Array(arrays) => data.set_item(key, arrays_into_py(py, arrays))?,
+                other => data.set_item(key, data_type_to_py_object(py, &other)?)?,
+            }
+        }
+        Ok(Some(data.into()))
+    }
+}
+
+/// Read a traditional .rhs or .rhd file `blocks_per_chunk` data blocks at a
+/// time, without loading the whole recording into memory.
+///
+/// Each item is a dict of numpy arrays under the same keys as `load`, holding
+/// the timestamps and signals of one chunk, plus `first_sample`, the index of
+/// the chunk's first sample in the file. The remaining arguments are as for
+/// `load`, except that `notch`, `bands` and `artifacts` raise `ValueError`,
+/// since the filters would restart at every chunk.
+#[pyfunction]
+#[pyo3(signature = (path, blocks_per_chunk=1000, *args, **kwargs))]
+fn iter_blocks(py: Python, path: String, blocks_per_chunk: usize, args: &Bound<PyTuple>, kwargs: Option<&Bound<PyDict>>) -> PyResult<PyBlockIterator> {
+    let options = load_options(py, args, kwargs)?;
+    let blocks = import_stream::BlockIterator::open(&path, blocks_per_chunk, &options)?;
+    Ok(PyBlockIterator { blocks })
+}
+
+/// Filter each channel (row) of `data`, a float32 array of channels by
+/// samples such as `amplifier_data`, with a Butterworth filter of `order`,
+/// returning the filtered copy.
+///
+/// `low` alone gives a high-pass filter, `high` alone a low-pass filter and
+/// both a band-pass filter, with cutoffs in Hz. Channels are filtered in
+/// parallel, on `num_threads` threads if given, with the GIL released.
+/// `zero_phase=True` filters forward and then backward, as in `load`.
+#[pyfunction]
+#[pyo3(signature = (data, sample_rate, low=None, high=None, order=4, zero_phase=false, num_threads=None))]
+#[allow(clippy::too_many_arguments)]
+fn butterworth_filter<'py>(py: Python<'py>, data: PyReadonlyArray2<'py, f32>, sample_rate: f32, low: Option<f32>, high: Option<f32>, order: usize, zero_phase: bool, num_threads: Option<usize>) -> PyResult<Bound<'py, PyArray2<f32>>> {
+    let filter = ButterworthFilter::new(filter_band(low, high)?, order, sample_rate)?;
+    let mut filtered = data.as_array().to_owned();
+    without_gil(py, || {
+        let pool = num_threads.map(thread_pool).transpose()?;
+        let mut filter_data = || {
+            if zero_phase {
+                filter_channels(&mut filtered, |signal| filter.filter_zero_phase(signal));
+            } else {
+                filter_channels(&mut filtered, |signal| filter.filter(signal));
+            }
+        };
+        match &pool {
+            Some(pool) => pool.install(filter_data),
+            None => filter_data(),
+        }
+        Ok(())
+    })?;
+    Ok(filtered.into_pyarray_bound(py))
+}
+
+#[pymodule]
+fn intan_import_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    let py = m.py();
+    m.add("IntanError", py.get_type_bound::<IntanError>())?;
+    m.add("IntanFormatError", py.get_type_bound::<IntanFormatError>())?;
+    m.add("TruncatedFileError", py.get_type_bound::<TruncatedFileError>())?;
+    m.add("ChannelNotFoundError", py.get_type_bound::<ChannelNotFoundError>())?;
+    m.add_class::<IntanRecording>()?;
+    m.add_class::<PyHeaderInfo>()?;
+    m.add_function(wrap_pyfunction!(load, m)?)?;
+    m.add_function(wrap_pyfunction!(load_series, m)?)?;
+    m.add_function(wrap_pyfunction!(read_header_info, m)?)?;
+    m.add_class::<PyMappedRecording>()?;
+    m.add_function(wrap_pyfunction!(open_mapped, m)?)?;
+    m.add_class::<PyBlockIterator>()?;
+    m.add_function(wrap_pyfunction!(iter_blocks, m)?)?;
+    m.add_function(wrap_pyfunction!(butterworth_filter, m)?)?;
+    m.add_function(wrap_pyfunction!(load_file_wrapper, m)?)?;
+    m.add_function(wrap_pyfunction!(load_file_per_signal_type_wrapper, m)?)?;
+    m.add_function(wrap_pyfunction!(load_file_per_channel_wrapper, m)?)?;
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import_hash::load_file;
+    use crate::test_files::{test_dir, write_rhs};
+
+    fn load_error(path: &str, options: &LoadOptions) -> RhsError {
+        match load_file(path, options) {
+            Err(error) => error,
+            Ok(_) => panic!("{} loaded without an error", path),
+        }
+    }
+
+    #[test]
+    fn errors_map_to_python_exceptions() {
+        let dir = test_dir("python_errors");
+        let path = dir.join("recording.rhs");
+        write_rhs(&path, 2, &(0..2 * 128).collect::<Vec<i32>>(), |_, _| 0);
+        let bytes = std::fs::read(&path).unwrap();
+        let path = path.to_str().unwrap();
+
+        let channels = LoadOptions { channels: Some(vec!["A-007".to_string()]), ..Default::default() };
+        assert_eq!(PyErrorKind::from(&load_error(path, &channels)), PyErrorKind::ChannelNotFound);
+        let port = LoadOptions { port: Some("D".to_string()), ..Default::default() };
+        assert_eq!(PyErrorKind::from(&load_error(path, &port)), PyErrorKind::ChannelNotFound);
+        let range = LoadOptions { start: Some(Position::Sample(200)), stop: Some(Position::Sample(100)), ..Default::default() };
+        assert_eq!(PyErrorKind::from(&load_error(path, &range)), PyErrorKind::Value);
+
+        // Files cut short in the data blocks or in the header.
+        let truncated = dir.join("truncated.rhs");
+        std::fs::write(&truncated, &bytes[..bytes.len() - 10]).unwrap();
+        assert_eq!(PyErrorKind::from(&load_error(truncated.to_str().unwrap(), &LoadOptions::default())), PyErrorKind::TruncatedFile);
+        std::fs::write(&truncated, &bytes[..10]).unwrap();
+        assert_eq!(PyErrorKind::from(&load_error(truncated.to_str().unwrap(), &LoadOptions::default())), PyErrorKind::TruncatedFile);
+
+        // More bytes than the data blocks account for is a format error
+        // rather than a truncation.
+        let oversized = RhsError::FileSizeError { context: String::new(), size: 20, expected: 10 };
+        assert_eq!(PyErrorKind::from(&oversized), PyErrorKind::IntanFormat);
+        let unrecognized = dir.join("unrecognized.rhs");
+        std::fs::write(&unrecognized, [0u8; 64]).unwrap();
+        assert_eq!(PyErrorKind::from(&load_error(unrecognized.to_str().unwrap(), &LoadOptions::default())), PyErrorKind::IntanFormat);
+        let incompatible = RhsError::IncompatibleHeaderError { path: path.to_string(), field: "sample_rate".to_string() };
+        assert_eq!(PyErrorKind::from(&incompatible), PyErrorKind::IntanFormat);
+
+        let missing = dir.join("missing.rhs");
+        assert_eq!(PyErrorKind::from(&load_error(missing.to_str().unwrap(), &LoadOptions::default())), PyErrorKind::OsError);
+    }
+}
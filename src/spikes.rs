@@ -0,0 +1,297 @@
+// External crates
+use ndarray::{s, Array2, ArrayView1, ArrayView2};
+use rayon::prelude::*;
+
+// Local modules
+use crate::header::{RhsHeader, SpikeTrigger};
+use crate::import_hash::RhsError;
+
+// Median absolute deviation of Gaussian noise, in standard deviations.
+const MAD_PER_SIGMA: f32 = 0.6745;
+
+/// How `detect_spikes` sets the threshold of each channel, in microVolts.
+/// Negative thresholds detect negative-going spikes and positive thresholds
+/// positive-going ones.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Threshold {
+    /// The voltage threshold of each channel's spike scope trigger, as set in
+    /// RHX and saved in `spike_triggers`. Channels whose spike scope triggers
+    /// on a digital input are skipped.
+    Header,
+    /// The same threshold on every channel.
+    Fixed(f32),
+    /// This multiple of each channel's RMS, such as -5.0. Channels whose RMS
+    /// is zero are skipped.
+    Rms(f32),
+    /// This multiple of each channel's noise level, estimated from its median
+    /// absolute deviation (MAD / 0.6745) so that the spikes themselves barely
+    /// raise it. Channels whose MAD is zero are skipped.
+    Mad(f32),
+}
+
+/// Options controlling how `detect_spikes` finds and cuts out spikes.
+#[derive(Debug, Clone)]
+pub struct SpikeOptions {
+    pub threshold: Threshold,
+    /// Length of each snippet before the threshold crossing, in ms.
+    pub pre_trigger_ms: f32,
+    /// Length of each snippet from the threshold crossing on, in ms. The peak
+    /// of each spike is searched for over the same window.
+    pub post_trigger_ms: f32,
+    /// Time after a threshold crossing during which the same channel cannot
+    /// trigger again, in ms.
+    pub dead_time_ms: f32,
+}
+
+impl Default for SpikeOptions {
+    fn default() -> SpikeOptions {
+        SpikeOptions { threshold: Threshold::Header, pre_trigger_ms: 1.0, post_trigger_ms: 2.0, dead_time_ms: 1.0 }
+    }
+}
+
+/// Spikes detected on one channel.
+#[derive(Debug, Clone)]
+pub struct ChannelSpikes {
+    /// Threshold used, in microVolts, or `None` if the channel was skipped.
+    pub threshold: Option<f32>,
+    /// Index of the sample at which each spike crossed the threshold, counted
+    /// from the first sample of the data.
+    pub samples: Vec<u64>,
+    /// Peak of each spike in microVolts: its most extreme sample within the
+    /// post-trigger window.
+    pub amplitudes: Vec<f32>,
+    /// One row per spike, running from the pre-trigger to the end of the
+    /// post-trigger window, so the threshold crossing lies at column
+    /// `pre_trigger_samples`. Samples beyond either end of the data are NaN.
+    pub snippets: Array2<f32>,
+    /// Number of samples in each snippet before the threshold crossing.
+    pub pre_trigger_samples: usize,
+}
+
+/// Detect spikes by threshold crossing on each channel (row) of `data`,
+/// amplifier data in microVolts loaded with `header`, and return the spikes
+/// of each channel in the same order.
+///
+/// Detection is meant for high-pass or band-pass filtered data, such as a
+/// spike band derived with `LoadOptions::bands`; on wideband data, slow local
+/// field potential swings cross the threshold too. Channels are searched in
+/// parallel.
+pub fn detect_spikes(data: ArrayView2<f32>, header: &RhsHeader, options: &SpikeOptions) -> std::result::Result<Vec<ChannelSpikes>, RhsError> {
+    let thresholds = channel_thresholds(data, &header.spike_triggers, options.threshold)?;
+    detect_threshold_crossings(data, &thresholds, header.sample_rate, options)
+}
+
+/// The threshold of each channel (row) of `data` in microVolts, or `None`
+/// for channels to skip. `spike_triggers` is only needed for
+/// `Threshold::Header`.
+pub fn channel_thresholds(data: ArrayView2<f32>, spike_triggers: &[SpikeTrigger], threshold: Threshold) -> std::result::Result<Vec<Option<f32>>, RhsError> {
+    let check_finite = |value: f32| {
+        if !value.is_finite() {
+            return Err(RhsError::InvalidArgumentError(format!("Spike threshold {} is not a finite number", value)));
+        }
+        Ok(value)
+    };
+
+    match threshold {
+        Threshold::Header => {
+            if spike_triggers.len() != data.nrows() {
+                return Err(RhsError::InvalidArgumentError(format!("{} spike trigger settings for {} channels of data", spike_triggers.len(), data.nrows())));
+            }
+            // Mode 1 triggers on the voltage threshold; mode 0 on a digital
+            // input.
+            Ok(spike_triggers.iter()
+                .map(|trigger| (trigger.voltage_trigger_mode == 1).then_some(trigger.voltage_threshold as f32))
+                .collect())
+        },
+        Threshold::Fixed(value) => Ok(vec![Some(check_finite(value)?); data.nrows()]),
+        Threshold::Rms(multiple) => {
+            let multiple = check_finite(multiple)?;
+            Ok(data.outer_iter().map(|channel| noise_threshold(multiple, rms(channel))).collect())
+        },
+        Threshold::Mad(multiple) => {
+            let multiple = check_finite(multiple)?;
+            Ok(data.outer_iter().map(|channel| noise_threshold(multiple, median_absolute_deviation(channel) / MAD_PER_SIGMA)).collect())
+        },
+    }
+}
+
+// A threshold of `multiple` times a channel's noise level. A channel without
+// noise, such as a constant or blanked one, would get a threshold of zero and
+// trigger on every crossing of it, so it is skipped instead.
+fn noise_threshold(multiple: f32, noise: f32) -> Option<f32> {
+    (noise > 0.0).then_some(multiple * noise)
+}
+
+/// Detect spikes on each channel (row) of `data`, sampled at `sample_rate` Hz,
+/// against the given threshold of each channel; `options.threshold` is not
+/// used.
+pub fn detect_threshold_crossings(data: ArrayView2<f32>, thresholds: &[Option<f32>], sample_rate: f32, options: &SpikeOptions) -> std::result::Result<Vec<ChannelSpikes>, RhsError> {
+    if thresholds.len() != data.nrows() {
+        return Err(RhsError::InvalidArgumentError(format!("{} thresholds for {} channels of data", thresholds.len(), data.nrows())));
+    }
+    let to_samples = |name: &str, ms: f32| {
+        if !ms.is_finite() || ms < 0.0 {
+            return Err(RhsError::InvalidArgumentError(format!("{} must be zero or more milliseconds, not {}", name, ms)));
+        }
+        Ok((ms * sample_rate / 1000.0).round() as usize)
+    };
+    let pre_trigger_samples = to_samples("Pre-trigger time", options.pre_trigger_ms)?;
+    let post_trigger_samples = to_samples("Post-trigger time", options.post_trigger_ms)?.max(1);
+    let dead_time_samples = to_samples("Dead time", options.dead_time_ms)?.max(1);
+
+    Ok((0..data.nrows()).into_par_iter()
+        .map(|row| {
+            let mut spikes = ChannelSpikes {
+                threshold: thresholds[row],
+                samples: Vec::new(),
+                amplitudes: Vec::new(),
+                snippets: Array2::zeros((0, pre_trigger_samples + post_trigger_samples)),
+                pre_trigger_samples,
+            };
+            if let Some(threshold) = thresholds[row] {
+                detect_channel(data.row(row), threshold, post_trigger_samples, dead_time_samples, &mut spikes);
+            }
+            spikes
+        })
+        .collect())
+}
+
+fn detect_channel(channel: ArrayView1<f32>, threshold: f32, post_trigger_samples: usize, dead_time_samples: usize, spikes: &mut ChannelSpikes) {
+    let negative = threshold < 0.0;
+    let beyond = |x: f32| if negative { x <= threshold } else { x >= threshold };
+    let num_samples = channel.len();
+
+    let mut i = 1;
+    while i < num_samples {
+        if !beyond(channel[i]) || beyond(channel[i - 1]) {
+            i += 1;
+            continue;
+        }
+
+        // The peak is the most extreme sample in the post-trigger window.
+        let window = channel.slice(s![i..(i + post_trigger_samples).min(num_samples)]);
+        let peak = if negative {
+            window.iter().copied().fold(f32::INFINITY, f32::min)
+        } else {
+            window.iter().copied().fold(f32::NEG_INFINITY, f32::max)
+        };
+        spikes.samples.push(i as u64);
+        spikes.amplitudes.push(peak);
+
+        i += dead_time_samples;
+    }
+
+    // Cut out the snippets, leaving NaN where they run past the data.
+    let pre_trigger_samples = spikes.pre_trigger_samples;
+    let snippet_length = pre_trigger_samples + post_trigger_samples;
+    spikes.snippets = Array2::from_elem((spikes.samples.len(), snippet_length), f32::NAN);
+    for (mut snippet, &sample) in spikes.snippets.outer_iter_mut().zip(&spikes.samples) {
+        let sample = sample as usize;
+        let start = sample.saturating_sub(pre_trigger_samples);
+        let end = (sample + post_trigger_samples).min(num_samples);
+        let offset = start + pre_trigger_samples - sample;
+        snippet.slice_mut(s![offset..offset + end - start]).assign(&channel.slice(s![start..end]));
+    }
+}
+
+fn rms(channel: ArrayView1<f32>) -> f32 {
+    let mean = channel.iter().map(|&x| x as f64).sum::<f64>() / channel.len().max(1) as f64;
+    let mean_square = channel.iter().map(|&x| (x as f64 - mean).powi(2)).sum::<f64>() / channel.len().max(1) as f64;
+    mean_square.sqrt() as f32
+}
+
+fn median_absolute_deviation(channel: ArrayView1<f32>) -> f32 {
+    let mut values: Vec<f32> = channel.to_vec();
+    let center = median(&mut values);
+    let mut deviations: Vec<f32> = channel.iter().map(|&x| (x - center).abs()).collect();
+    median(&mut deviations)
+}
+
+fn median(values: &mut [f32]) -> f32 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    let middle = values.len() / 2;
+    *values.select_nth_unstable_by(middle, f32::total_cmp).1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::Array2;
+
+    fn options() -> SpikeOptions {
+        SpikeOptions { threshold: Threshold::Fixed(-50.0), pre_trigger_ms: 1.0, post_trigger_ms: 2.0, dead_time_ms: 1.0 }
+    }
+
+    // 1 kHz, so that each ms is one sample.
+    const SAMPLE_RATE: f32 = 1000.0;
+
+    #[test]
+    fn threshold_sign_sets_spike_direction() {
+        let mut data = Array2::zeros((1, 40));
+        data[[0, 10]] = -80.0;
+        data[[0, 20]] = 80.0;
+        data[[0, 21]] = 90.0;
+
+        let negative = detect_threshold_crossings(data.view(), &[Some(-50.0)], SAMPLE_RATE, &options()).unwrap();
+        assert_eq!(negative[0].samples, vec![10]);
+        assert_eq!(negative[0].amplitudes, vec![-80.0]);
+
+        let positive = detect_threshold_crossings(data.view(), &[Some(50.0)], SAMPLE_RATE, &options()).unwrap();
+        assert_eq!(positive[0].samples, vec![20]);
+        assert_eq!(positive[0].amplitudes, vec![90.0]);
+        assert_eq!(positive[0].snippets.row(0).to_vec(), vec![0.0, 80.0, 90.0]);
+    }
+
+    #[test]
+    fn dead_time_suppresses_retriggering() {
+        // Crossings at samples 10, 12 and 16, with a dead time of 5 samples.
+        let mut data = Array2::zeros((1, 30));
+        for sample in [10, 12, 16] {
+            data[[0, sample]] = -80.0;
+        }
+        let options = SpikeOptions { dead_time_ms: 5.0, ..options() };
+        let spikes = detect_threshold_crossings(data.view(), &[Some(-50.0)], SAMPLE_RATE, &options).unwrap();
+        assert_eq!(spikes[0].samples, vec![10, 16]);
+    }
+
+    #[test]
+    fn snippets_past_the_data_are_nan() {
+        let mut data = Array2::zeros((1, 10));
+        data[[0, 9]] = -80.0;
+        let spikes = detect_threshold_crossings(data.view(), &[Some(-50.0)], SAMPLE_RATE, &options()).unwrap();
+        let snippet = spikes[0].snippets.row(0).to_vec();
+        assert_eq!(snippet[..2], [0.0, -80.0]);
+        assert!(snippet[2].is_nan());
+    }
+
+    #[test]
+    fn channels_without_noise_are_skipped() {
+        let mut data = Array2::from_elem((2, 100), 3.0);
+        for (i, x) in data.row_mut(1).iter_mut().enumerate() {
+            *x = if i % 2 == 0 { 10.0 } else { -10.0 };
+        }
+        for threshold in [Threshold::Rms(-5.0), Threshold::Mad(-5.0)] {
+            let thresholds = channel_thresholds(data.view(), &[], threshold).unwrap();
+            assert_eq!(thresholds[0], None);
+            assert!(thresholds[1].is_some_and(|threshold| threshold < 0.0));
+        }
+
+        let spikes = detect_threshold_crossings(data.view(), &[None, Some(-60.0)], SAMPLE_RATE, &options()).unwrap();
+        assert_eq!(spikes[0].threshold, None);
+        assert!(spikes[0].samples.is_empty());
+    }
+
+    #[test]
+    fn noise_thresholds_scale_noise_level() {
+        // The median is 0 and the median deviation from it 1.
+        let data = Array2::from_shape_vec((1, 3), vec![-1.0, 0.0, 1.0]).unwrap();
+        let thresholds = channel_thresholds(data.view(), &[], Threshold::Mad(-5.0)).unwrap();
+        assert!((thresholds[0].unwrap() - -5.0 / MAD_PER_SIGMA).abs() < 1e-5);
+
+        let data = Array2::from_shape_vec((1, 4), vec![-1.0, 1.0, -1.0, 1.0]).unwrap();
+        let thresholds = channel_thresholds(data.view(), &[], Threshold::Rms(4.0)).unwrap();
+        assert!((thresholds[0].unwrap() - 4.0).abs() < 1e-5);
+    }
+}
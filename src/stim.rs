@@ -0,0 +1,532 @@
+// External crates
+use ndarray::{s, Array1, Array2, ArrayView1, ArrayView2, ArrayViewMut1, Zip};
+use rayon::prelude::*;
+
+// Local modules
+use crate::header::RhsHeader;
+use crate::import_hash::{Arrays, RhsError};
+
+/// Which phase of a pulse comes first. Negative (cathodic) current flows out
+/// of the electrode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolarityOrder {
+    CathodicFirst,
+    AnodicFirst,
+}
+
+impl PolarityOrder {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PolarityOrder::CathodicFirst => "cathodic_first",
+            PolarityOrder::AnodicFirst => "anodic_first",
+        }
+    }
+}
+
+/// One phase of a stimulation pulse: a run of samples at the same current.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StimPhase {
+    /// Current in microAmps, negative for cathodic phases.
+    pub amplitude: f32,
+    pub first_sample: u64,
+    pub num_samples: u64,
+}
+
+/// One stimulation pulse on one channel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StimPulse {
+    /// Native name of the amplifier channel, e.g. `A-000`.
+    pub channel: String,
+    /// Index of the pulse's train among the trains on its channel, and of the
+    /// pulse within the train, both counting from 0.
+    pub train: usize,
+    pub pulse_in_train: usize,
+    /// First sample of the first phase, and the sample after the last phase,
+    /// counted from the first sample of the data.
+    pub onset_sample: u64,
+    pub offset_sample: u64,
+    /// Time of `onset_sample` and `offset_sample` in seconds.
+    pub onset_time: f64,
+    pub offset_time: f64,
+    pub polarity: PolarityOrder,
+    /// Duration of the first phase in seconds.
+    pub pulse_width: f64,
+    /// Time between the end of the first phase and the start of the second
+    /// in seconds, or 0 for monophasic pulses.
+    pub interphase_gap: f64,
+    pub phases: Vec<StimPhase>,
+}
+
+/// How `remove_artifacts` treats the samples around stimulation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ArtifactMethod {
+    /// Leave the data as it is.
+    #[default]
+    Keep,
+    /// Set the samples to zero.
+    Zero,
+    /// Set the samples to NaN.
+    Nan,
+    /// Draw a straight line between the samples on either side.
+    Interpolate,
+    /// Subtract the channel's mean artifact, averaged over all its artifact
+    /// windows aligned at their first sample, leaving any evoked response
+    /// that does not repeat exactly with every pulse.
+    Template,
+}
+
+/// Options controlling how `extract_stim_pulses` groups phases into pulses
+/// and pulses into trains.
+#[derive(Debug, Clone)]
+pub struct StimPulseOptions {
+    /// Longest run of zero current between two phases of the same pulse, in
+    /// ms. A pulse also ends once its phases balance each other's charge.
+    pub max_interphase_gap_ms: f32,
+    /// Longest time between the end of one pulse and the start of the next
+    /// for both to belong to the same train, in ms.
+    pub max_train_gap_ms: f32,
+}
+
+impl Default for StimPulseOptions {
+    fn default() -> StimPulseOptions {
+        StimPulseOptions { max_interphase_gap_ms: 1.0, max_train_gap_ms: 100.0 }
+    }
+}
+
+/// Turn the `stim_data` and timestamps `t` loaded from an .rhs file with
+/// `header` into a table of stimulation pulses, ordered by channel and onset.
+///
+/// Both scaled (microAmps and seconds) and raw (stimulation steps and sample
+/// numbers) data are accepted; raw currents are converted with the header's
+/// `stim_step_size`.
+pub fn extract_stim_pulses(header: &RhsHeader, stim_data: &Arrays, t: &Arrays, options: &StimPulseOptions) -> std::result::Result<Vec<StimPulse>, RhsError> {
+    let Some(stim_parameters) = &header.stim_parameters else {
+        return Err(RhsError::InvalidArgumentError("Only .rhs recordings hold stimulation data".to_string()));
+    };
+
+    // Stimulation currents in microAmps and timestamps in seconds.
+    let current: Array2<f32> = match stim_data {
+        Arrays::ArrayTwoFloat(stim_data) => stim_data.clone(),
+        Arrays::ArrayTwo(stim_data) => stim_data.mapv(|steps| steps as f32 * stim_parameters.stim_step_size / 1.0e-6),
+        _ => return Err(RhsError::InvalidArgumentError("stim_data must hold stimulation steps or microAmps".to_string())),
+    };
+    let times: Array1<f64> = match t {
+        Arrays::ArrayOneFloat(t) => t.clone(),
+        Arrays::ArrayOne(t) => t.mapv(|sample| sample as f64 / header.sample_rate as f64),
+        _ => return Err(RhsError::InvalidArgumentError("t must hold timestamps in seconds or samples".to_string())),
+    };
+
+    let channel_names: Vec<String> = header.amplifier_channels.iter().map(|channel| channel.native_channel_name.clone()).collect();
+    find_stim_pulses(current.view(), times.view(), &channel_names, header.sample_rate, options)
+}
+
+/// Find the stimulation pulses in `current`, one row per channel in
+/// microAmps, given the timestamp of each sample in seconds and the name of
+/// each channel.
+pub fn find_stim_pulses(current: ArrayView2<f32>, times: ArrayView1<f64>, channel_names: &[String], sample_rate: f32, options: &StimPulseOptions) -> std::result::Result<Vec<StimPulse>, RhsError> {
+    if current.nrows() != channel_names.len() || current.ncols() != times.len() {
+        return Err(RhsError::InvalidArgumentError(format!(
+            "Stimulation data of {} channels by {} samples does not match {} channels and {} timestamps",
+            current.nrows(), current.ncols(), channel_names.len(), times.len(),
+        )));
+    }
+    let to_samples = |name: &str, ms: f32| {
+        if !ms.is_finite() || ms < 0.0 {
+            return Err(RhsError::InvalidArgumentError(format!("{} must be zero or more milliseconds, not {}", name, ms)));
+        }
+        Ok((ms * sample_rate / 1000.0).round() as u64)
+    };
+    let max_interphase_gap = to_samples("Maximum interphase gap", options.max_interphase_gap_ms)?;
+    let max_train_gap = to_samples("Maximum train gap", options.max_train_gap_ms)?;
+    let sample_period = 1.0 / sample_rate as f64;
+
+    let mut pulses = Vec::new();
+    for (channel, channel_name) in current.outer_iter().zip(channel_names) {
+        let mut train = 0;
+        let mut pulse_in_train = 0;
+        let mut last_offset: Option<u64> = None;
+        for phases in group_phases(find_phases(channel), max_interphase_gap) {
+            let first = &phases[0];
+            let last = &phases[phases.len() - 1];
+            let onset_sample = first.first_sample;
+            let offset_sample = last.first_sample + last.num_samples;
+
+            if let Some(last_offset) = last_offset {
+                if onset_sample - last_offset > max_train_gap {
+                    train += 1;
+                    pulse_in_train = 0;
+                } else {
+                    pulse_in_train += 1;
+                }
+            }
+            last_offset = Some(offset_sample);
+
+            let interphase_gap = match phases.get(1) {
+                Some(second) => (second.first_sample - (first.first_sample + first.num_samples)) as f64 * sample_period,
+                None => 0.0,
+            };
+            pulses.push(StimPulse {
+                channel: channel_name.clone(),
+                train,
+                pulse_in_train,
+                onset_sample,
+                offset_sample,
+                onset_time: times[onset_sample as usize],
+                offset_time: times[offset_sample as usize - 1] + sample_period,
+                polarity: if first.amplitude < 0.0 { PolarityOrder::CathodicFirst } else { PolarityOrder::AnodicFirst },
+                pulse_width: first.num_samples as f64 * sample_period,
+                interphase_gap,
+                phases,
+            });
+        }
+    }
+
+    Ok(pulses)
+}
+
+// Split a channel into runs of samples at the same non-zero current.
+fn find_phases(channel: ArrayView1<f32>) -> Vec<StimPhase> {
+    let mut phases: Vec<StimPhase> = Vec::new();
+    for (i, &amplitude) in channel.iter().enumerate() {
+        if amplitude == 0.0 {
+            continue;
+        }
+        match phases.last_mut() {
+            Some(phase) if phase.amplitude == amplitude && phase.first_sample + phase.num_samples == i as u64 => phase.num_samples += 1,
+            _ => phases.push(StimPhase { amplitude, first_sample: i as u64, num_samples: 1 }),
+        }
+    }
+    phases
+}
+
+// Group consecutive phases into pulses. A pulse ends at a gap longer than
+// `max_interphase_gap` samples, or once its phases balance each other's
+// charge, so that the pulses of a fast train are not merged.
+fn group_phases(phases: Vec<StimPhase>, max_interphase_gap: u64) -> Vec<Vec<StimPhase>> {
+    let mut pulses: Vec<Vec<StimPhase>> = Vec::new();
+    for phase in phases {
+        match pulses.last_mut() {
+            Some(pulse) if !charge_balanced(pulse) && phase.first_sample - pulse_end(pulse) <= max_interphase_gap => pulse.push(phase),
+            _ => pulses.push(vec![phase]),
+        }
+    }
+    pulses
+}
+
+fn pulse_end(phases: &[StimPhase]) -> u64 {
+    phases.last().map_or(0, |phase| phase.first_sample + phase.num_samples)
+}
+
+// Whether the net charge of the phases is within 1% of the charge they move.
+fn charge_balanced(phases: &[StimPhase]) -> bool {
+    let charge = |phase: &StimPhase| phase.amplitude as f64 * phase.num_samples as f64;
+    let net_charge: f64 = phases.iter().map(charge).sum();
+    let total_charge: f64 = phases.iter().map(|phase| charge(phase).abs()).sum();
+    phases.len() > 1 && net_charge.abs() <= 0.01 * total_charge
+}
+
+/// Mark the samples of each channel to treat as stimulation artifact: those
+/// set in any of `flags`, such as `amp_settle_data`, `charge_recovery_data`
+/// and non-zero `stim_data`, widened by `pre_samples` before and
+/// `post_samples` after each flagged stretch. With `all_channels`, a sample
+/// flagged on any channel is marked on every channel.
+pub fn artifact_mask(flags: &[ArrayView2<bool>], pre_samples: usize, post_samples: usize, all_channels: bool) -> std::result::Result<Array2<bool>, RhsError> {
+    let Some(shape) = flags.first().map(|flag| flag.dim()) else {
+        return Err(RhsError::InvalidArgumentError("No stimulation flags to mark artifacts from".to_string()));
+    };
+    if flags.iter().any(|flag| flag.dim() != shape) {
+        return Err(RhsError::InvalidArgumentError("Stimulation flags must all have the same number of channels and samples".to_string()));
+    }
+
+    let mut flagged = Array2::from_elem(shape, false);
+    for flag in flags {
+        Zip::from(&mut flagged).and(flag).for_each(|flagged, &flag| *flagged |= flag);
+    }
+    if all_channels {
+        let any_channel: Vec<bool> = flagged.columns().into_iter().map(|column| column.iter().any(|&flag| flag)).collect();
+        for mut channel in flagged.outer_iter_mut() {
+            channel.assign(&ArrayView1::from(&any_channel));
+        }
+    }
+
+    let num_samples = shape.1;
+    let mut mask = Array2::from_elem(shape, false);
+    for (flags, mut channel) in flagged.outer_iter().zip(mask.outer_iter_mut()) {
+        for (start, end) in runs(flags) {
+            let start = start.saturating_sub(pre_samples);
+            let end = (end + post_samples).min(num_samples);
+            channel.slice_mut(s![start..end]).fill(true);
+        }
+    }
+    Ok(mask)
+}
+
+/// Treat the samples marked in `mask` on each channel (row) of `data` by
+/// `method`. Channels are processed in parallel.
+pub fn remove_artifacts(data: &mut Array2<f32>, mask: ArrayView2<bool>, method: ArtifactMethod) -> std::result::Result<(), RhsError> {
+    if data.dim() != mask.dim() {
+        return Err(RhsError::InvalidArgumentError(format!("Artifact mask of shape {:?} does not match data of shape {:?}", mask.dim(), data.dim())));
+    }
+    let rows: Vec<(ArrayViewMut1<f32>, ArrayView1<bool>)> = data.outer_iter_mut().zip(mask.outer_iter()).collect();
+    rows.into_par_iter().for_each(|(channel, mask)| remove_channel_artifacts(channel, mask, method));
+    Ok(())
+}
+
+fn remove_channel_artifacts(mut channel: ArrayViewMut1<f32>, mask: ArrayView1<bool>, method: ArtifactMethod) {
+    let windows = runs(mask);
+    match method {
+        ArtifactMethod::Keep => {},
+        ArtifactMethod::Zero | ArtifactMethod::Nan => {
+            let value = if method == ArtifactMethod::Zero { 0.0 } else { f32::NAN };
+            for (start, end) in windows {
+                channel.slice_mut(s![start..end]).fill(value);
+            }
+        },
+        ArtifactMethod::Interpolate => {
+            let num_samples = channel.len();
+            for (start, end) in windows {
+                // Hold the sample on one side at either end of the data.
+                let before = (start > 0).then(|| channel[start - 1]);
+                let after = (end < num_samples).then(|| channel[end]);
+                let (first, last) = match (before, after) {
+                    (Some(before), Some(after)) => (before, after),
+                    (Some(value), None) | (None, Some(value)) => (value, value),
+                    (None, None) => (0.0, 0.0),
+                };
+                let steps = (end - start + 1) as f32;
+                for i in start..end {
+                    channel[i] = first + (last - first) * (i - start + 1) as f32 / steps;
+                }
+            }
+        },
+        ArtifactMethod::Template => {
+            let length = windows.iter().map(|(start, end)| end - start).max().unwrap_or(0);
+            let mut sum = vec![0.0f64; length];
+            let mut count = vec![0usize; length];
+            for &(start, end) in &windows {
+                for i in start..end {
+                    sum[i - start] += channel[i] as f64;
+                    count[i - start] += 1;
+                }
+            }
+            for &(start, end) in &windows {
+                for i in start..end {
+                    channel[i] -= (sum[i - start] / count[i - start] as f64) as f32;
+                }
+            }
+        },
+    }
+}
+
+// Start and end (exclusive) of each run of set samples.
+fn runs(flags: ArrayView1<bool>) -> Vec<(usize, usize)> {
+    let mut runs = Vec::new();
+    let mut start = None;
+    for (i, &flag) in flags.iter().enumerate() {
+        match (flag, start) {
+            (true, None) => start = Some(i),
+            (false, Some(run_start)) => {
+                runs.push((run_start, i));
+                start = None;
+            },
+            _ => {},
+        }
+    }
+    if let Some(run_start) = start {
+        runs.push((run_start, flags.len()));
+    }
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::header::{Channel, SignalType, StimParameters};
+    use crate::import_hash::parse_data;
+
+    const NEGATIVE: u16 = 0x0100;
+    const AMP_SETTLE: u16 = 0x2000;
+    const CHARGE_RECOVERY: u16 = 0x4000;
+
+    // A 10 kHz recording with 2 uA stimulation steps.
+    fn header(num_channels: usize) -> RhsHeader {
+        let amplifier_channels = (0..num_channels).map(|i| Channel {
+            signal_type: SignalType::Amplifier,
+            port_name: "Port A".to_string(),
+            port_prefix: "A".to_string(),
+            port_number: 1,
+            native_channel_name: format!("A-{:03}", i),
+            custom_channel_name: format!("A-{:03}", i),
+            native_order: i as i32,
+            custom_order: i as i32,
+            chip_channel: i as i32,
+            board_stream: 0,
+            electrode_impedance_magnitude: 0.0,
+            electrode_impedance_phase: 0.0,
+        }).collect();
+        RhsHeader {
+            sample_rate: 10000.0,
+            stim_parameters: Some(StimParameters { stim_step_size: 2.0e-6, ..Default::default() }),
+            amplifier_channels,
+            ..Default::default()
+        }
+    }
+
+    // Decode stimulation words, one row per channel, as `load_file` does in
+    // raw mode.
+    fn decode(header: &RhsHeader, words: Array2<u16>) -> HashMap<String, Arrays> {
+        let num_samples = words.ncols();
+        let mut data = HashMap::new();
+        data.insert("stim_data_raw".to_string(), Arrays::ArrayTwoU16(words));
+        data.insert("t".to_string(), Arrays::ArrayOne(Array1::from_iter(0..num_samples as i32)));
+        parse_data(header, &mut data, true);
+        data
+    }
+
+    fn pulses(words: Array2<u16>) -> Vec<StimPulse> {
+        let header = header(words.nrows());
+        let data = decode(&header, words);
+        extract_stim_pulses(&header, &data["stim_data"], &data["t"], &StimPulseOptions::default()).unwrap()
+    }
+
+    fn set(words: &mut Array2<u16>, channel: usize, samples: std::ops::Range<usize>, word: u16) {
+        words.slice_mut(s![channel, samples]).fill(word);
+    }
+
+    #[test]
+    fn biphasic_pulses_with_interphase_gap() {
+        // Channel 0 is cathodic first and channel 1 anodic first: 4 samples
+        // at 5 steps, 2 samples off with the amplifiers settling, 4 samples at
+        // 5 steps of the other polarity and then charge recovery.
+        let mut words = Array2::zeros((2, 40));
+        for (channel, first, second) in [(0, NEGATIVE, 0), (1, 0, NEGATIVE)] {
+            set(&mut words, channel, 10..14, 5 | first | AMP_SETTLE);
+            set(&mut words, channel, 14..16, AMP_SETTLE);
+            set(&mut words, channel, 16..20, 5 | second | AMP_SETTLE);
+            set(&mut words, channel, 20..25, CHARGE_RECOVERY);
+        }
+
+        let pulses = pulses(words);
+        assert_eq!(pulses.len(), 2);
+        for (pulse, channel, polarity, amplitudes) in [
+            (&pulses[0], "A-000", PolarityOrder::CathodicFirst, [-10.0, 10.0]),
+            (&pulses[1], "A-001", PolarityOrder::AnodicFirst, [10.0, -10.0]),
+        ] {
+            assert_eq!(pulse.channel, channel);
+            assert_eq!(pulse.polarity, polarity);
+            assert_eq!((pulse.onset_sample, pulse.offset_sample), (10, 20));
+            assert!((pulse.onset_time - 0.001).abs() < 1e-12);
+            assert!((pulse.offset_time - 0.002).abs() < 1e-12);
+            assert!((pulse.pulse_width - 0.0004).abs() < 1e-12);
+            assert!((pulse.interphase_gap - 0.0002).abs() < 1e-12);
+            assert_eq!(pulse.phases.iter().map(|phase| phase.amplitude).collect::<Vec<_>>(), amplitudes);
+            assert_eq!(pulse.phases.iter().map(|phase| (phase.first_sample, phase.num_samples)).collect::<Vec<_>>(), [(10, 4), (16, 4)]);
+        }
+    }
+
+    #[test]
+    fn contiguous_pulses_split_at_charge_balance() {
+        // Two biphasic pulses back to back with no gap at all.
+        let mut words = Array2::zeros((1, 20));
+        set(&mut words, 0, 2..4, 3 | NEGATIVE);
+        set(&mut words, 0, 4..6, 3);
+        set(&mut words, 0, 6..8, 3 | NEGATIVE);
+        set(&mut words, 0, 8..10, 3);
+
+        let pulses = pulses(words);
+        assert_eq!(pulses.iter().map(|pulse| (pulse.onset_sample, pulse.offset_sample)).collect::<Vec<_>>(), [(2, 6), (6, 10)]);
+        assert!(pulses.iter().all(|pulse| pulse.interphase_gap == 0.0 && pulse.phases.len() == 2));
+    }
+
+    #[test]
+    fn long_gap_splits_monophasic_pulses() {
+        // 20 samples (2 ms) between phases is longer than the 1 ms default.
+        let mut words = Array2::zeros((1, 50));
+        set(&mut words, 0, 5..9, 4 | NEGATIVE);
+        set(&mut words, 0, 29..33, 4);
+
+        let pulses = pulses(words);
+        assert_eq!(pulses.len(), 2);
+        assert_eq!(pulses[0].phases.len(), 1);
+        assert_eq!(pulses[0].interphase_gap, 0.0);
+        assert_eq!(pulses[0].polarity, PolarityOrder::CathodicFirst);
+        assert_eq!(pulses[1].polarity, PolarityOrder::AnodicFirst);
+        assert_eq!((pulses[1].train, pulses[1].pulse_in_train), (0, 1));
+    }
+
+    #[test]
+    fn pulses_grouped_into_trains() {
+        // Three pulses 10 ms apart, then two more after a 500 ms pause.
+        let mut words = Array2::zeros((1, 7000));
+        for onset in [0, 100, 200, 5200, 5300] {
+            set(&mut words, 0, onset..onset + 2, 1 | NEGATIVE);
+            set(&mut words, 0, onset + 2..onset + 4, 1);
+        }
+
+        let pulses = pulses(words);
+        assert_eq!(
+            pulses.iter().map(|pulse| (pulse.onset_sample, pulse.train, pulse.pulse_in_train)).collect::<Vec<_>>(),
+            [(0, 0, 0), (100, 0, 1), (200, 0, 2), (5200, 1, 0), (5300, 1, 1)],
+        );
+    }
+
+    #[test]
+    fn scaled_and_raw_data_give_the_same_pulses() {
+        let header = header(1);
+        let mut words = Array2::zeros((1, 20));
+        set(&mut words, 0, 2..5, 7 | NEGATIVE);
+        set(&mut words, 0, 6..9, 7);
+        let data = decode(&header, words);
+        let raw = extract_stim_pulses(&header, &data["stim_data"], &data["t"], &StimPulseOptions::default()).unwrap();
+
+        let (Arrays::ArrayTwo(steps), Arrays::ArrayOne(t)) = (&data["stim_data"], &data["t"]) else { unreachable!() };
+        let current = Arrays::ArrayTwoFloat(steps.mapv(|steps| steps as f32 * 2.0));
+        let seconds = Arrays::ArrayOneFloat(t.mapv(|sample| sample as f64 / 10000.0));
+        let scaled = extract_stim_pulses(&header, &current, &seconds, &StimPulseOptions::default()).unwrap();
+        assert_eq!(raw, scaled);
+        assert_eq!(raw[0].phases[0].amplitude, -14.0);
+    }
+
+    #[test]
+    fn artifact_mask_covers_flags_and_windows() {
+        let header = header(2);
+        let mut words = Array2::zeros((2, 30));
+        set(&mut words, 0, 10..12, 2 | NEGATIVE | AMP_SETTLE);
+        set(&mut words, 0, 12..14, AMP_SETTLE);
+        set(&mut words, 0, 14..16, CHARGE_RECOVERY);
+        let data = decode(&header, words);
+        let (Arrays::ArrayTwoBool(amp_settle), Arrays::ArrayTwoBool(charge_recovery), Arrays::ArrayTwo(stim)) =
+            (&data["amp_settle_data"], &data["charge_recovery_data"], &data["stim_data"]) else { unreachable!() };
+        let stimulating = stim.mapv(|steps| steps != 0);
+        let flags = [amp_settle.view(), charge_recovery.view(), stimulating.view()];
+
+        let mask = artifact_mask(&flags, 2, 3, false).unwrap();
+        let marked = |channel: usize| mask.row(channel).iter().enumerate().filter(|(_, &marked)| marked).map(|(i, _)| i).collect::<Vec<_>>();
+        assert_eq!(marked(0), (8..19).collect::<Vec<_>>());
+        assert!(marked(1).is_empty());
+
+        let mask = artifact_mask(&flags, 0, 0, true).unwrap();
+        assert_eq!(mask.row(1), mask.row(0));
+        assert_eq!(mask.row(1).iter().filter(|&&marked| marked).count(), 6);
+    }
+
+    #[test]
+    fn artifact_removal_methods() {
+        let mask = Array2::from_shape_vec((1, 6), vec![false, true, true, false, true, true]).unwrap();
+        let channel = Array2::from_shape_vec((1, 6), vec![1.0, 9.0, 9.0, 4.0, 8.0, 10.0]).unwrap();
+
+        let mut data = channel.clone();
+        remove_artifacts(&mut data, mask.view(), ArtifactMethod::Interpolate).unwrap();
+        assert_eq!(data.row(0).to_vec(), [1.0, 2.0, 3.0, 4.0, 4.0, 4.0]);
+
+        let mut data = channel.clone();
+        remove_artifacts(&mut data, mask.view(), ArtifactMethod::Template).unwrap();
+        assert_eq!(data.row(0).to_vec(), [1.0, 0.5, -0.5, 4.0, -0.5, 0.5]);
+
+        let mut data = channel.clone();
+        remove_artifacts(&mut data, mask.view(), ArtifactMethod::Nan).unwrap();
+        assert_eq!(data.iter().filter(|x| x.is_nan()).count(), 4);
+    }
+}
@@ -0,0 +1,219 @@
+// Small synthetic recordings written for the tests.
+
+// Standard library imports
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Local modules
+use crate::import_dat::SaveFormat;
+
+const SAMPLES_PER_BLOCK: usize = 128;
+
+/// Enabled channels of each RHD signal type written by `write_rhd`.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct RhdChannels {
+    pub(crate) amplifier: usize,
+    pub(crate) aux_input: usize,
+    pub(crate) supply_voltage: usize,
+    pub(crate) temp_sensor: usize,
+}
+
+/// A fresh directory under the system temporary directory, unique to this
+/// process and `name`.
+pub(crate) fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("intan_import_py_{}_{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn push_qstring(bytes: &mut Vec<u8>, s: &str) {
+    let utf16: Vec<u8> = s.encode_utf16().flat_map(|c| c.to_le_bytes()).collect();
+    bytes.extend((utf16.len() as u32).to_le_bytes());
+    bytes.extend(utf16);
+}
+
+fn push_i16s(bytes: &mut Vec<u8>, values: &[i16]) {
+    bytes.extend(values.iter().flat_map(|v| v.to_le_bytes()));
+}
+
+fn push_f32s(bytes: &mut Vec<u8>, values: &[f32]) {
+    bytes.extend(values.iter().flat_map(|v| v.to_le_bytes()));
+}
+
+// The header of an .rhs file sampled at 30 kHz, with amplifier channels
+// A-000 onwards on port A and digital inputs DIGITAL-IN-01 onwards.
+fn rhs_header(num_amplifier_channels: usize, num_dig_in_channels: usize) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytes.extend(0xd69127acu32.to_le_bytes());
+    push_i16s(&mut bytes, &[3, 0]);
+    push_f32s(&mut bytes, &[30000.0]);
+    push_i16s(&mut bytes, &[1]);
+    push_f32s(&mut bytes, &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0]);
+    push_i16s(&mut bytes, &[0]);
+    push_f32s(&mut bytes, &[1000.0, 1000.0]);
+    push_i16s(&mut bytes, &[1, 0]);
+    push_f32s(&mut bytes, &[10.0, 1.0, 0.0]);
+    for note in ["", "", ""] {
+        push_qstring(&mut bytes, note);
+    }
+    push_i16s(&mut bytes, &[0, 0]);
+    push_qstring(&mut bytes, "hw");
+
+    // One signal group holding the amplifier channels, and one holding the
+    // digital inputs.
+    push_i16s(&mut bytes, &[2]);
+    push_qstring(&mut bytes, "Port A");
+    push_qstring(&mut bytes, "A");
+    push_i16s(&mut bytes, &[1, num_amplifier_channels as i16, num_amplifier_channels as i16]);
+    for channel in 0..num_amplifier_channels as i16 {
+        let name = format!("A-{:03}", channel);
+        push_qstring(&mut bytes, &name);
+        push_qstring(&mut bytes, &name);
+        push_i16s(&mut bytes, &[channel, channel, 0, 1, channel, 0, 0]);
+        push_i16s(&mut bytes, &[1, -50, 0, 0]);
+        push_f32s(&mut bytes, &[1.0e5, -45.0]);
+    }
+
+    push_qstring(&mut bytes, "Digital In");
+    push_qstring(&mut bytes, "DIGITAL-IN");
+    push_i16s(&mut bytes, &[1, num_dig_in_channels as i16, 0]);
+    for channel in 0..num_dig_in_channels as i16 {
+        let name = format!("DIGITAL-IN-{:02}", channel + 1);
+        push_qstring(&mut bytes, &name);
+        push_qstring(&mut bytes, &name);
+        push_i16s(&mut bytes, &[channel, channel, 5, 1, channel, 0, 0]);
+        push_i16s(&mut bytes, &[0, 0, 0, 0]);
+        push_f32s(&mut bytes, &[0.0, 0.0]);
+    }
+    bytes
+}
+
+/// Write a traditional .rhs file sampled at 30 kHz, with
+/// `num_amplifier_channels` amplifier channels, no DC amplifier data and no
+/// board channels. `timestamps` holds one timestamp per sample and must fill
+/// whole data blocks of 128 samples, and `amplifier(channel, sample)` gives
+/// each amplifier sample in steps above zero. There is no stimulation.
+pub(crate) fn write_rhs(path: &PathBuf, num_amplifier_channels: usize, timestamps: &[i32], amplifier: impl Fn(usize, usize) -> i16) {
+    assert_eq!(timestamps.len() % SAMPLES_PER_BLOCK, 0);
+
+    let mut bytes = rhs_header(num_amplifier_channels, 0);
+    for (block, block_timestamps) in timestamps.chunks(SAMPLES_PER_BLOCK).enumerate() {
+        bytes.extend(block_timestamps.iter().flat_map(|t| t.to_le_bytes()));
+        for channel in 0..num_amplifier_channels {
+            let samples = block * SAMPLES_PER_BLOCK..(block + 1) * SAMPLES_PER_BLOCK;
+            bytes.extend(samples.map(|sample| (amplifier(channel, sample) as i32 + 32768) as u16).flat_map(u16::to_le_bytes));
+        }
+        for _ in 0..num_amplifier_channels {
+            bytes.extend((0..SAMPLES_PER_BLOCK).flat_map(|_| 0u16.to_le_bytes()));
+        }
+    }
+    fs::write(path, bytes).unwrap();
+}
+
+/// Write a traditional .rhd file of header version `major.minor`, sampled at
+/// 20 kHz with no board channels. Version 1.x files hold 60 samples per data
+/// block and later ones 128. `timestamps` holds the 32 bits of each timestamp
+/// and must fill whole data blocks, and `sample(data_key, channel, sample)`
+/// gives each stored sample of each signal, counting samples at that
+/// signal's own rate: auxiliary inputs are sampled at 1/4 of the amplifier
+/// rate, supply voltage and temperature sensors once per block.
+pub(crate) fn write_rhd(path: &PathBuf, (major, minor): (i16, i16), channels: RhdChannels, timestamps: &[u32], sample: impl Fn(&str, usize, usize) -> u16) {
+    let samples_per_block = if major == 1 { 60 } else { SAMPLES_PER_BLOCK };
+    assert_eq!(timestamps.len() % samples_per_block, 0);
+
+    let mut bytes = Vec::new();
+    bytes.extend(0xc6912702u32.to_le_bytes());
+    push_i16s(&mut bytes, &[major, minor]);
+    push_f32s(&mut bytes, &[20000.0]);
+    push_i16s(&mut bytes, &[1]);
+    push_f32s(&mut bytes, &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+    push_i16s(&mut bytes, &[2]);
+    push_f32s(&mut bytes, &[1000.0, 1000.0]);
+    for note in ["", "", ""] {
+        push_qstring(&mut bytes, note);
+    }
+    if (major, minor) >= (1, 1) {
+        push_i16s(&mut bytes, &[channels.temp_sensor as i16]);
+    }
+    if (major, minor) >= (1, 3) {
+        push_i16s(&mut bytes, &[0]);
+    }
+    if major > 1 {
+        push_qstring(&mut bytes, "hw");
+    }
+
+    // One signal group holding the amplifier, auxiliary input and supply
+    // voltage channels of a headstage.
+    let group: Vec<(i16, String)> = (0..channels.amplifier).map(|i| (0, format!("A-{:03}", i)))
+        .chain((0..channels.aux_input).map(|i| (1, format!("A-AUX{}", i + 1))))
+        .chain((0..channels.supply_voltage).map(|i| (2, format!("A-VDD{}", i + 1))))
+        .collect();
+    push_i16s(&mut bytes, &[1]);
+    push_qstring(&mut bytes, "Port A");
+    push_qstring(&mut bytes, "A");
+    push_i16s(&mut bytes, &[1, group.len() as i16, channels.amplifier as i16]);
+    for (i, (signal_type, name)) in group.iter().enumerate() {
+        push_qstring(&mut bytes, name);
+        push_qstring(&mut bytes, name);
+        push_i16s(&mut bytes, &[i as i16, i as i16, *signal_type, 1, i as i16, 0]);
+        push_i16s(&mut bytes, &[0, 0, 0, 0]);
+        push_f32s(&mut bytes, &[1.0e5, -45.0]);
+    }
+
+    let signals = [
+        ("amplifier_data", channels.amplifier, samples_per_block),
+        ("aux_input_data", channels.aux_input, samples_per_block / 4),
+        ("supply_voltage_data", channels.supply_voltage, 1),
+        ("temp_sensor_data", channels.temp_sensor, 1),
+    ];
+    for (block, block_timestamps) in timestamps.chunks(samples_per_block).enumerate() {
+        bytes.extend(block_timestamps.iter().flat_map(|t| t.to_le_bytes()));
+        for (data_key, num_channels, num_samples) in signals {
+            for channel in 0..num_channels {
+                let samples = block * num_samples..(block + 1) * num_samples;
+                bytes.extend(samples.flat_map(|i| sample(data_key, channel, i).to_le_bytes()));
+            }
+        }
+    }
+    fs::write(path, bytes).unwrap();
+}
+
+/// Write a One File Per Signal Type or One File Per Channel session into
+/// `dir`: an info.rhs header like `write_rhs`'s with `num_dig_in_channels`
+/// digital inputs, and the .dat files Intan RHX saves next to it.
+/// `amplifier(channel, sample)` gives each amplifier sample in steps above
+/// zero, as saved in the signed .dat files, and `dig_in(channel, sample)`
+/// each digital input state. There is no stimulation.
+pub(crate) fn write_session(dir: &Path, format: SaveFormat, num_amplifier_channels: usize, num_dig_in_channels: usize, num_samples: usize, amplifier: impl Fn(usize, usize) -> i16, dig_in: impl Fn(usize, usize) -> bool) {
+    fs::write(dir.join("info.rhs"), rhs_header(num_amplifier_channels, num_dig_in_channels)).unwrap();
+    fs::write(dir.join("time.dat"), (0..num_samples as i32).flat_map(i32::to_le_bytes).collect::<Vec<u8>>()).unwrap();
+
+    match format {
+        SaveFormat::OneFilePerSignalType => {
+            // Channels are interleaved sample by sample, and the digital
+            // inputs share one word per sample.
+            let samples = || (0..num_samples).flat_map(|sample| (0..num_amplifier_channels).map(move |channel| (channel, sample)));
+            write_u16s(&dir.join("amplifier.dat"), samples().map(|(channel, sample)| amplifier(channel, sample) as u16));
+            write_u16s(&dir.join("stim.dat"), samples().map(|_| 0));
+            if num_dig_in_channels > 0 {
+                let word = |sample| (0..num_dig_in_channels).filter(|&channel| dig_in(channel, sample)).fold(0, |word, channel| word | 1 << channel);
+                write_u16s(&dir.join("digitalin.dat"), (0..num_samples).map(word));
+            }
+        },
+        SaveFormat::OneFilePerChannel => {
+            for channel in 0..num_amplifier_channels {
+                write_u16s(&dir.join(format!("amp-A-{:03}.dat", channel)), (0..num_samples).map(|sample| amplifier(channel, sample) as u16));
+                write_u16s(&dir.join(format!("stim-A-{:03}.dat", channel)), (0..num_samples).map(|_| 0));
+            }
+            for channel in 0..num_dig_in_channels {
+                write_u16s(&dir.join(format!("board-DIGITAL-IN-{:02}.dat", channel + 1)), (0..num_samples).map(|sample| dig_in(channel, sample) as u16));
+            }
+        },
+        SaveFormat::Traditional => panic!("not a split session format"),
+    }
+}
+
+fn write_u16s(path: &Path, samples: impl Iterator<Item = u16>) {
+    fs::write(path, samples.flat_map(u16::to_le_bytes).collect::<Vec<u8>>()).unwrap();
+}